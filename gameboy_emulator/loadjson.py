@@ -227,6 +227,34 @@ def generate_prefixed(name, op1, op2):
     print(f"    {mode:12}({params}),")
 
 
+def cycles_of(instr, prefixed):
+  # Conditional branches are listed as [taken, not taken]; the table stores the
+  # not-taken cost and the instruction adds the difference when it branches.
+  cycles = instr["cycles"][-1]
+  if prefixed:
+    # The CB prefix already accounts for its own 4 cycles.
+    cycles -= 4
+    if instr["mnemonic"] == "BIT" and instr.get("operand2") == "(HL)":
+      cycles = 8
+  return cycles
+
+
+def generate_cycles(instructions):
+  table = []
+  for i in range(256):
+    opcode = f"0x{i:02x}"
+    instr = instructions["unprefixed"].get(opcode)
+    table.append(0 if instr is None else cycles_of(instr, False))
+  for i in range(256):
+    instr = instructions["cbprefixed"][f"0x{i:02x}"]
+    table.append(cycles_of(instr, True))
+
+  print("pub const CYCLES: [u8; 512] = [")
+  for row in range(0, 512, 16):
+    print("    " + " ".join(f"{c:2}," for c in table[row:row + 16]))
+  print("];")
+
+
 def main(argc, argv):
   with open("src/opcodes.json", "r") as file:
     instructions = json.load(file)
@@ -255,6 +283,8 @@ def main(argc, argv):
 
     generate_prefixed(name, op1, op2)
 
+  print()
+  generate_cycles(instructions)
 
   return 0
 
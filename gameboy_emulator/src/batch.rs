@@ -0,0 +1,213 @@
+use crate::cpu::CPU;
+use crate::emulator::Emulator;
+use crate::joypad::Button;
+use crate::png;
+use crate::screenshot::Screenshot;
+use crate::serial::SerialCapture;
+use crate::state;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const HRAM: std::ops::Range<usize> = 0xFF80..0xFFFF;
+/// How long a run waiting for a condition lasts unless told otherwise: ten
+/// minutes of emulated time.
+pub const DEFAULT_FRAME_LIMIT: u64 = 36_000;
+
+/// The keys held during a run, read from a text file with one change per
+/// line: the frame it starts on, then the keys joined with `+`, or `-` for
+/// none. `#` starts a comment. The keys stay held until the next change.
+///
+/// ```text
+/// 120 start
+/// 130 -
+/// 200 a+right
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Inputs {
+    /// Frame and `Button` mask, in frame order.
+    changes: Vec<(u64, u8)>,
+}
+
+impl Inputs {
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let mut changes: Vec<(u64, u8)> = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |message: &str| state::invalid(&format!("line {}: {}", number + 1, message));
+            let (frame, keys) = line.split_once(char::is_whitespace).ok_or_else(|| error("expected a frame and keys"))?;
+            let frame: u64 = frame.parse().map_err(|_| error("invalid frame number"))?;
+            if changes.last().is_some_and(|&(last, _)| frame <= last) {
+                return Err(error("frames must increase"));
+            }
+            let mut mask = 0;
+            if keys.trim() != "-" {
+                for name in keys.trim().split('+') {
+                    let button = Button::from_name(name.trim()).ok_or_else(|| error("unknown key"))?;
+                    mask |= button.mask();
+                }
+            }
+            changes.push((frame, mask));
+        }
+        Ok(Inputs { changes })
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Inputs::parse(&fs::read_to_string(path)?)
+    }
+
+    /// The keys held on `frame`, as a mask of `Button` bits.
+    pub fn held(&self, frame: u64) -> u8 {
+        let index = self.changes.partition_point(|&(start, _)| start <= frame);
+        if index == 0 { 0 } else { self.changes[index - 1].1 }
+    }
+
+    /// Press and release keys so exactly those of `frame` are held.
+    pub fn apply(&self, frame: u64, cpu: &mut CPU) {
+        let held = self.held(frame);
+        for button in Button::ALL {
+            if held & button.mask() != 0 {
+                cpu.press(button);
+            } else {
+                cpu.release(button);
+            }
+        }
+    }
+}
+
+/// When a batch run may stop early, checked after every frame.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Condition {
+    /// The serial output contains this text, like Blargg's "Passed".
+    Serial(String),
+    /// The byte at the address has the value.
+    Memory(u16, u8),
+}
+
+impl Condition {
+    /// Parse `serial:<TEXT>` or `memory:<ADDRESS>=<VALUE>`, the numbers in
+    /// hexadecimal.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid condition '{}'", text);
+        let (kind, argument) = text.split_once(':').ok_or_else(invalid)?;
+        match kind {
+            "serial" if !argument.is_empty() => Ok(Condition::Serial(argument.to_string())),
+            "memory" => {
+                let (address, value) = argument.split_once('=').ok_or_else(invalid)?;
+                let hex = |number: &str| number.trim_start_matches("0x").to_string();
+                let address = u16::from_str_radix(&hex(address), 16).map_err(|_| invalid())?;
+                let value = u8::from_str_radix(&hex(value), 16).map_err(|_| invalid())?;
+                Ok(Condition::Memory(address, value))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    pub fn holds(&self, emulator: &Emulator, serial: &SerialCapture) -> bool {
+        match self {
+            Condition::Serial(text) => serial.text().contains(text.as_str()),
+            Condition::Memory(address, value) => emulator.cpu.get_memory_8bit(*address) == *value,
+        }
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Condition::Serial(text) => write!(f, "serial:{}", text),
+            Condition::Memory(address, value) => write!(f, "memory:{:04X}={:02X}", address, value),
+        }
+    }
+}
+
+/// A run without display, for smoke testing ROMs: the inputs are replayed
+/// until the frame limit or the condition, whichever comes first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Batch {
+    pub frames: u64,
+    pub inputs: Option<Inputs>,
+    pub until: Option<Condition>,
+}
+
+impl Default for Batch {
+    fn default() -> Self {
+        Batch { frames: DEFAULT_FRAME_LIMIT, inputs: None, until: None }
+    }
+}
+
+/// How a batch run ended.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchReport {
+    pub frames: u64,
+    /// Whether the condition held; false without one.
+    pub condition_met: bool,
+    /// CRC-32 of the last frame as 24-bit RGB, to compare runs cheaply.
+    pub frame_hash: u32,
+}
+
+impl Batch {
+    /// Run `emulator`, whose serial port should be connected to `serial` for
+    /// serial conditions, calling `after_frame` after each frame.
+    pub fn run(
+        &self,
+        emulator: &mut Emulator,
+        serial: &SerialCapture,
+        mut after_frame: impl FnMut(&Emulator) -> io::Result<()>,
+    ) -> io::Result<BatchReport> {
+        let mut frames = 0;
+        let mut condition_met = false;
+        while frames < self.frames {
+            if let Some(inputs) = &self.inputs {
+                inputs.apply(frames, &mut emulator.cpu);
+            }
+            emulator.run_frame();
+            frames += 1;
+            after_frame(emulator)?;
+
+            if self.until.as_ref().is_some_and(|condition| condition.holds(emulator, serial)) {
+                condition_met = true;
+                break;
+            }
+        }
+        Ok(BatchReport { frames, condition_met, frame_hash: png::crc32(&emulator.cpu.ppu.frame_rgb()) })
+    }
+}
+
+impl BatchReport {
+    /// Write `screenshot.png`, as `screenshot` says, `serial.bin`, `ram.bin`
+    /// (see `ram_dump`) and `report.txt`, with the frame count, condition and
+    /// frame hash, into `directory`, creating it if needed.
+    pub fn write(
+        &self,
+        directory: &Path,
+        emulator: &Emulator,
+        serial: &SerialCapture,
+        screenshot: &Screenshot,
+    ) -> io::Result<()> {
+        fs::create_dir_all(directory)?;
+        emulator.screenshot_with(&directory.join("screenshot.png"), screenshot)?;
+        fs::write(directory.join("serial.bin"), serial.bytes())?;
+        fs::write(directory.join("ram.bin"), ram_dump(&emulator.cpu))?;
+        fs::write(directory.join("report.txt"), self.to_string())
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "frames: {}", self.frames)?;
+        writeln!(f, "condition met: {}", if self.condition_met { "yes" } else { "no" })?;
+        writeln!(f, "frame hash: {:08X}", self.frame_hash)
+    }
+}
+
+/// All the work RAM banks, then HRAM, then the cartridge RAM, if any.
+pub fn ram_dump(cpu: &CPU) -> Vec<u8> {
+    let mut bytes = cpu.wram.to_vec();
+    bytes.extend_from_slice(&cpu.memory[HRAM]);
+    bytes.extend_from_slice(&cpu.cartridge.ram);
+    bytes
+}
@@ -0,0 +1,397 @@
+use crate::boot_rom;
+use crate::cartridge::Mbc;
+use crate::compatibility;
+use crate::cpu::{Register16bit, CPU};
+use crate::hdma;
+use crate::joypad;
+use crate::model::Model;
+use crate::ppu;
+use crate::state;
+use crate::timer;
+use std::io;
+
+/// The last four bytes of a file with BESS blocks. They are preceded by the
+/// offset of the first block.
+const FOOTER_MAGIC: [u8; 4] = *b"BESS";
+const FOOTER_SIZE: usize = 8;
+const BLOCK_HEADER_SIZE: usize = 8;
+
+const CORE_MAJOR_VERSION: u16 = 1;
+const CORE_MINOR_VERSION: u16 = 1;
+const CORE_SIZE: usize = 0xD0;
+const CORE_IO_OFFSET: usize = 0x18;
+const CORE_BUFFERS_OFFSET: usize = CORE_IO_OFFSET + IO_SIZE;
+const IO_SIZE: usize = 0x80;
+const INFO_SIZE: usize = 0x12;
+const RTC_SIZE: usize = 0x30;
+const MBC_WRITE_SIZE: usize = 3;
+
+const EXECUTION_RUNNING: u8 = 0;
+const EXECUTION_HALTED: u8 = 1;
+const EXECUTION_STOPPED: u8 = 2;
+
+const TITLE: std::ops::Range<usize> = 0x0134..0x0144;
+const GLOBAL_CHECKSUM: std::ops::Range<usize> = 0x014E..0x0150;
+
+const DMG_WRAM_SIZE: usize = 0x2000;
+const DMG_VRAM_SIZE: usize = 0x2000;
+const HRAM: std::ops::Range<usize> = 0xFF80..0xFFFF;
+const IE_ADDRESS: usize = 0xFFFF;
+const SB_ADDRESS: u16 = 0xFF01;
+const SC_ADDRESS: u16 = 0xFF02;
+const DMA_ADDRESS: u16 = 0xFF46;
+const KEY1_ADDRESS: u16 = 0xFF4D;
+const SVBK_ADDRESS: u16 = 0xFF70;
+
+fn model_id(model: Model) -> &'static [u8; 4] {
+    // The third letter is the CPU revision.
+    match model {
+        Model::Dmg0 => b"GD0 ",
+        Model::Dmg => b"GDB ",
+        Model::Mgb => b"GM  ",
+        Model::Sgb => b"SN  ",
+        Model::Sgb2 => b"S2  ",
+        Model::Cgb => b"CCE ",
+        Model::Agb => b"CA  ",
+    }
+}
+
+fn model_from_id(id: &[u8]) -> Option<Model> {
+    match id {
+        [b'G', b'D', b'0', ..] => Some(Model::Dmg0),
+        [b'G', b'D', ..] => Some(Model::Dmg),
+        [b'G', b'M', ..] => Some(Model::Mgb),
+        [b'S', b'N' | b'P', ..] => Some(Model::Sgb),
+        [b'S', b'2', ..] => Some(Model::Sgb2),
+        [b'C', b'C', ..] => Some(Model::Cgb),
+        [b'C', b'A', ..] => Some(Model::Agb),
+        _ => None,
+    }
+}
+
+fn push_block(file: &mut Vec<u8>, id: &[u8; 4], data: &[u8]) {
+    file.extend_from_slice(id);
+    file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    file.extend_from_slice(data);
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().expect("4 bytes"))
+}
+
+/// Save the machine as a Best Effort Save State, the format shared by
+/// SameBoy and other emulators: the memory buffers, followed by NAME, CORE,
+/// INFO, MBC, RTC and END blocks and the footer pointing at them.
+pub fn export(cpu: &CPU) -> Vec<u8> {
+    let cgb = cpu.model.is_cgb();
+    let hram = &cpu.memory[HRAM];
+    let (wram, vram) = if cgb { (&cpu.wram[..], &cpu.ppu.vram[..]) } else { (&cpu.wram[..DMG_WRAM_SIZE], &cpu.ppu.vram[..DMG_VRAM_SIZE]) };
+    let (bg_palettes, obj_palettes): (&[u8], &[u8]) =
+        if cgb { (&cpu.ppu.bg_palettes.data, &cpu.ppu.obj_palettes.data) } else { (&[], &[]) };
+
+    let mut file = Vec::new();
+    let mut buffers = Vec::new();
+    for buffer in [wram, vram, &cpu.cartridge.ram, &cpu.ppu.oam, hram, bg_palettes, obj_palettes] {
+        buffers.extend_from_slice(&(buffer.len() as u32).to_le_bytes());
+        buffers.extend_from_slice(&(file.len() as u32).to_le_bytes());
+        file.extend_from_slice(buffer);
+    }
+
+    let first_block = file.len() as u32;
+    let name = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    push_block(&mut file, b"NAME", name.as_bytes());
+    push_block(&mut file, b"CORE", &core(cpu, &buffers));
+
+    let mut info = cpu.cartridge.rom[TITLE].to_vec();
+    info.extend_from_slice(&cpu.cartridge.rom[GLOBAL_CHECKSUM]);
+    push_block(&mut file, b"INFO", &info);
+
+    let writes = mbc_writes(&cpu.cartridge.mbc);
+    if !writes.is_empty() {
+        let data: Vec<u8> = writes.iter().flat_map(|&(address, value)| address.to_le_bytes().into_iter().chain([value])).collect();
+        push_block(&mut file, b"MBC ", &data);
+    }
+    if let Mbc::Mbc3 { rtc, latched, .. } = cpu.cartridge.mbc {
+        if cpu.cartridge.header.has_rtc() {
+            push_block(&mut file, b"RTC ", &rtc_block(rtc, latched, cpu.cartridge.host_time()));
+        }
+    }
+    push_block(&mut file, b"END ", &[]);
+
+    file.extend_from_slice(&first_block.to_le_bytes());
+    file.extend_from_slice(&FOOTER_MAGIC);
+    file
+}
+
+fn core(cpu: &CPU, buffers: &[u8]) -> Vec<u8> {
+    let mut core = Vec::with_capacity(CORE_SIZE);
+    core.extend_from_slice(&CORE_MAJOR_VERSION.to_le_bytes());
+    core.extend_from_slice(&CORE_MINOR_VERSION.to_le_bytes());
+    core.extend_from_slice(model_id(cpu.model));
+    core.extend_from_slice(&cpu.get_pc().to_le_bytes());
+    for register in [Register16bit::AF, Register16bit::BC, Register16bit::DE, Register16bit::HL] {
+        core.extend_from_slice(&cpu.get_register_16bit(register).to_le_bytes());
+    }
+    core.extend_from_slice(&cpu.get_sp().to_le_bytes());
+    core.push(cpu.ime_flag as u8);
+    core.push(cpu.memory[IE_ADDRESS]);
+    core.push(if cpu.stopped {
+        EXECUTION_STOPPED
+    } else if cpu.halted {
+        EXECUTION_HALTED
+    } else {
+        EXECUTION_RUNNING
+    });
+    core.push(0x00);
+    core.extend_from_slice(&io_registers(cpu));
+    core.extend_from_slice(buffers);
+    core
+}
+
+/// 0xFF00-0xFF7F as read, except for registers that can't be read back,
+/// which hold what was last written.
+fn io_registers(cpu: &CPU) -> [u8; IO_SIZE] {
+    let mut io = [0; IO_SIZE];
+    for (index, value) in io.iter_mut().enumerate() {
+        *value = cpu.get_memory_8bit(0xFF00 + index as u16);
+    }
+    let register = |address: u16| (address - 0xFF00) as usize;
+
+    let destination = 0x8000 | cpu.hdma.destination;
+    io[register(hdma::HDMA1_ADDRESS)] = (cpu.hdma.source >> 8) as u8;
+    io[register(hdma::HDMA2_ADDRESS)] = cpu.hdma.source as u8;
+    io[register(hdma::HDMA3_ADDRESS)] = (destination >> 8) as u8;
+    io[register(hdma::HDMA4_ADDRESS)] = destination as u8;
+    io[register(compatibility::KEY0_ADDRESS)] =
+        if cpu.ppu.dmg_compatibility { compatibility::KEY0_DMG_COMPATIBILITY } else { 0x00 };
+    io[register(boot_rom::BANK_ADDRESS)] = cpu.boot_rom.is_none() as u8;
+    // In DMG compatibility mode the banks read 0xFF but stay selected.
+    if cpu.model.is_cgb() {
+        io[register(ppu::VBK_ADDRESS)] = 0xFE | cpu.ppu.vram_bank;
+        io[register(SVBK_ADDRESS)] = 0xF8 | cpu.wram_bank;
+    }
+    io
+}
+
+/// Writes that put a fresh mapper in the same state.
+fn mbc_writes(mbc: &Mbc) -> Vec<(u16, u8)> {
+    let enable = |enabled: bool| if enabled { 0x0A } else { 0x00 };
+    match *mbc {
+        Mbc::RomOnly => Vec::new(),
+        Mbc::Mbc1 { ram_enabled, rom_bank, upper_bits, advanced_mode } => vec![
+            (0x0000, enable(ram_enabled)),
+            (0x2000, rom_bank),
+            (0x4000, upper_bits),
+            (0x6000, advanced_mode as u8),
+        ],
+        Mbc::Mbc2 { ram_enabled, rom_bank } => vec![(0x0000, enable(ram_enabled)), (0x0100, rom_bank)],
+        Mbc::Mbc3 { ram_enabled, rom_bank, ram_bank, latch, .. } => {
+            vec![(0x0000, enable(ram_enabled)), (0x2000, rom_bank), (0x4000, ram_bank), (0x6000, latch)]
+        }
+        Mbc::Mbc5 { ram_enabled, rom_bank, ram_bank } => vec![
+            (0x0000, enable(ram_enabled)),
+            (0x2000, rom_bank as u8),
+            (0x3000, (rom_bank >> 8) as u8),
+            (0x4000, ram_bank),
+        ],
+    }
+}
+
+/// The clock registers, current then latched, as 32-bit values, and the
+/// host time they were saved at.
+fn rtc_block(rtc: [u8; 5], latched: [u8; 5], now: u64) -> Vec<u8> {
+    let mut block = Vec::with_capacity(RTC_SIZE);
+    for register in rtc.into_iter().chain(latched) {
+        block.extend_from_slice(&(register as u32).to_le_bytes());
+    }
+    block.extend_from_slice(&now.to_le_bytes());
+    block
+}
+
+/// Load a Best Effort Save State written by this or another emulator into
+/// the machine, which must have the same game inserted. Blocks we don't
+/// know are skipped, and whatever BESS doesn't record, like the position of
+/// the PPU within a line, is approximated. On error the machine may be left
+/// partly loaded; `Emulator::import_bess` takes care of restoring it.
+pub fn import(cpu: &mut CPU, file: &[u8]) -> io::Result<()> {
+    let blocks_end = file.len().checked_sub(FOOTER_SIZE).ok_or_else(|| state::invalid("not a BESS save state"))?;
+    if file[blocks_end + 4..] != FOOTER_MAGIC {
+        return Err(state::invalid("not a BESS save state"));
+    }
+
+    let mut offset = read_u32(file, blocks_end) as usize;
+    let (mut core, mut info, mut mbc, mut rtc) = (None, None, None, None);
+    loop {
+        if offset + BLOCK_HEADER_SIZE > blocks_end {
+            return Err(state::invalid("BESS save state is truncated"));
+        }
+        let id = &file[offset..offset + 4];
+        let start = offset + BLOCK_HEADER_SIZE;
+        let end = start + read_u32(file, offset + 4) as usize;
+        if end > blocks_end {
+            return Err(state::invalid("BESS save state is truncated"));
+        }
+        let data = &file[start..end];
+        offset = end;
+
+        match id {
+            b"CORE" => core = Some(data),
+            b"INFO" => info = Some(data),
+            b"MBC " => mbc = Some(data),
+            b"RTC " => rtc = Some(data),
+            b"END " => break,
+            _ => {}
+        }
+    }
+
+    if let Some(info) = info {
+        let rom = &cpu.cartridge.rom;
+        if info.len() != INFO_SIZE || info[..16] != rom[TITLE] || info[16..] != rom[GLOBAL_CHECKSUM] {
+            return Err(state::invalid("BESS save state is for another game"));
+        }
+    }
+    let core = core.ok_or_else(|| state::invalid("BESS save state has no CORE block"))?;
+    import_core(cpu, file, core)?;
+
+    if let Some(mbc) = mbc {
+        if !mbc.len().is_multiple_of(MBC_WRITE_SIZE) {
+            return Err(state::invalid("BESS save state has an invalid MBC block"));
+        }
+        for write in mbc.chunks_exact(MBC_WRITE_SIZE) {
+            let address = read_u16(write, 0);
+            if matches!(address, 0x0000..=0x7FFF | 0xA000..=0xBFFF) {
+                cpu.cartridge.write(address, write[2]);
+            }
+        }
+    }
+    if let (Some(block), Mbc::Mbc3 { rtc, latched, .. }) = (rtc, &mut cpu.cartridge.mbc) {
+        if block.len() != RTC_SIZE {
+            return Err(state::invalid("BESS save state has an invalid RTC block"));
+        }
+        for (index, register) in rtc.iter_mut().chain(latched.iter_mut()).enumerate() {
+            *register = read_u32(block, index * 4) as u8;
+        }
+        let saved_at = u64::from_le_bytes(block[0x28..0x30].try_into().expect("8 bytes"));
+        cpu.cartridge.catch_up_rtc(saved_at);
+    }
+    Ok(())
+}
+
+fn import_core(cpu: &mut CPU, file: &[u8], core: &[u8]) -> io::Result<()> {
+    if core.len() < CORE_SIZE || read_u16(core, 0) != CORE_MAJOR_VERSION {
+        return Err(state::invalid("BESS save state has an unsupported CORE block"));
+    }
+    let model = model_from_id(&core[4..8]).ok_or_else(|| state::invalid("BESS save state is for an unknown model"))?;
+    let buffer = |index: usize| -> io::Result<&[u8]> {
+        let descriptor = CORE_BUFFERS_OFFSET + index * 8;
+        let size = read_u32(core, descriptor) as usize;
+        let offset = read_u32(core, descriptor + 4) as usize;
+        file.get(offset..offset + size).ok_or_else(|| state::invalid("BESS save state is truncated"))
+    };
+    let buffers = [buffer(0)?, buffer(1)?, buffer(2)?, buffer(3)?, buffer(4)?, buffer(5)?, buffer(6)?];
+
+    cpu.model = model;
+    cpu.set_pc(read_u16(core, 0x08));
+    for (index, register) in [Register16bit::AF, Register16bit::BC, Register16bit::DE, Register16bit::HL]
+        .into_iter()
+        .enumerate()
+    {
+        cpu.set_register_16bit(register, read_u16(core, 0x0A + index * 2));
+    }
+    cpu.set_sp(read_u16(core, 0x12));
+    cpu.ime_flag = core[0x14] != 0;
+    cpu.memory[IE_ADDRESS] = core[0x15];
+    (cpu.halted, cpu.stopped) = match core[0x16] {
+        EXECUTION_RUNNING => (false, false),
+        EXECUTION_HALTED => (true, false),
+        EXECUTION_STOPPED => (false, true),
+        _ => return Err(state::invalid("BESS save state has an invalid execution state")),
+    };
+    import_io(cpu, &core[CORE_IO_OFFSET..CORE_IO_OFFSET + IO_SIZE]);
+
+    let [wram, vram, cartridge_ram, oam, hram, bg_palettes, obj_palettes] = buffers;
+    let copy = |destination: &mut [u8], source: &[u8]| {
+        let length = destination.len().min(source.len());
+        destination[..length].copy_from_slice(&source[..length]);
+    };
+    copy(&mut cpu.wram, wram);
+    copy(&mut cpu.ppu.vram, vram);
+    copy(&mut cpu.cartridge.ram, cartridge_ram);
+    copy(&mut cpu.ppu.oam, oam);
+    copy(&mut cpu.memory[HRAM], hram);
+    copy(&mut cpu.ppu.bg_palettes.data, bg_palettes);
+    copy(&mut cpu.ppu.obj_palettes.data, obj_palettes);
+
+    let sgb = model.is_sgb() && cpu.cartridge.header.supports_sgb();
+    if cpu.sgb.is_some() != sgb {
+        cpu.set_sgb_mode(sgb);
+    }
+    Ok(())
+}
+
+/// Set each I/O register to its saved value without the side effects of
+/// writing it, like starting a DMA. CGB registers read 0xFF on other models
+/// and are left at their defaults.
+fn import_io(cpu: &mut CPU, io: &[u8]) {
+    let cgb = cpu.model.is_cgb();
+    let mut key0 = 0x00;
+    let mut ly = 0;
+
+    for (index, &value) in io.iter().enumerate() {
+        let address = 0xFF00 + index as u16;
+        match address {
+            joypad::JOYP_ADDRESS => cpu.joypad.write_register(value),
+            SB_ADDRESS => cpu.serial.set_data(value),
+            SC_ADDRESS => cpu.serial.set_control(value),
+            timer::DIV_ADDRESS => cpu.timer.set_counter((value as u16) << 8),
+            timer::TIMA_ADDRESS => cpu.timer.tima = value,
+            timer::TMA_ADDRESS => cpu.timer.tma = value,
+            timer::TAC_ADDRESS => cpu.timer.tac = value & 0x07,
+            ppu::LCDC_ADDRESS => cpu.ppu.lcdc = value,
+            ppu::LY_ADDRESS => ly = value,
+            ppu::STAT_ADDRESS
+            | ppu::SCY_ADDRESS
+            | ppu::SCX_ADDRESS
+            | ppu::LYC_ADDRESS
+            | ppu::BGP_ADDRESS..=ppu::WX_ADDRESS => {
+                cpu.ppu.write_register(address, value);
+            }
+            DMA_ADDRESS => cpu.memory[address as usize] = value,
+            ppu::VBK_ADDRESS => cpu.ppu.vram_bank = if cgb { value & 0x01 } else { 0 },
+            ppu::BCPS_ADDRESS => cpu.ppu.bg_palettes.set_spec(value),
+            ppu::OCPS_ADDRESS => cpu.ppu.obj_palettes.set_spec(value),
+            // Palette data comes from its own buffers.
+            ppu::BCPD_ADDRESS | ppu::OCPD_ADDRESS => {}
+            compatibility::KEY0_ADDRESS => key0 = value,
+            KEY1_ADDRESS => {
+                cpu.double_speed = cgb && value & 0x80 != 0;
+                cpu.speed_switch_armed = cgb && value & 0x01 != 0;
+            }
+            boot_rom::BANK_ADDRESS => {
+                if value & 0x01 != 0 {
+                    cpu.boot_rom = None;
+                }
+            }
+            hdma::HDMA1_ADDRESS..=hdma::HDMA4_ADDRESS => {
+                cpu.hdma.write_register(address, value);
+            }
+            hdma::HDMA5_ADDRESS => {
+                cpu.hdma.hblank_active = cgb && value & 0x80 == 0;
+                cpu.hdma.blocks = if cpu.hdma.hblank_active { (value & 0x7F) + 1 } else { 0 };
+            }
+            SVBK_ADDRESS => cpu.wram_bank = if cgb { (value & 0x07).max(1) } else { 1 },
+            _ => cpu.memory[address as usize] = value,
+        }
+    }
+
+    if cgb {
+        cpu.set_key0(key0);
+    } else {
+        cpu.set_cgb_mode(false);
+    }
+    cpu.ppu.start_line(ly);
+}
@@ -0,0 +1,244 @@
+use crate::cartridge::Header;
+use crate::compatibility;
+use crate::model::Model;
+
+/// Writing a nonzero value here unmaps the boot ROM for good.
+pub const BANK_ADDRESS: u16 = 0xFF50;
+
+pub const DMG_SIZE: usize = 0x100;
+/// The CGB boot ROM skips 0x0100-0x01FF, where the cartridge header stays
+/// visible, so its file is 0x900 bytes long with a hole in the middle.
+pub const CGB_SIZE: usize = 0x900;
+
+const HEADER_START: u16 = 0x0100;
+const HEADER_END: u16 = 0x01FF;
+
+/// Where the CGB replacement program goes, past the header hole.
+const CGB_PROGRAM_START: u16 = 0x0200;
+/// Every boot ROM ends with the write to 0xFF50 here, so the next
+/// instruction fetched is the cartridge's at 0x0100.
+const HANDOFF_ADDRESS: u16 = 0x00FE;
+const LOGO_ADDRESS: u16 = 0x0104;
+const LOGO_SIZE: u8 = 48;
+/// The logo takes 24 tiles, starting at tile 1, laid out on two rows of the
+/// background map.
+const LOGO_TILES: u8 = 24;
+const LOGO_TILES_PER_ROW: u8 = 12;
+const LOGO_MAP_END: u16 = 0x992F;
+const LOGO_FRAMES: u8 = 32;
+
+const JR_NZ: u8 = 0x20;
+const JR_Z: u8 = 0x28;
+
+const BCPS_ADDRESS: u16 = 0xFF68;
+const BCPD_ADDRESS: u16 = 0xFF69;
+const OCPS_ADDRESS: u16 = 0xFF6A;
+const OCPD_ADDRESS: u16 = 0xFF6B;
+const PALETTE_AUTO_INCREMENT: u8 = 0x80;
+
+/// A boot ROM mapped over the start of the cartridge ROM: 0x0000-0x00FF, and
+/// 0x0200-0x08FF as well for the CGB.
+pub struct BootRom {
+    data: Vec<u8>,
+}
+
+impl BootRom {
+    /// Returns `None` unless `data` has the size of a DMG or CGB boot ROM.
+    pub fn new(data: &[u8]) -> Option<Self> {
+        match data.len() {
+            DMG_SIZE | CGB_SIZE => Some(BootRom { data: data.to_vec() }),
+            _ => None,
+        }
+    }
+
+    /// A boot program of our own for `model` and the cartridge with
+    /// `header`, for when no Nintendo boot ROM is at hand. It clears VRAM,
+    /// shows the cartridge logo for a moment, sets the I/O registers as the
+    /// model's boot ROM does, colorizes games without CGB support on the
+    /// CGB, and hands over at 0x0100 with the model's post-boot registers.
+    ///
+    /// Unlike the real ones it doesn't check the logo or the header checksum,
+    /// doesn't read held keys to pick CGB palettes, and takes its own time,
+    /// so DIV won't match.
+    pub fn replacement(model: Model, header: &Header) -> Self {
+        let (size, origin) = if model.is_cgb() { (CGB_SIZE, CGB_PROGRAM_START) } else { (DMG_SIZE, 0x0000) };
+        let mut program = Program { origin, code: Vec::new() };
+
+        // LD SP, 0xFFFE
+        program.emit(&[0x31, 0xFE, 0xFF]);
+        program.clear_vram();
+        program.unpack_logo();
+        program.map_logo();
+        for (address, value) in model.post_boot_io() {
+            program.write_io(address, value);
+        }
+        program.wait_frames(LOGO_FRAMES);
+        if model.is_cgb() {
+            program.set_cgb_mode(header);
+        }
+        program.load_registers(model.post_boot_registers(header));
+        // JP 0x00FE
+        program.emit(&[0xC3, HANDOFF_ADDRESS as u8, (HANDOFF_ADDRESS >> 8) as u8]);
+
+        // The header only changes operands, so the program's size depends on
+        // the model alone; boot_rom_tests checks that every model's fits
+        // before the handoff.
+        let start = origin as usize;
+        let end = start + program.code.len();
+
+        let mut data = vec![0x00; size];
+        data[start..end].copy_from_slice(&program.code);
+        if model.is_cgb() {
+            // JP 0x0200
+            data[..3].copy_from_slice(&[0xC3, CGB_PROGRAM_START as u8, (CGB_PROGRAM_START >> 8) as u8]);
+        }
+        // LDH (0x50), A
+        let handoff = HANDOFF_ADDRESS as usize;
+        data[handoff..handoff + 2].copy_from_slice(&[0xE0, BANK_ADDRESS as u8]);
+        BootRom { data }
+    }
+
+    /// The whole file, as given to `new`.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.data.len() == CGB_SIZE
+    }
+
+    /// The byte at `address` if the boot ROM covers it, otherwise the
+    /// cartridge shows through.
+    pub fn read(&self, address: u16) -> Option<u8> {
+        if (HEADER_START..=HEADER_END).contains(&address) {
+            return None;
+        }
+        self.data.get(address as usize).copied()
+    }
+}
+
+/// Machine code being put together at `origin`.
+struct Program {
+    origin: u16,
+    code: Vec<u8>,
+}
+
+impl Program {
+    fn here(&self) -> u16 {
+        self.origin + self.code.len() as u16
+    }
+
+    fn emit(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    /// A relative jump back to `target`, `opcode` giving the condition.
+    fn jump_back(&mut self, opcode: u8, target: u16) {
+        let offset = target as i32 - (self.here() as i32 + 2);
+        self.emit(&[opcode, offset as i8 as u8]);
+    }
+
+    /// LD A, value and LDH (address), A.
+    fn write_io(&mut self, address: u16, value: u8) {
+        self.emit(&[0x3E, value, 0xE0, address as u8]);
+    }
+
+    fn clear_vram(&mut self) {
+        // XOR A, LD HL, 0x9FFF
+        self.emit(&[0xAF, 0x21, 0xFF, 0x9F]);
+        let clear = self.here();
+        // LD (HL-), A, BIT 7, H
+        self.emit(&[0x32, 0xCB, 0x7C]);
+        self.jump_back(JR_NZ, clear);
+    }
+
+    /// Copy the logo from the header into tiles 1-24, doubling its size:
+    /// each bit becomes two pixels across and each nibble two rows.
+    fn unpack_logo(&mut self) {
+        // LD DE, 0x0104, LD HL, 0x8010
+        self.emit(&[0x11, LOGO_ADDRESS as u8, (LOGO_ADDRESS >> 8) as u8, 0x21, 0x10, 0x80]);
+        let byte = self.here();
+        // LD A, (DE), LD C, A
+        self.emit(&[0x1A, 0x4F]);
+        for _ in 0..2 {
+            // LD B, 4
+            self.emit(&[0x06, 0x04]);
+            let bit = self.here();
+            // Shift the top bit of C into A twice, C only moving on once:
+            // PUSH BC, RL C, RLA, POP BC, RL C, RLA, DEC B
+            self.emit(&[0xC5, 0xCB, 0x11, 0x17, 0xC1, 0xCB, 0x11, 0x17, 0x05]);
+            self.jump_back(JR_NZ, bit);
+            // LD (HL+), A, INC HL, LD (HL+), A, INC HL
+            self.emit(&[0x22, 0x23, 0x22, 0x23]);
+        }
+        // INC DE, LD A, E, CP 0x34
+        self.emit(&[0x13, 0x7B, 0xFE, LOGO_ADDRESS as u8 + LOGO_SIZE]);
+        self.jump_back(JR_NZ, byte);
+    }
+
+    /// Put tiles 1-12 and 13-24 in the middle of rows 8 and 9 of the
+    /// background map, filling it backwards.
+    fn map_logo(&mut self) {
+        // LD HL, 0x992F, LD A, 24
+        self.emit(&[0x21, LOGO_MAP_END as u8, (LOGO_MAP_END >> 8) as u8, 0x3E, LOGO_TILES]);
+        let row = self.here();
+        // LD C, 12
+        self.emit(&[0x0E, LOGO_TILES_PER_ROW]);
+        let cell = self.here();
+        // LD (HL-), A, DEC A, DEC C
+        self.emit(&[0x32, 0x3D, 0x0D]);
+        self.jump_back(JR_NZ, cell);
+        // LD L, 0x0F, AND A
+        self.emit(&[0x2E, (LOGO_MAP_END - 0x20) as u8, 0xA7]);
+        self.jump_back(JR_NZ, row);
+    }
+
+    /// Count `frames` VBlanks by polling LY.
+    fn wait_frames(&mut self, frames: u8) {
+        // LD B, frames
+        self.emit(&[0x06, frames]);
+        let frame = self.here();
+        // LDH A, (0x44), CP 144
+        self.emit(&[0xF0, 0x44, 0xFE, 0x90]);
+        self.jump_back(JR_NZ, frame);
+        let vblank = self.here();
+        self.emit(&[0xF0, 0x44, 0xFE, 0x90]);
+        self.jump_back(JR_Z, vblank);
+        // DEC B
+        self.emit(&[0x05]);
+        self.jump_back(JR_NZ, frame);
+    }
+
+    /// Write KEY0 from the header, first loading the compatibility palettes
+    /// for a cartridge without CGB support.
+    fn set_cgb_mode(&mut self, header: &Header) {
+        if header.supports_cgb() {
+            self.write_io(compatibility::KEY0_ADDRESS, header.cgb_flag);
+            return;
+        }
+
+        let palettes = compatibility::for_header(header);
+        self.write_io(BCPS_ADDRESS, PALETTE_AUTO_INCREMENT);
+        self.write_colors(BCPD_ADDRESS, &palettes.bg);
+        self.write_io(OCPS_ADDRESS, PALETTE_AUTO_INCREMENT);
+        self.write_colors(OCPD_ADDRESS, &palettes.obj0);
+        self.write_colors(OCPD_ADDRESS, &palettes.obj1);
+        self.write_io(compatibility::KEY0_ADDRESS, compatibility::KEY0_DMG_COMPATIBILITY);
+    }
+
+    fn write_colors(&mut self, address: u16, colors: &[u32; 4]) {
+        for &rgb in colors {
+            for byte in compatibility::rgb_to_color(rgb).to_le_bytes() {
+                self.write_io(address, byte);
+            }
+        }
+    }
+
+    /// Set A, F, B, C, D, E, H and L, going through the stack for F.
+    fn load_registers(&mut self, [a, f, b, c, d, e, h, l]: [u8; 8]) {
+        // LD HL, AF, PUSH HL, POP AF
+        self.emit(&[0x21, f, a, 0xE5, 0xF1]);
+        // LD BC, LD DE, LD HL
+        self.emit(&[0x01, c, b, 0x11, e, d, 0x21, l, h]);
+    }
+}
@@ -0,0 +1,237 @@
+use crate::boot_rom::BootRom;
+use crate::cartridge::{HostClock, RtcMode};
+use crate::debug::DebugHook;
+use crate::emulator::{Emulator, DEFAULT_SAMPLE_RATE};
+use crate::model::Model;
+use crate::ppu::GAMEBOY_DOCTOR_LY;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// Why `EmulatorBuilder::build` failed.
+#[derive(Debug)]
+pub enum EmulatorError {
+    /// Neither ROM bytes nor a ROM path were given.
+    MissingRom,
+    /// The ROM is empty.
+    EmptyRom,
+    /// A boot ROM must be 256 (DMG) or 2304 (CGB) bytes long.
+    InvalidBootRom { size: usize },
+    /// Reading a file failed.
+    Io { path: PathBuf, source: io::Error },
+}
+
+impl fmt::Display for EmulatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmulatorError::MissingRom => write!(f, "no ROM was given"),
+            EmulatorError::EmptyRom => write!(f, "the ROM is empty"),
+            EmulatorError::InvalidBootRom { size } => {
+                write!(f, "a boot ROM is 256 bytes (DMG) or 2304 bytes (CGB) long, not {}", size)
+            }
+            EmulatorError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+        }
+    }
+}
+
+impl Error for EmulatorError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            EmulatorError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+enum RomSource {
+    Bytes(Vec<u8>),
+    Path(PathBuf),
+}
+
+enum Boot {
+    /// Start at 0x0100 as if the boot ROM had run.
+    Skip,
+    /// Run our own boot program.
+    Replacement,
+    /// Run a dump of the real boot ROM.
+    Dump(Vec<u8>),
+    DumpPath(PathBuf),
+}
+
+/// Configures an `Emulator`: what to run, on which model and how. Only the
+/// ROM is required; by default the model is picked from the cartridge
+/// header and the boot ROM is skipped.
+pub struct EmulatorBuilder {
+    model: Option<Model>,
+    rom: Option<RomSource>,
+    boot: Boot,
+    save_directory: Option<PathBuf>,
+    rtc_mode: RtcMode,
+    host_clock: Option<HostClock>,
+    sample_rate: u32,
+    ram_seed: Option<u64>,
+    gameboy_doctor: bool,
+    debug_hooks: Vec<Box<dyn DebugHook>>,
+}
+
+impl Default for EmulatorBuilder {
+    fn default() -> Self {
+        EmulatorBuilder {
+            model: None,
+            rom: None,
+            boot: Boot::Skip,
+            save_directory: None,
+            rtc_mode: RtcMode::default(),
+            host_clock: None,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            ram_seed: None,
+            gameboy_doctor: false,
+            debug_hooks: Vec::new(),
+        }
+    }
+}
+
+impl EmulatorBuilder {
+    pub fn new() -> Self {
+        EmulatorBuilder::default()
+    }
+
+    pub fn model(mut self, model: Model) -> Self {
+        self.model = Some(model);
+        self
+    }
+
+    pub fn rom(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.rom = Some(RomSource::Bytes(bytes.into()));
+        self
+    }
+
+    /// Read the ROM from `path`, next to which save slots go by default.
+    pub fn rom_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.rom = Some(RomSource::Path(path.into()));
+        self
+    }
+
+    /// Run this boot ROM dump before the cartridge. Without a model, its size
+    /// picks the DMG or the CGB.
+    pub fn boot_rom(mut self, bytes: impl Into<Vec<u8>>) -> Self {
+        self.boot = Boot::Dump(bytes.into());
+        self
+    }
+
+    pub fn boot_rom_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.boot = Boot::DumpPath(path.into());
+        self
+    }
+
+    /// Run the built-in boot program, which shows the logo like the real
+    /// one, instead of skipping it.
+    pub fn replacement_boot_rom(mut self) -> Self {
+        self.boot = Boot::Replacement;
+        self
+    }
+
+    /// Start straight at the cartridge, the default.
+    pub fn skip_boot(mut self) -> Self {
+        self.boot = Boot::Skip;
+        self
+    }
+
+    /// Keep save slots here instead of next to the ROM.
+    pub fn save_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.save_directory = Some(directory.into());
+        self
+    }
+
+    pub fn rtc_mode(mut self, mode: RtcMode) -> Self {
+        self.rtc_mode = mode;
+        self
+    }
+
+    /// Read the host's time, in seconds since the Unix epoch, from `clock`
+    /// rather than the system clock when the clock runs in real time.
+    pub fn host_clock(mut self, clock: impl Fn() -> u64 + 'static) -> Self {
+        self.host_clock = Some(Rc::new(clock));
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = sample_rate;
+        self
+    }
+
+    /// Power on with WRAM and HRAM holding noise generated from `seed`,
+    /// rather than zeros, to catch games reading memory they never wrote.
+    pub fn ram_seed(mut self, seed: u64) -> Self {
+        self.ram_seed = Some(seed);
+        self
+    }
+
+    /// Make LY always read as 0x90, like the emulator the logs Gameboy
+    /// Doctor compares traces against were made with.
+    pub fn gameboy_doctor(mut self) -> Self {
+        self.gameboy_doctor = true;
+        self
+    }
+
+    pub fn debug_hook(mut self, hook: impl DebugHook + 'static) -> Self {
+        self.debug_hooks.push(Box::new(hook));
+        self
+    }
+
+    pub fn build(self) -> Result<Emulator, EmulatorError> {
+        let (rom, rom_path) = match self.rom {
+            Some(RomSource::Bytes(bytes)) => (bytes, None),
+            Some(RomSource::Path(path)) => (read(&path)?, Some(path)),
+            None => return Err(EmulatorError::MissingRom),
+        };
+        if rom.is_empty() {
+            return Err(EmulatorError::EmptyRom);
+        }
+        let boot_rom = match &self.boot {
+            Boot::Dump(bytes) => Some(bytes.clone()),
+            Boot::DumpPath(path) => Some(read(path)?),
+            Boot::Skip | Boot::Replacement => None,
+        };
+        let boot_rom = boot_rom
+            .map(|bytes| BootRom::new(&bytes).ok_or(EmulatorError::InvalidBootRom { size: bytes.len() }))
+            .transpose()?;
+
+        let mut emulator = match self.model {
+            Some(model) => Emulator::new(model),
+            None => Emulator::default(),
+        };
+        if let Some(clock) = self.host_clock {
+            emulator.cpu.cartridge.set_host_clock(clock);
+        }
+        emulator.cpu.cartridge.set_rtc_mode(self.rtc_mode);
+        if let Some(seed) = self.ram_seed {
+            emulator.cpu.randomize_ram(seed);
+        }
+        if self.gameboy_doctor {
+            emulator.cpu.ppu.set_ly_stub(Some(GAMEBOY_DOCTOR_LY));
+        }
+        if let Some(boot_rom) = boot_rom {
+            emulator.cpu.load_boot_rom(boot_rom);
+        }
+        emulator.cpu.load_rom(&rom);
+        match self.boot {
+            Boot::Skip => emulator.cpu.skip_boot(),
+            Boot::Replacement => emulator.cpu.load_replacement_boot_rom(),
+            Boot::Dump(_) | Boot::DumpPath(_) => {}
+        }
+
+        emulator.rom_path = rom_path;
+        emulator.save_directory = self.save_directory;
+        emulator.sample_rate = self.sample_rate;
+        emulator.debug_hooks = self.debug_hooks;
+        Ok(emulator)
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, EmulatorError> {
+    fs::read(path).map_err(|source| EmulatorError::Io { path: path.to_path_buf(), source })
+}
@@ -0,0 +1,340 @@
+use crate::batch::{Batch, Condition, Inputs, DEFAULT_FRAME_LIMIT};
+use crate::debug::Trace;
+use crate::emulator::Emulator;
+use crate::gif::GifRecorder;
+use crate::model::Model;
+use crate::recording::{SilentAudio, VideoFormat, VideoWriter, WavWriter};
+use crate::screenshot::{DmgPalette, Screenshot, MAX_SCALE};
+use crate::serial::SerialCapture;
+use crate::terminal;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+pub const EXIT_SUCCESS: i32 = 0;
+/// The ROM couldn't be loaded or an output couldn't be written.
+pub const EXIT_FAILURE: i32 = 1;
+/// The arguments don't make sense.
+pub const EXIT_USAGE: i32 = 2;
+
+pub const USAGE: &str = "\
+Usage: gameboy_emulator [OPTIONS] <ROM>
+
+Options:
+  -m, --model <MODEL>      dmg0, dmg, mgb, sgb, sgb2, cgb or agb
+                           (default: picked from the cartridge header)
+  -b, --boot-rom <PATH>    run this boot ROM first, or \"builtin\" for the
+                           built-in one (default: skip it)
+      --headless           run as fast as possible without drawing, instead
+                           of in real time in the terminal; needs --frames
+                           or --until
+  -f, --frames <N>         stop after N frames
+  -i, --input <PATH>       replay the keys in PATH, one change per line: the
+                           frame, then keys like \"a+right\" or \"-\" for
+                           none (headless only)
+  -u, --until <CONDITION>  stop once \"serial:<TEXT>\" was sent over the link
+                           cable or \"memory:<ADDRESS>=<VALUE>\" holds, in hex;
+                           checked after every frame, for at most 36000
+                           frames (10 minutes) without --frames (headless
+                           only)
+  -o, --output <DIR>       write screenshot.png, serial.bin, ram.bin (work RAM,
+                           HRAM, cartridge RAM) and report.txt (frames,
+                           condition, frame CRC-32) to DIR (headless only)
+  -s, --screenshot <PATH>  write the last frame to PATH as PNG
+      --palette <PALETTE>  draw DMG games' screenshots and GIFs in
+                           \"grayscale\" (default), \"pea-soup\" green, or four
+                           colors from lightest to darkest like
+                           \"e0f8d0,88c070,346856,081820\"
+      --scale <N>          scale screenshots up N times, 1 to 16 (default: 1)
+  -r, --record <PATH>      write every frame to PATH: as Y4M video for a .y4m
+                           file, raw 24-bit RGB otherwise or to stdout for
+                           \"-\" (headless only; serial output then goes to
+                           stderr)
+  -g, --gif <PATH>         write the frames to PATH as an animated GIF, DMG
+                           games in the --palette colors
+      --gif-skip <N>       leave N frames out after each one in the GIF
+                           (default: 1, as browsers slow down faster GIFs)
+  -a, --audio <PATH>       write the sound to PATH as 16-bit stereo WAV at
+                           48 kHz, as long as the video; silent until sound
+                           is emulated
+  -t, --trace <PATH>       log the registers before every instruction to
+                           PATH, in Gameboy Doctor's format; LY then always
+                           reads 0x90, as in the logs it compares against
+      --speed <FACTOR>     run FACTOR times faster than real time (default: 1)
+  -h, --help               print this help
+
+Without --headless the screen is drawn in the terminal, which needs 24-bit
+color and at least 160x73 characters, and stdin and stdout must be that
+terminal. Keys: arrows for the D-pad, x for A,
+z for B, enter for Start, space for Select and q to quit.
+
+Whatever the game sends over the link cable is printed, so test ROMs report
+their results. Exits with 0 on success, 1 if loading the ROM or writing an
+output failed or --until never held, and 2 for invalid arguments.
+";
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Run(Box<Options>),
+    Help,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BootOption {
+    Skip,
+    Builtin,
+    Path(PathBuf),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Options {
+    pub rom: PathBuf,
+    pub model: Option<Model>,
+    pub boot: BootOption,
+    pub headless: bool,
+    pub frames: Option<u64>,
+    pub input: Option<PathBuf>,
+    pub until: Option<Condition>,
+    pub output: Option<PathBuf>,
+    pub screenshot: Option<PathBuf>,
+    /// How `screenshot` and the one in `output` are drawn.
+    pub screenshot_format: Screenshot,
+    pub record: Option<PathBuf>,
+    pub audio: Option<PathBuf>,
+    pub gif: Option<PathBuf>,
+    /// Frames left out of the GIF after each one kept.
+    pub gif_skip: u32,
+    pub trace: Option<PathBuf>,
+    pub speed: f64,
+}
+
+impl Options {
+    /// Whether the video goes to stdout, for `--record -`.
+    pub fn record_to_stdout(&self) -> bool {
+        self.record.as_deref() == Some(Path::new("-"))
+    }
+}
+
+/// Parse the arguments after the program name. Options take their value as
+/// the next argument or after an `=`.
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut rom = None;
+    let mut options = Options {
+        rom: PathBuf::new(),
+        model: None,
+        boot: BootOption::Skip,
+        headless: false,
+        frames: None,
+        input: None,
+        until: None,
+        output: None,
+        screenshot: None,
+        screenshot_format: Screenshot::default(),
+        record: None,
+        audio: None,
+        gif: None,
+        gif_skip: 1,
+        trace: None,
+        speed: 1.0,
+    };
+
+    while let Some(arg) = args.next() {
+        if !arg.starts_with('-') || arg == "-" {
+            if rom.replace(PathBuf::from(&arg)).is_some() {
+                return Err(format!("unexpected argument '{}'", arg));
+            }
+            continue;
+        }
+
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) => (name.to_string(), Some(value.to_string())),
+            None => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value.clone().or_else(|| args.next()).ok_or_else(|| format!("{} needs a value", name))
+        };
+        match name.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--headless" => options.headless = true,
+            "-m" | "--model" => {
+                let value = value()?;
+                options.model = Some(Model::from_name(&value).ok_or_else(|| format!("unknown model '{}'", value))?);
+            }
+            "-b" | "--boot-rom" => {
+                let value = value()?;
+                options.boot = if value == "builtin" { BootOption::Builtin } else { BootOption::Path(value.into()) };
+            }
+            "-f" | "--frames" => {
+                let value = value()?;
+                options.frames = Some(value.parse().map_err(|_| format!("invalid frame count '{}'", value))?);
+            }
+            "-i" | "--input" => options.input = Some(value()?.into()),
+            "-u" | "--until" => options.until = Some(Condition::parse(&value()?)?),
+            "-o" | "--output" => options.output = Some(value()?.into()),
+            "-s" | "--screenshot" => options.screenshot = Some(value()?.into()),
+            "--palette" => options.screenshot_format.palette = DmgPalette::parse(&value()?)?,
+            "--scale" => {
+                let value = value()?;
+                options.screenshot_format.scale = value
+                    .parse()
+                    .ok()
+                    .filter(|scale| (1..=MAX_SCALE).contains(scale))
+                    .ok_or_else(|| format!("invalid scale '{}'", value))?;
+            }
+            "-r" | "--record" => options.record = Some(value()?.into()),
+            "-g" | "--gif" => options.gif = Some(value()?.into()),
+            "--gif-skip" => {
+                let value = value()?;
+                options.gif_skip = value.parse().map_err(|_| format!("invalid frame skip '{}'", value))?;
+            }
+            "-a" | "--audio" => options.audio = Some(value()?.into()),
+            "-t" | "--trace" => options.trace = Some(value()?.into()),
+            "--speed" => {
+                let value = value()?;
+                options.speed = value
+                    .parse()
+                    .ok()
+                    .filter(|speed: &f64| speed.is_finite() && *speed > 0.0)
+                    .ok_or_else(|| format!("invalid speed '{}'", value))?;
+            }
+            _ => return Err(format!("unknown option '{}'", name)),
+        }
+    }
+
+    options.rom = rom.ok_or("no ROM given")?;
+    if options.headless && options.frames.is_none() && options.until.is_none() {
+        return Err("--headless needs --frames or --until".to_string());
+    }
+    if !options.headless && (options.input.is_some() || options.until.is_some() || options.output.is_some()) {
+        return Err("--input, --until and --output need --headless".to_string());
+    }
+    if !options.headless && options.record_to_stdout() {
+        return Err("--record - needs --headless".to_string());
+    }
+    Ok(Command::Run(Box::new(options)))
+}
+
+/// Run the ROM as `options` say until the frame limit, if any.
+pub fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let mut builder = Emulator::builder().rom_path(&options.rom);
+    if let Some(model) = options.model {
+        builder = builder.model(model);
+    }
+    builder = match &options.boot {
+        BootOption::Skip => builder.skip_boot(),
+        BootOption::Builtin => builder.replacement_boot_rom(),
+        BootOption::Path(path) => builder.boot_rom_path(path),
+    };
+    let trace = options.trace.as_deref().map(|path| create(path).map(Trace::new)).transpose()?;
+    if let Some(trace) = &trace {
+        builder = builder.debug_hook(trace.clone()).gameboy_doctor();
+    }
+    let mut emulator = builder.build()?;
+
+    let serial = SerialCapture::new();
+    emulator.cpu.serial.connect(Box::new(serial.clone()));
+    let mut video = match options.record.as_deref() {
+        Some(_) if options.record_to_stdout() => {
+            Some(VideoWriter::new(BufWriter::new(io::stdout()), VideoFormat::Rgb)?)
+        }
+        Some(path) => Some(VideoWriter::new(create(path)?, VideoFormat::from_path(path))?),
+        None => None,
+    };
+    let sample_rate = emulator.sample_rate;
+    let mut audio = match &options.audio {
+        Some(path) => Some((
+            WavWriter::new(create(path)?, sample_rate, 2).map_err(|error| with_path(path, error))?,
+            SilentAudio::new(sample_rate),
+        )),
+        None => None,
+    };
+    let inputs = match &options.input {
+        Some(path) => Some(Inputs::read(path).map_err(|error| with_path(path, error))?),
+        None => None,
+    };
+
+    let mut gif = match &options.gif {
+        Some(path) => Some(
+            GifRecorder::new(create(path)?, options.screenshot_format.palette, options.gif_skip)
+                .map_err(|error| with_path(path, error))?,
+        ),
+        None => None,
+    };
+    let mut after_frame = |emulator: &Emulator| -> io::Result<()> {
+        if let Some(video) = &mut video {
+            video.write_frame(&emulator.cpu.ppu.frame_rgb())?;
+        }
+        if let Some(gif) = &mut gif {
+            gif.add_frame(&emulator.cpu)?;
+        }
+        if let Some((audio, source)) = &mut audio {
+            audio.write_samples(&source.frame_samples())?;
+        }
+        Ok(())
+    };
+    let mut printed = 0;
+    let mut report = None;
+    if options.headless {
+        let frames = options.frames.unwrap_or(DEFAULT_FRAME_LIMIT);
+        let batch = Batch { frames, inputs, until: options.until.clone() };
+        let mut serial_output: Box<dyn Write> =
+            if options.record_to_stdout() { Box::new(io::stderr()) } else { Box::new(io::stdout()) };
+        report = Some(batch.run(&mut emulator, &serial, |emulator| {
+            after_frame(emulator)?;
+            print_serial(&serial, &mut printed, &mut serial_output)
+        })?);
+    } else {
+        // Printed once the terminal shows the normal screen again.
+        terminal::run(&mut emulator, options.speed, options.frames, after_frame)?;
+        print_serial(&serial, &mut printed, &mut io::stdout())?;
+    }
+
+    if let Some(path) = &options.screenshot {
+        emulator.screenshot_with(path, &options.screenshot_format).map_err(|error| with_path(path, error))?;
+    }
+    if let Some(video) = video {
+        video.finish()?;
+    }
+    if let Some((audio, _)) = audio {
+        audio.finish()?;
+    }
+    if let Some(gif) = gif {
+        gif.finish()?;
+    }
+    if let Some(trace) = trace {
+        trace.finish()?;
+    }
+    if let Some(report) = report {
+        if let Some(directory) = &options.output {
+            report
+                .write(directory, &emulator, &serial, &options.screenshot_format)
+                .map_err(|error| with_path(directory, error))?;
+        }
+        if let Some(condition) = options.until.as_ref().filter(|_| !report.condition_met) {
+            return Err(format!("{} didn't hold after {} frames", condition, report.frames).into());
+        }
+    }
+    Ok(())
+}
+
+fn create(path: &Path) -> io::Result<BufWriter<File>> {
+    File::create(path).map(BufWriter::new).map_err(|error| with_path(path, error))
+}
+
+fn with_path(path: &Path, error: io::Error) -> io::Error {
+    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+/// Print what the game sent after the first `printed` bytes, which are
+/// kept for `--until` and `--output`.
+fn print_serial(serial: &SerialCapture, printed: &mut usize, output: &mut dyn Write) -> io::Result<()> {
+    let bytes = serial.bytes();
+    if bytes.len() == *printed {
+        return Ok(());
+    }
+    output.write_all(&bytes[*printed..])?;
+    *printed = bytes.len();
+    output.flush()
+}
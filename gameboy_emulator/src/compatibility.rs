@@ -0,0 +1,233 @@
+use crate::cartridge::Header;
+use crate::joypad::Button;
+
+pub const KEY0_ADDRESS: u16 = 0xFF4C;
+/// KEY0 value the boot ROM writes for cartridges without CGB support.
+pub const KEY0_DMG_COMPATIBILITY: u8 = 0x04;
+
+const NINTENDO_OLD_LICENSEE: u8 = 0x01;
+const USE_NEW_LICENSEE: u8 = 0x33;
+const NINTENDO_NEW_LICENSEE: [u8; 2] = *b"01";
+
+/// Colors given to a DMG game on the CGB, as 24-bit RGB from lightest to
+/// darkest: one palette for the background and one for each of OBP0/OBP1.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Palettes {
+    pub bg: [u32; 4],
+    pub obj0: [u32; 4],
+    pub obj1: [u32; 4],
+}
+
+const fn uniform(colors: [u32; 4]) -> Palettes {
+    Palettes { bg: colors, obj0: colors, obj1: colors }
+}
+
+const WHITE_RED: [u32; 4] = [0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000];
+const WHITE_GREEN: [u32; 4] = [0xFFFFFF, 0x7BFF31, 0x008400, 0x000000];
+const WHITE_BLUE: [u32; 4] = [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000];
+const WHITE_BROWN: [u32; 4] = [0xFFFFFF, 0xFFAD63, 0x843100, 0x000000];
+
+pub const BROWN: Palettes = uniform(WHITE_BROWN);
+pub const RED: Palettes = Palettes { bg: WHITE_RED, obj0: WHITE_GREEN, obj1: WHITE_BLUE };
+pub const DARK_BROWN: Palettes = Palettes {
+    bg: [0xFFE6C5, 0xCE9C84, 0x846B29, 0x5A3108],
+    obj0: WHITE_BROWN,
+    obj1: WHITE_BROWN,
+};
+pub const BLUE: Palettes = Palettes { bg: WHITE_BLUE, obj0: WHITE_RED, obj1: WHITE_GREEN };
+pub const DARK_BLUE: Palettes = Palettes {
+    bg: [0xFFFFFF, 0x8C8CDE, 0x52528C, 0x000000],
+    obj0: WHITE_RED,
+    obj1: WHITE_BROWN,
+};
+pub const GRAYSCALE: Palettes = uniform([0xFFFFFF, 0xA5A5A5, 0x525252, 0x000000]);
+pub const PALE_YELLOW: Palettes = uniform([0xFFFFA5, 0xFF9494, 0x9494FF, 0x000000]);
+pub const ORANGE: Palettes = uniform([0xFFFFFF, 0xFFFF00, 0xFF0000, 0x000000]);
+pub const YELLOW: Palettes = Palettes {
+    bg: [0xFFFFFF, 0xFFFF00, 0x7B4A00, 0x000000],
+    obj0: WHITE_BLUE,
+    obj1: WHITE_GREEN,
+};
+pub const GREEN: Palettes = uniform([0xFFFFFF, 0x52FF00, 0xFF4200, 0x000000]);
+/// Also what every game missing from the title table gets.
+pub const DARK_GREEN: Palettes = Palettes {
+    bg: [0xFFFFFF, 0x7BFF31, 0x0063C5, 0x000000],
+    obj0: WHITE_RED,
+    obj1: WHITE_RED,
+};
+pub const INVERTED: Palettes = uniform([0x000000, 0x008484, 0xFFDE00, 0xFFFFFF]);
+
+/// The boot ROM's colors in the CGB's 15-bit format, four to a palette from
+/// lightest to darkest.
+const COLORS: [u16; 120] = [
+    0x7FFF, 0x32BF, 0x00D0, 0x0000, 0x639F, 0x4279, 0x15B0, 0x04CB, 0x7FFF, 0x6E31, 0x454A, 0x0000,
+    0x7FFF, 0x1BEF, 0x0200, 0x0000, 0x7FFF, 0x421F, 0x1CF2, 0x0000, 0x7FFF, 0x5294, 0x294A, 0x0000,
+    0x7FFF, 0x03FF, 0x012F, 0x0000, 0x7FFF, 0x03EF, 0x01D6, 0x0000, 0x7FFF, 0x42B5, 0x3DC8, 0x0000,
+    0x7E74, 0x03FF, 0x0180, 0x0000, 0x67FF, 0x77AC, 0x1A13, 0x2D6B, 0x7ED6, 0x4BFF, 0x2175, 0x0000,
+    0x53FF, 0x4A5F, 0x7E52, 0x0000, 0x4FFF, 0x7ED2, 0x3A4C, 0x1CE0, 0x03ED, 0x7FFF, 0x255F, 0x0000,
+    0x036A, 0x021F, 0x03FF, 0x7FFF, 0x7FFF, 0x01DF, 0x0112, 0x0000, 0x231F, 0x035F, 0x00F2, 0x0009,
+    0x7FFF, 0x03EA, 0x011F, 0x0000, 0x299F, 0x001A, 0x000C, 0x0000, 0x7FFF, 0x027F, 0x001F, 0x0000,
+    0x7FFF, 0x03E0, 0x0206, 0x0120, 0x7FFF, 0x7EEB, 0x001F, 0x7C00, 0x7FFF, 0x3FFF, 0x7E00, 0x001F,
+    0x7FFF, 0x03FF, 0x001F, 0x0000, 0x03FF, 0x001F, 0x000C, 0x0000, 0x7FFF, 0x033F, 0x0193, 0x0000,
+    0x0000, 0x4200, 0x037F, 0x7FFF, 0x7FFF, 0x7E8C, 0x7C00, 0x0000, 0x7FFF, 0x1BEF, 0x6180, 0x0000,
+];
+
+/// Where the OBJ0, OBJ1 and BG palettes of a combination start in `COLORS`,
+/// given as palette numbers.
+const fn palettes(obj0: usize, obj1: usize, bg: usize) -> [usize; 3] {
+    [obj0 * 4, obj1 * 4, bg * 4]
+}
+
+/// The boot ROM's palette combinations. A few start in the middle of a
+/// palette, as they do in the boot ROM.
+const COMBINATIONS: [[usize; 3]; 51] = [
+    palettes(4, 4, 29),
+    palettes(18, 18, 18),
+    palettes(20, 20, 20),
+    palettes(24, 24, 24),
+    palettes(9, 9, 9),
+    palettes(0, 0, 0),
+    palettes(27, 27, 27),
+    palettes(5, 5, 5),
+    palettes(12, 12, 12),
+    palettes(26, 26, 26),
+    palettes(16, 8, 8),
+    palettes(4, 28, 28),
+    palettes(4, 2, 2),
+    palettes(3, 4, 4),
+    palettes(4, 29, 29),
+    palettes(28, 4, 28),
+    palettes(2, 17, 2),
+    palettes(16, 16, 8),
+    palettes(4, 4, 7),
+    palettes(4, 4, 18),
+    palettes(4, 4, 20),
+    palettes(19, 19, 9),
+    [4 * 4 - 1, 4 * 4 - 1, 11 * 4],
+    palettes(17, 17, 2),
+    palettes(4, 4, 2),
+    palettes(4, 4, 3),
+    palettes(28, 28, 0),
+    palettes(3, 3, 0),
+    palettes(0, 0, 1),
+    palettes(18, 22, 18),
+    palettes(20, 22, 20),
+    palettes(24, 22, 24),
+    palettes(16, 22, 8),
+    palettes(17, 4, 13),
+    [28 * 4 - 1, 0, 14 * 4],
+    [28 * 4 - 1, 4 * 4, 15 * 4],
+    palettes(19, 22, 9),
+    palettes(16, 28, 10),
+    palettes(4, 23, 28),
+    palettes(17, 22, 2),
+    palettes(4, 0, 2),
+    palettes(4, 28, 3),
+    palettes(28, 3, 0),
+    palettes(3, 28, 4),
+    palettes(21, 28, 4),
+    palettes(3, 28, 0),
+    palettes(25, 3, 28),
+    palettes(0, 28, 8),
+    palettes(4, 3, 28),
+    palettes(28, 3, 6),
+    palettes(4, 28, 29),
+];
+
+/// Title checksums of the Nintendo games with colors of their own. The boot
+/// ROM keeps only the sum of the title bytes, so other games whose titles
+/// sum the same get these colors too.
+const TITLE_CHECKSUMS: [u8; 94] = [
+    0x00, 0x88, 0x16, 0x36, 0xD1, 0xDB, 0xF2, 0x3C, 0x8C, 0x92, 0x3D, 0x5C, 0x58, 0xC9, 0x3E, 0x70,
+    0x1D, 0x59, 0x69, 0x19, 0x35, 0xA8, 0x14, 0xAA, 0x75, 0x95, 0x99, 0x34, 0x6F, 0x15, 0xFF, 0x97,
+    0x4B, 0x90, 0x17, 0x10, 0x39, 0xF7, 0xF6, 0xA2, 0x49, 0x4E, 0x43, 0x68, 0xE0, 0x8B, 0xF0, 0xCE,
+    0x0C, 0x29, 0xE8, 0xB7, 0x86, 0x9A, 0x52, 0x01, 0x9D, 0x71, 0x9C, 0xBD, 0x5D, 0x6D, 0x67, 0x3F,
+    0x6B, 0xB3, 0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+    0x46, 0x28, 0xA5, 0xC6, 0xD3, 0x27, 0x61, 0x18, 0x66, 0x6A, 0xBF, 0x0D, 0xF4, 0xB3,
+];
+
+/// Checksums from this entry on are shared by several titles, which are told
+/// apart by their fourth letter.
+const FIRST_SHARED_CHECKSUM: usize = 65;
+const FOURTH_LETTERS: &[u8; TITLE_CHECKSUMS.len() - FIRST_SHARED_CHECKSUM] = b"BEFAARBEKEK R-URAR INAILICE R";
+
+/// The entry of `COMBINATIONS` for each title checksum.
+const TITLE_COMBINATIONS: [u8; 94] = [
+    0, 4, 5, 35, 34, 31, 31, 15, 10, 5, 19, 36, 7, 37, 30, 44, 21, 32, 31, 20, 5, 33, 13, 14, 5, 29, 5, 18, 9, 3,
+    2, 26, 25, 25, 41, 42, 26, 45, 42, 45, 36, 38, 26, 42, 30, 41, 34, 34, 5, 42, 6, 5, 33, 25, 42, 42, 40, 2,
+    16, 25, 42, 42, 5, 0, 39, 36, 32, 25, 6, 32, 12, 36, 11, 39, 18, 39, 24, 31, 50, 17, 46, 6, 27, 0, 47, 41,
+    41, 0, 0, 19, 34, 23, 18, 29,
+];
+
+/// Scale a 15-bit color to 24-bit RGB, rounding, so that `rgb_to_color`
+/// gives it back.
+fn color_to_rgb(color: u16) -> u32 {
+    let channel = |shift: u16| (((color >> shift) & 0x1F) as u32 * 0xFF + 15) / 0x1F;
+    channel(0) << 16 | channel(5) << 8 | channel(10)
+}
+
+fn combination(index: usize) -> Palettes {
+    let palette = |start: usize| std::array::from_fn(|color| color_to_rgb(COLORS[start + color]));
+    let [obj0, obj1, bg] = COMBINATIONS[index];
+    Palettes { bg: palette(bg), obj0: palette(obj0), obj1: palette(obj1) }
+}
+
+/// Only games published by Nintendo get a palette of their own.
+pub fn licensed_by_nintendo(header: &Header) -> bool {
+    header.old_licensee == NINTENDO_OLD_LICENSEE
+        || (header.old_licensee == USE_NEW_LICENSEE && header.new_licensee == NINTENDO_NEW_LICENSEE)
+}
+
+/// The colors the CGB boot ROM picks for a cartridge from its header alone.
+pub fn for_header(header: &Header) -> Palettes {
+    if !licensed_by_nintendo(header) {
+        return DARK_GREEN;
+    }
+
+    let fourth_letter = header.title.as_bytes().get(3).copied();
+    let index = (0..TITLE_CHECKSUMS.len()).find(|&index| {
+        TITLE_CHECKSUMS[index] == header.title_checksum
+            && (index < FIRST_SHARED_CHECKSUM || Some(FOURTH_LETTERS[index - FIRST_SHARED_CHECKSUM]) == fourth_letter)
+    });
+    index.map_or(DARK_GREEN, |index| combination(TITLE_COMBINATIONS[index] as usize))
+}
+
+/// The colors chosen by holding a direction, alone or with A or B, while the
+/// logo is shown. Other key combinations don't override anything.
+pub fn for_buttons(pressed: u8) -> Option<Palettes> {
+    let direction = [Button::Up, Button::Left, Button::Down, Button::Right]
+        .into_iter()
+        .filter(|button| pressed & button.mask() != 0)
+        .collect::<Vec<_>>();
+    let a = pressed & Button::A.mask() != 0;
+    let b = pressed & Button::B.mask() != 0;
+
+    let palettes = match (direction.as_slice(), a, b) {
+        ([Button::Up], false, false) => BROWN,
+        ([Button::Up], true, false) => RED,
+        ([Button::Up], false, true) => DARK_BROWN,
+        ([Button::Left], false, false) => BLUE,
+        ([Button::Left], true, false) => DARK_BLUE,
+        ([Button::Left], false, true) => GRAYSCALE,
+        ([Button::Down], false, false) => PALE_YELLOW,
+        ([Button::Down], true, false) => ORANGE,
+        ([Button::Down], false, true) => YELLOW,
+        ([Button::Right], false, false) => GREEN,
+        ([Button::Right], true, false) => DARK_GREEN,
+        ([Button::Right], false, true) => INVERTED,
+        _ => return None,
+    };
+    Some(palettes)
+}
+
+/// The colors a DMG game gets on the CGB: the button override if one is
+/// held, otherwise the title lookup.
+pub fn select(header: &Header, pressed: u8) -> Palettes {
+    for_buttons(pressed).unwrap_or_else(|| for_header(header))
+}
+
+/// Convert a 24-bit RGB color to the CGB's 15-bit format.
+pub fn rgb_to_color(rgb: u32) -> u16 {
+    let channel = |shift: u32| ((rgb >> shift) & 0xFF) as u16 >> 3;
+    channel(16) | channel(8) << 5 | channel(0) << 10
+}
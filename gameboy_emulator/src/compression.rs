@@ -0,0 +1,136 @@
+use crate::state;
+use std::io;
+
+/// Matches shorter than this cost more to encode than the literals.
+const MIN_MATCH: usize = 4;
+/// A match can reach back as far as a 16-bit offset allows.
+const MAX_OFFSET: usize = 0xFFFF;
+const HASH_BITS: u32 = 12;
+/// A length nibble of 15 means more length bytes follow.
+const LENGTH_EXTENDED: usize = 15;
+
+/// Compress `data` into an LZ4-style stream: its length, then sequences of
+/// literals each followed by a match copied from earlier output. Save
+/// states are mostly zeros and repeated tiles, which this shrinks a lot
+/// while staying fast enough to run every frame.
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(data.len() / 4 + 16);
+    output.extend_from_slice(&(data.len() as u32).to_le_bytes());
+
+    // Last position seen for each hash of four bytes.
+    let mut table = vec![None; 1 << HASH_BITS];
+    let mut literal_start = 0;
+    let mut position = 0;
+    while position + MIN_MATCH <= data.len() {
+        let key = &data[position..position + MIN_MATCH];
+        let hash = hash(key);
+        let candidate = table[hash].filter(|&candidate| {
+            position - candidate <= MAX_OFFSET && &data[candidate..candidate + MIN_MATCH] == key
+        });
+        table[hash] = Some(position);
+
+        let Some(candidate) = candidate else {
+            position += 1;
+            continue;
+        };
+        let mut length = MIN_MATCH;
+        while position + length < data.len() && data[candidate + length] == data[position + length] {
+            length += 1;
+        }
+        write_sequence(&mut output, &data[literal_start..position], Some((position - candidate, length)));
+        position += length;
+        literal_start = position;
+    }
+    write_sequence(&mut output, &data[literal_start..], None);
+    output
+}
+
+/// Undo `compress`. Streams that don't decode to exactly the length they
+/// announce fail with `InvalidData`.
+pub fn decompress(bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let corrupt = || state::invalid("compressed data is corrupt");
+    let size = bytes.get(..4).ok_or_else(corrupt)?;
+    let size = u32::from_le_bytes(size.try_into().expect("4 bytes")) as usize;
+    // Every input byte decodes to at most 255 or so output bytes, which
+    // bounds what a damaged length can make us allocate.
+    let mut output = Vec::with_capacity(size.min(bytes.len().saturating_mul(256)));
+
+    let mut position = 4;
+    while position < bytes.len() {
+        let token = bytes[position] as usize;
+        position += 1;
+
+        let literal_length = read_length(bytes, &mut position, token >> 4)?;
+        let literals = position
+            .checked_add(literal_length)
+            .and_then(|end| bytes.get(position..end))
+            .filter(|literals| output.len() + literals.len() <= size)
+            .ok_or_else(corrupt)?;
+        output.extend_from_slice(literals);
+        position += literal_length;
+        if position == bytes.len() {
+            break;
+        }
+
+        let offset = bytes.get(position..position + 2).ok_or_else(corrupt)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        position += 2;
+        let length = read_length(bytes, &mut position, token & 0x0F)? + MIN_MATCH;
+        if offset == 0 || offset > output.len() || output.len() + length > size {
+            return Err(corrupt());
+        }
+        // Byte by byte, since a match may overlap the bytes it produces.
+        let start = output.len() - offset;
+        for index in start..start + length {
+            output.push(output[index]);
+        }
+    }
+
+    if output.len() == size { Ok(output) } else { Err(corrupt()) }
+}
+
+fn hash(key: &[u8]) -> usize {
+    let value = u32::from_le_bytes([key[0], key[1], key[2], key[3]]);
+    (value.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_length = matched.map_or(0, |(_, length)| length - MIN_MATCH);
+    let token = (literals.len().min(LENGTH_EXTENDED) << 4) | match_length.min(LENGTH_EXTENDED);
+    output.push(token as u8);
+    write_length(output, literals.len());
+    output.extend_from_slice(literals);
+    if let Some((offset, _)) = matched {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(output, match_length);
+    }
+}
+
+/// The part of `length` that didn't fit in its nibble, as bytes of 255
+/// ended by a smaller one.
+fn write_length(output: &mut Vec<u8>, length: usize) {
+    if length < LENGTH_EXTENDED {
+        return;
+    }
+    let mut rest = length - LENGTH_EXTENDED;
+    while rest >= 0xFF {
+        output.push(0xFF);
+        rest -= 0xFF;
+    }
+    output.push(rest as u8);
+}
+
+fn read_length(bytes: &[u8], position: &mut usize, nibble: usize) -> io::Result<usize> {
+    let mut length = nibble;
+    if nibble == LENGTH_EXTENDED {
+        loop {
+            let byte = *bytes.get(*position).ok_or_else(|| state::invalid("compressed data is corrupt"))?;
+            *position += 1;
+            length += byte as usize;
+            if byte != 0xFF {
+                break;
+            }
+        }
+    }
+    Ok(length)
+}
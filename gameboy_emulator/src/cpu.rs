@@ -1,10 +1,50 @@
 use crate::bitwise;
+use crate::boot_rom;
+use crate::boot_rom::BootRom;
+use crate::cartridge::Cartridge;
+use crate::compatibility;
+use crate::hdma;
+use crate::hdma::Hdma;
+use crate::joypad;
+use crate::joypad::{Button, Joypad};
+use crate::model::Model;
+use crate::ppu;
+use crate::ppu::PPU;
+use crate::serial::Serial;
+use crate::sgb::Sgb;
+use crate::state::{self, StateReader, StateWriter};
+use crate::table::CYCLES;
 use crate::table::INSTRUCTIONS;
+use crate::timer;
+use crate::timer::Timer;
+use std::io;
 
 const REGISTER_COUNT: usize = 8;
 const MEMORY_SIZE: usize = 65536;
 const IE_REGISTER_ADDRESS: usize = 0xFFFF;
-// const IRQ_REGISTER_ADDRESS: usize = 0xFF0F;
+const HRAM: std::ops::Range<usize> = 0xFF80..0xFFFF;
+const IF_REGISTER_ADDRESS: usize = 0xFF0F;
+const SB_REGISTER_ADDRESS: u16 = 0xFF01;
+const SC_REGISTER_ADDRESS: u16 = 0xFF02;
+const DMA_REGISTER_ADDRESS: u16 = 0xFF46;
+const KEY1_REGISTER_ADDRESS: u16 = 0xFF4D;
+const SVBK_REGISTER_ADDRESS: u16 = 0xFF70;
+
+const WRAM_BANK_SIZE: usize = 0x1000;
+const WRAM_BANKS: usize = 8;
+const OAM_DMA_LENGTH: u16 = 0xA0;
+/// Copying a 16-byte VRAM DMA block takes 8 machine cycles at normal speed,
+/// and as long in real time at double speed.
+const VRAM_DMA_BLOCK_CYCLES: u32 = 8 * 4;
+
+const INTERRUPT_DISPATCH_CYCLES: u32 = 20;
+const HALT_CYCLES: u32 = 4;
+/// The CPU is stopped for 2050 machine cycles while the clock switches speed.
+const SPEED_SWITCH_CYCLES: u32 = 2050 * 4;
+
+const KEY1_CURRENT_SPEED: usize = 7;
+const KEY1_PREPARE_SWITCH: usize = 0;
+const KEY1_UNUSED_MASK: u8 = 0x7E;
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum Flag {
@@ -43,12 +83,55 @@ pub enum Interrupt {
     Joypad,
 }
 
+impl Interrupt {
+    /// Interrupts in priority order, matching their bit in IE and IF.
+    pub const ALL: [Interrupt; 5] = [
+        Interrupt::VBLank,
+        Interrupt::LCDStatus,
+        Interrupt::Timer,
+        Interrupt::Serial,
+        Interrupt::Joypad,
+    ];
+
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn vector(self) -> u16 {
+        0x0040 + 8 * self as u16
+    }
+}
+
 pub struct CPU {
     pub registers: [u8; REGISTER_COUNT],
     pub stack_pointer: u16,
     pub program_counter: u16,
     pub memory: [u8; MEMORY_SIZE],
     pub ime_flag: bool,
+    pub halted: bool,
+    pub stopped: bool,
+    pub model: Model,
+    /// Whether `model` was chosen with `set_model` rather than picked from
+    /// the cartridge header.
+    model_selected: bool,
+    pub cgb_mode: bool,
+    pub double_speed: bool,
+    pub speed_switch_armed: bool,
+    pub cartridge: Cartridge,
+    /// Mapped over the cartridge until it writes to 0xFF50.
+    pub boot_rom: Option<BootRom>,
+    pub ppu: PPU,
+    pub serial: Serial,
+    pub timer: Timer,
+    pub hdma: Hdma,
+    pub joypad: Joypad,
+    pub sgb: Option<Box<Sgb>>,
+    pub wram: [u8; WRAM_BANKS * WRAM_BANK_SIZE],
+    pub wram_bank: u8,
+    instruction_cycles: u32,
+    /// Cycles of the current instruction spent in a speed switch, during
+    /// which the timer is not clocked.
+    stall_cycles: u32,
 }
 
 impl CPU {
@@ -59,37 +142,403 @@ impl CPU {
             program_counter: 0x0000,
             memory: [0x00; MEMORY_SIZE],
             ime_flag: false,
+            halted: false,
+            stopped: false,
+            model: Model::Dmg,
+            model_selected: false,
+            cgb_mode: false,
+            double_speed: false,
+            speed_switch_armed: false,
+            cartridge: Cartridge::default(),
+            boot_rom: None,
+            ppu: PPU::default(),
+            serial: Serial::default(),
+            timer: Timer::default(),
+            hdma: Hdma::default(),
+            joypad: Joypad::default(),
+            sgb: None,
+            wram: [0x00; WRAM_BANKS * WRAM_BANK_SIZE],
+            wram_bank: 1,
+            instruction_cycles: 0,
+            stall_cycles: 0,
         })
     }
 
+    /// Insert a cartridge. Unless a model was chosen, the header picks one.
+    /// CGB mode and the SGB are only enabled for cartridges supporting them,
+    /// except that a CGB boot ROM always starts in CGB mode.
     pub fn load_rom(&mut self, rom_bytes: &[u8]) {
-        let until = rom_bytes.len();
-        self.memory[..until].copy_from_slice(rom_bytes);
+        let rtc_mode = self.cartridge.rtc_mode();
+        let host_clock = self.cartridge.host_clock();
+        self.cartridge = Cartridge::new(rom_bytes.to_vec());
+        self.cartridge.set_host_clock(host_clock);
+        self.cartridge.set_rtc_mode(rtc_mode);
+        let header = &self.cartridge.header;
+        if !self.model_selected {
+            self.model = Model::from_header(header);
+        }
+        let cgb_mode = self.model.is_cgb() && (header.supports_cgb() || self.boot_rom.is_some());
+        let sgb_mode = self.model.is_sgb() && header.supports_sgb();
+        self.set_cgb_mode(cgb_mode);
+        self.set_sgb_mode(sgb_mode);
     }
 
-    pub fn execute_instruction(&mut self) {
-        let opcode = self.fetch_next_8bits_pc();
-        INSTRUCTIONS[opcode as usize].execute(self);
+    pub fn set_model(&mut self, model: Model) {
+        self.model = model;
+        self.model_selected = true;
     }
 
-    pub fn handle_interrupts(&mut self) {
-        if self.interrupts_enabled() {
-            match self.active_interrupt() {
-                Interrupt::VBLank => self.handle_vblank_interrupt(),
-                Interrupt::LCDStatus => self.handle_lcdstatus_interrupt(),
-                Interrupt::Timer => self.handle_timer_interrupt(),
-                Interrupt::Serial => self.handle_serial_interrupt(),
-                Interrupt::Joypad => self.handle_joypad_interrupt(),
+    /// Map a boot ROM and start running it at 0x0000, instead of calling
+    /// `skip_boot`. Unless a model was chosen, the size of the boot ROM picks
+    /// the DMG or the CGB rather than the cartridge header. A CGB boot ROM
+    /// runs in CGB mode until it writes KEY0.
+    pub fn load_boot_rom(&mut self, boot_rom: BootRom) {
+        if !self.model_selected {
+            self.set_model(if boot_rom.is_cgb() { Model::Cgb } else { Model::Dmg });
+        }
+        self.boot_rom = Some(boot_rom);
+        self.set_cgb_mode(self.model.is_cgb());
+        self.set_pc(0x0000);
+    }
+
+    /// Boot the cartridge with our own boot program for the model, built
+    /// for its header, so the cartridge must be loaded first.
+    pub fn load_replacement_boot_rom(&mut self) {
+        // Keep the model the header picked rather than the one implied by
+        // the boot ROM size, which can't tell the AGB from the CGB.
+        self.set_model(self.model);
+        self.load_boot_rom(BootRom::replacement(self.model, &self.cartridge.header));
+    }
+
+    /// Start the cartridge at 0x0100 with the CPU and I/O registers set as
+    /// the model's boot ROM would leave them, without running one. On a CGB
+    /// a cartridge without CGB support is colorized, as the boot ROM does.
+    pub fn skip_boot(&mut self) {
+        if self.model.is_cgb() && !self.cartridge.header.supports_cgb() {
+            self.colorize();
+        }
+
+        self.registers = self.model.post_boot_registers(&self.cartridge.header);
+        self.set_sp(0xFFFE);
+        self.set_pc(0x0100);
+        self.timer.set_counter(self.model.post_boot_divider());
+        for (address, value) in self.model.post_boot_io() {
+            self.set_memory_8bit(address, value);
+        }
+        // Writing DMA would start a transfer, so it's set behind its back.
+        self.memory[DMA_REGISTER_ADDRESS as usize] = if self.model.is_cgb() { 0x00 } else { 0xFF };
+    }
+
+    /// Fill WRAM and HRAM with noise generated from `seed`, as they come up
+    /// at power on, instead of zeros.
+    pub fn randomize_ram(&mut self, seed: u64) {
+        // SplitMix64, which is fine with any seed.
+        let mut state = seed;
+        let mut next = || {
+            state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+            let mut value = state;
+            value = (value ^ (value >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            value = (value ^ (value >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            (value ^ (value >> 31)) as u8
+        };
+        for byte in self.wram.iter_mut().chain(&mut self.memory[HRAM]) {
+            *byte = next();
+        }
+    }
+
+    pub fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+        self.ppu.cgb_mode = cgb_mode;
+        self.ppu.dmg_compatibility = false;
+    }
+
+    /// Plug the cartridge into a Super Game Boy, which listens for packets on
+    /// the joypad port and colors and frames the screen.
+    pub fn set_sgb_mode(&mut self, enabled: bool) {
+        self.sgb = if enabled { Some(Box::default()) } else { None };
+    }
+
+    /// Apply a KEY0 value: bit 2 set puts the CGB in DMG compatibility mode,
+    /// cleared leaves it in CGB mode. The boot ROM writes it once, before
+    /// handing over to the cartridge.
+    pub fn set_key0(&mut self, value: u8) {
+        let dmg_compatibility = value & compatibility::KEY0_DMG_COMPATIBILITY != 0;
+        self.set_cgb_mode(!dmg_compatibility);
+        self.ppu.dmg_compatibility = dmg_compatibility;
+    }
+
+    /// Do what the CGB boot ROM does for a cartridge without CGB support:
+    /// load the colors picked from the held keys or the header into palettes
+    /// BG0, OBJ0 and OBJ1, and enter DMG compatibility mode.
+    pub fn colorize(&mut self) {
+        let palettes = compatibility::select(&self.cartridge.header, self.joypad.pressed());
+        for (color, rgb) in palettes.bg.into_iter().enumerate() {
+            self.ppu.bg_palettes.set_color(0, color as u8, compatibility::rgb_to_color(rgb));
+        }
+        for (palette, colors) in [palettes.obj0, palettes.obj1].into_iter().enumerate() {
+            for (color, rgb) in colors.into_iter().enumerate() {
+                self.ppu.obj_palettes.set_color(palette as u8, color as u8, compatibility::rgb_to_color(rgb));
             }
         }
+        self.set_key0(compatibility::KEY0_DMG_COMPATIBILITY);
+    }
+
+    pub fn press(&mut self, button: Button) {
+        self.press_player(0, button);
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.release_player(0, button);
+    }
+
+    /// Press a key on joypad `player`, 0 for player 1. Players 2 to 4 only
+    /// have a joypad on the SGB, after MLT_REQ.
+    pub fn press_player(&mut self, player: u8, button: Button) {
+        let newly_pressed = match (player, &mut self.sgb) {
+            (0, _) => self.joypad.press(button),
+            (1..=3, Some(sgb)) => sgb.press(player, button),
+            _ => false,
+        };
+        if newly_pressed {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    pub fn release_player(&mut self, player: u8, button: Button) {
+        match (player, &mut self.sgb) {
+            (0, _) => self.joypad.release(button),
+            (1..=3, Some(sgb)) => sgb.release(player, button),
+            _ => {}
+        }
+    }
+
+    /// Write the whole machine's state. Which endpoint is plugged into the
+    /// serial port, and whether the model was chosen or picked from the
+    /// header, are settings rather than state and aren't saved.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.registers);
+        state.write_u16(self.stack_pointer);
+        state.write_u16(self.program_counter);
+        state.write_bytes(&self.memory);
+        state.write_bool(self.ime_flag);
+        state.write_bool(self.halted);
+        state.write_bool(self.stopped);
+        let model = Model::ALL.iter().position(|&model| model == self.model).expect("model in Model::ALL");
+        state.write_u8(model as u8);
+        state.write_bool(self.cgb_mode);
+        state.write_bool(self.double_speed);
+        state.write_bool(self.speed_switch_armed);
+        state.write_bytes(&self.wram);
+        state.write_u8(self.wram_bank);
+        state.write_bool(self.boot_rom.is_some());
+        if let Some(boot_rom) = &self.boot_rom {
+            state.write_vec(boot_rom.data());
+        }
+        state.write_bool(self.sgb.is_some());
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(state);
+        }
+        self.cartridge.save_state(state);
+        self.ppu.save_state(state);
+        self.serial.save_state(state);
+        self.timer.save_state(state);
+        self.hdma.save_state(state);
+        self.joypad.save_state(state);
+    }
+
+    /// Load a state written by `save_state` for the same cartridge. On error
+    /// the machine may be left partly loaded; `Emulator::load_state` takes
+    /// care of restoring it.
+    pub fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.read_bytes(&mut self.registers)?;
+        self.stack_pointer = state.read_u16()?;
+        self.program_counter = state.read_u16()?;
+        state.read_bytes(&mut self.memory)?;
+        self.ime_flag = state.read_bool()?;
+        self.halted = state.read_bool()?;
+        self.stopped = state.read_bool()?;
+        self.model = Model::ALL[state.read_index(Model::ALL.len())?];
+        self.cgb_mode = state.read_bool()?;
+        self.double_speed = state.read_bool()?;
+        self.speed_switch_armed = state.read_bool()?;
+        state.read_bytes(&mut self.wram)?;
+        self.wram_bank = (state.read_u8()? & 0x07).max(1);
+        self.boot_rom = if state.read_bool()? {
+            let boot_rom = BootRom::new(&state.read_vec()?);
+            Some(boot_rom.ok_or_else(|| state::invalid("save state has an invalid boot ROM"))?)
+        } else {
+            None
+        };
+        self.sgb = if state.read_bool()? {
+            let mut sgb = Box::<Sgb>::default();
+            sgb.load_state(state)?;
+            Some(sgb)
+        } else {
+            None
+        };
+        self.cartridge.load_state(state)?;
+        self.ppu.load_state(state)?;
+        self.serial.load_state(state)?;
+        self.timer.load_state(state)?;
+        self.hdma.load_state(state)?;
+        self.joypad.load_state(state)?;
+        Ok(())
+    }
+
+    /// Execute a single instruction (or dispatch a pending interrupt) and
+    /// advance the rest of the hardware by the cycles it took. Returns the
+    /// number of cycles elapsed at normal speed, which in double speed mode
+    /// is half the number of CPU cycles.
+    pub fn execute_instruction(&mut self) -> u32 {
+        self.instruction_cycles = 0;
+        self.stall_cycles = 0;
+
+        if self.stopped {
+            self.wake_from_stop();
+        }
+
+        if self.handle_interrupts() {
+            self.add_cycles(INTERRUPT_DISPATCH_CYCLES);
+        } else if self.halted || self.stopped {
+            self.add_cycles(HALT_CYCLES);
+        } else {
+            let opcode = self.fetch_next_8bits_pc();
+            self.add_cycles(CYCLES[opcode as usize] as u32);
+            INSTRUCTIONS[opcode as usize].execute(self);
+        }
+
+        let cycles = self.instruction_cycles;
+        self.tick(cycles)
+    }
+
+    /// Add to the cost of the instruction being executed, e.g. when a
+    /// conditional branch is taken.
+    pub fn add_cycles(&mut self, cycles: u32) {
+        self.instruction_cycles += cycles;
+    }
+
+    /// Dispatch the highest priority pending interrupt, if any. A pending
+    /// interrupt always wakes the CPU from HALT, even with IME cleared.
+    /// Returns `true` if an interrupt was dispatched.
+    pub fn handle_interrupts(&mut self) -> bool {
+        let pending = self.pending_interrupts();
+        if pending == 0 {
+            return false;
+        }
+        self.halted = false;
+        if !self.ime_flag {
+            return false;
+        }
+
+        let interrupt = Interrupt::ALL
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+            .expect("pending interrupt");
+
+        self.memory[IF_REGISTER_ADDRESS] &= !interrupt.mask();
+        self.reset_ime_flag();
+        self.push_16bit_sp(self.program_counter);
+        self.set_pc(interrupt.vector());
+        true
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.memory[IF_REGISTER_ADDRESS] |= interrupt.mask();
     }
 
     pub fn get_memory_8bit(&self, address: u16) -> u8 {
-        self.memory[address as usize]
+        if let Some(value) = self.boot_rom.as_ref().and_then(|boot_rom| boot_rom.read(address)) {
+            return value;
+        }
+
+        match address {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.read(address),
+            0x8000..=0x9FFF => self.ppu.read_vram(address),
+            0xC000..=0xFDFF => self.wram[self.wram_offset(address)],
+            0xFE00..=0xFE9F => self.ppu.read_oam(address),
+            joypad::JOYP_ADDRESS => match &self.sgb {
+                Some(sgb) => sgb.read_joypad(&self.joypad),
+                None => self.joypad.read_register(),
+            },
+            SB_REGISTER_ADDRESS => self.serial.get_data(),
+            SC_REGISTER_ADDRESS => self.serial.get_control(),
+            ppu::LCDC_ADDRESS..=ppu::LY_ADDRESS
+            | ppu::LYC_ADDRESS
+            | ppu::BGP_ADDRESS..=ppu::WX_ADDRESS
+            | ppu::VBK_ADDRESS
+            | ppu::BCPS_ADDRESS..=ppu::OCPD_ADDRESS => self.ppu.read_register(address),
+            timer::DIV_ADDRESS..=timer::TAC_ADDRESS => self.timer.read_register(address),
+            KEY1_REGISTER_ADDRESS if self.cgb_mode => {
+                let mut value = KEY1_UNUSED_MASK;
+                bitwise::assign_bit(&mut value, KEY1_CURRENT_SPEED, self.double_speed);
+                bitwise::assign_bit(&mut value, KEY1_PREPARE_SWITCH, self.speed_switch_armed);
+                value
+            }
+            KEY1_REGISTER_ADDRESS => 0xFF,
+            // KEY0 is only writable by the boot ROM and can't be read back.
+            compatibility::KEY0_ADDRESS => 0xFF,
+            boot_rom::BANK_ADDRESS => 0xFF,
+            hdma::HDMA1_ADDRESS..=hdma::HDMA5_ADDRESS if self.cgb_mode => self.hdma.read_register(address),
+            hdma::HDMA1_ADDRESS..=hdma::HDMA5_ADDRESS => 0xFF,
+            SVBK_REGISTER_ADDRESS if self.cgb_mode => 0xF8 | self.wram_bank,
+            SVBK_REGISTER_ADDRESS => 0xFF,
+            _ => self.memory[address as usize],
+        }
     }
 
     pub fn set_memory_8bit(&mut self, address: u16, value: u8) {
-        self.memory[address as usize] = value;
+        match address {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.write(address, value),
+            0x8000..=0x9FFF => self.ppu.write_vram(address, value),
+            0xC000..=0xFDFF => self.wram[self.wram_offset(address)] = value,
+            0xFE00..=0xFE9F => self.ppu.write_oam(address, value),
+            joypad::JOYP_ADDRESS => {
+                self.joypad.write_register(value);
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value);
+                }
+            }
+            SB_REGISTER_ADDRESS => self.serial.set_data(value),
+            SC_REGISTER_ADDRESS => self.serial.set_control(value),
+            DMA_REGISTER_ADDRESS => {
+                self.memory[address as usize] = value;
+                self.oam_dma(value);
+            }
+            ppu::LCDC_ADDRESS..=ppu::LY_ADDRESS
+            | ppu::LYC_ADDRESS
+            | ppu::BGP_ADDRESS..=ppu::WX_ADDRESS
+            | ppu::VBK_ADDRESS
+            | ppu::BCPS_ADDRESS..=ppu::OCPD_ADDRESS => {
+                let interrupts = self.ppu.write_register(address, value);
+                self.memory[IF_REGISTER_ADDRESS] |= interrupts;
+            }
+            timer::DIV_ADDRESS..=timer::TAC_ADDRESS => {
+                if self.timer.write_register(address, value) {
+                    self.request_interrupt(Interrupt::Timer);
+                }
+            }
+            KEY1_REGISTER_ADDRESS if self.cgb_mode => {
+                self.speed_switch_armed = bitwise::get_bit(value, KEY1_PREPARE_SWITCH);
+            }
+            KEY1_REGISTER_ADDRESS => {}
+            compatibility::KEY0_ADDRESS if self.model.is_cgb() && self.boot_rom.is_some() => self.set_key0(value),
+            compatibility::KEY0_ADDRESS => {}
+            boot_rom::BANK_ADDRESS => {
+                if value != 0 {
+                    self.boot_rom = None;
+                }
+            }
+            hdma::HDMA1_ADDRESS..=hdma::HDMA5_ADDRESS if self.cgb_mode => {
+                match self.hdma.write_register(address, value) {
+                    Some(hdma::Request::General) => self.general_dma(),
+                    Some(hdma::Request::HBlank) => self.start_hblank_dma(),
+                    _ => {}
+                }
+            }
+            hdma::HDMA1_ADDRESS..=hdma::HDMA5_ADDRESS => {}
+            SVBK_REGISTER_ADDRESS if self.cgb_mode => self.wram_bank = (value & 0x07).max(1),
+            _ => self.memory[address as usize] = value,
+        }
     }
 
     pub fn get_memory_16bit(&self, address: u16) -> u16 {
@@ -252,46 +701,135 @@ impl CPU {
         self.ime_flag = false;
     }
 
-    fn interrupts_enabled(&self) -> bool {
-        self.memory[IE_REGISTER_ADDRESS] & (0xF) != 0
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    /// Execute STOP. DIV is reset either way. On the CGB with a speed switch
+    /// prepared through KEY1 this switches between normal and double speed,
+    /// stalling the CPU while the clock settles; otherwise the CPU stops
+    /// until a joypad interrupt is requested.
+    pub fn stop(&mut self) {
+        if self.timer.reset_divider() {
+            self.request_interrupt(Interrupt::Timer);
+        }
+
+        if self.cgb_mode && self.speed_switch_armed {
+            self.double_speed = !self.double_speed;
+            self.speed_switch_armed = false;
+            self.add_cycles(SPEED_SWITCH_CYCLES);
+            self.stall_cycles += SPEED_SWITCH_CYCLES;
+        } else {
+            self.stopped = true;
+        }
+    }
+
+    /// Whether the CPU can't resume by itself: halted with every interrupt
+    /// disabled, or stopped until a button is pressed.
+    pub fn is_locked(&self) -> bool {
+        let halted_for_good = self.halted && self.memory[IE_REGISTER_ADDRESS] & 0x1F == 0;
+        let waiting_for_button = self.stopped && self.memory[IF_REGISTER_ADDRESS] & Interrupt::Joypad.mask() == 0;
+        halted_for_good || waiting_for_button
+    }
+
+    fn wake_from_stop(&mut self) {
+        if self.memory[IF_REGISTER_ADDRESS] & Interrupt::Joypad.mask() != 0 {
+            self.stopped = false;
+        }
+    }
+
+    fn pending_interrupts(&self) -> u8 {
+        self.memory[IE_REGISTER_ADDRESS] & self.memory[IF_REGISTER_ADDRESS] & 0x1F
     }
 
-    fn active_interrupt(&self) -> Interrupt {
-        if (self.memory[IE_REGISTER_ADDRESS] & 1) != 0 {
-            Interrupt::VBLank
-        } else if (self.memory[IE_REGISTER_ADDRESS] & 2) != 0 {
-            Interrupt::LCDStatus
-        } else if (self.memory[IE_REGISTER_ADDRESS] & 4) != 0 {
-            Interrupt::Timer
-        } else if (self.memory[IE_REGISTER_ADDRESS] & 8) != 0 {
-            Interrupt::Serial
+    /// Offset into `wram` for 0xC000-0xDFFF and its echo at 0xE000-0xFDFF.
+    /// On the CGB, 0xD000-0xDFFF maps the bank selected by SVBK.
+    fn wram_offset(&self, address: u16) -> usize {
+        let offset = (address as usize - 0xC000) % (2 * WRAM_BANK_SIZE);
+        if offset < WRAM_BANK_SIZE {
+            offset
         } else {
-            Interrupt::Joypad
+            let bank = if self.cgb_mode { self.wram_bank as usize } else { 1 };
+            bank * WRAM_BANK_SIZE + offset - WRAM_BANK_SIZE
         }
     }
 
-    fn handle_vblank_interrupt(&mut self) {
-        // TODO -> implement vblank interrupt
-        todo!("VBLANK INTERRUPT");
+    /// Copy 160 bytes from `source` * 0x100 into OAM. The transfer is done
+    /// at once rather than over 160 machine cycles.
+    fn oam_dma(&mut self, source: u8) {
+        let source = (source as u16) << 8;
+        for offset in 0..OAM_DMA_LENGTH {
+            let value = self.get_memory_8bit(source + offset);
+            self.ppu.write_oam(0xFE00 + offset, value);
+        }
     }
 
-    fn handle_lcdstatus_interrupt(&mut self) {
-        // TODO -> implement lcd status interrupt
-        todo!("LCD STATUS INTERRUPT");
+    /// Copy every block of a general purpose VRAM DMA. The CPU is halted
+    /// until it completes, which is charged to the current instruction.
+    fn general_dma(&mut self) {
+        while self.vram_dma_block() {
+            self.add_cycles(self.vram_dma_block_cycles());
+        }
     }
 
-    fn handle_timer_interrupt(&mut self) {
-        // TODO -> implement timer interrupt
-        todo!("TIMER INTERRUPT");
+    /// Started during HBlank, or with the LCD off, an HBlank DMA copies its
+    /// first block right away rather than at the next HBlank.
+    fn start_hblank_dma(&mut self) {
+        let drawing = self.ppu.lcd_enabled() && self.ppu.mode() != ppu::Mode::HBlank;
+        if !drawing && self.vram_dma_block() {
+            self.add_cycles(self.vram_dma_block_cycles());
+        }
     }
 
-    fn handle_serial_interrupt(&mut self) {
-        // TODO -> implement serial interrupt
-        todo!("SERIAL INTERRUPT");
+    /// Copy the next VRAM DMA block, if any. Returns `true` if one was copied.
+    fn vram_dma_block(&mut self) -> bool {
+        let Some((source, destination)) = self.hdma.next_block() else {
+            return false;
+        };
+        for offset in 0..hdma::BLOCK_SIZE {
+            let value = self.get_memory_8bit(source.wrapping_add(offset));
+            self.ppu.write_vram(destination + offset, value);
+        }
+        true
     }
 
-    fn handle_joypad_interrupt(&mut self) {
-        // TODO -> implement joypad interrupt
-        todo!("JOYPAD INTERRUPT");
+    fn vram_dma_block_cycles(&self) -> u32 {
+        if self.double_speed { 2 * VRAM_DMA_BLOCK_CYCLES } else { VRAM_DMA_BLOCK_CYCLES }
+    }
+
+    /// Advance the hardware by `cycles` CPU cycles. The timer and serial port
+    /// are clocked by the CPU, while the PPU and the cartridge clock always
+    /// run at normal speed.
+    /// Returns the elapsed normal-speed cycles.
+    fn tick(&mut self, cycles: u32) -> u32 {
+        if self.timer.step(cycles - self.stall_cycles) {
+            self.request_interrupt(Interrupt::Timer);
+        }
+
+        if self.serial.step(cycles) {
+            self.request_interrupt(Interrupt::Serial);
+        }
+
+        let mut elapsed = if self.double_speed { cycles / 2 } else { cycles };
+        self.cartridge.step(elapsed);
+        let interrupts = self.ppu.step(elapsed);
+        self.memory[IF_REGISTER_ADDRESS] |= interrupts;
+        if interrupts & Interrupt::VBLank.mask() != 0 {
+            if let Some(sgb) = &mut self.sgb {
+                sgb.vblank(&self.ppu);
+            }
+        }
+
+        // HBlank DMA copies a block at the start of each HBlank, halting the
+        // CPU while the rest of the hardware keeps running.
+        let hblanks = self.ppu.take_hblanks();
+        if self.hdma.hblank_active {
+            let blocks = (0..hblanks).filter(|_| self.vram_dma_block()).count() as u32;
+            if blocks > 0 {
+                self.stall_cycles = 0;
+                elapsed += self.tick(blocks * self.vram_dma_block_cycles());
+            }
+        }
+        elapsed
     }
 }
@@ -0,0 +1,66 @@
+use crate::cpu::CPU;
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// Watches the machine run, for tracing and debugging tools. `Emulator::step`
+/// calls every hook in `Emulator::debug_hooks`; each method does nothing
+/// unless overridden.
+pub trait DebugHook {
+    /// Before each instruction, with PC pointing at it.
+    fn before_instruction(&mut self, _cpu: &CPU) {}
+
+    /// When a frame has been drawn, at the start of VBlank.
+    fn frame_done(&mut self, _cpu: &CPU) {}
+}
+
+/// Logs the registers and the four bytes at PC before every instruction, one
+/// line each, in the format Gameboy Doctor compares against. Clones share
+/// the same output, so one handle can be given to the emulator and another
+/// kept to check for write errors.
+#[derive(Clone)]
+pub struct Trace {
+    output: Rc<RefCell<TraceOutput>>,
+}
+
+struct TraceOutput {
+    writer: Box<dyn Write>,
+    /// The first write that failed, after which tracing stops.
+    error: Option<io::Error>,
+}
+
+impl Trace {
+    pub fn new(writer: impl Write + 'static) -> Self {
+        Trace { output: Rc::new(RefCell::new(TraceOutput { writer: Box::new(writer), error: None })) }
+    }
+
+    /// Flush the output, reporting the first write that failed.
+    pub fn finish(&self) -> io::Result<()> {
+        let mut output = self.output.borrow_mut();
+        match output.error.take() {
+            Some(error) => Err(error),
+            None => output.writer.flush(),
+        }
+    }
+
+    pub fn line(cpu: &CPU) -> String {
+        let [a, f, b, c, d, e, h, l] = cpu.registers;
+        let pc = cpu.get_pc();
+        let memory = [0, 1, 2, 3].map(|offset| cpu.get_memory_8bit(pc.wrapping_add(offset)));
+        format!(
+            "A:{:02X} F:{:02X} B:{:02X} C:{:02X} D:{:02X} E:{:02X} H:{:02X} L:{:02X} SP:{:04X} PC:{:04X} PCMEM:{:02X},{:02X},{:02X},{:02X}",
+            a, f, b, c, d, e, h, l, cpu.get_sp(), pc, memory[0], memory[1], memory[2], memory[3]
+        )
+    }
+}
+
+impl DebugHook for Trace {
+    fn before_instruction(&mut self, cpu: &CPU) {
+        let mut output = self.output.borrow_mut();
+        if output.error.is_none() {
+            if let Err(error) = writeln!(output.writer, "{}", Trace::line(cpu)) {
+                output.error = Some(error);
+            }
+        }
+    }
+}
@@ -2,6 +2,7 @@ use crate::cpu::Register16bit;
 use crate::cpu::Register8bit;
 use crate::cpu::CPU;
 
+use crate::table::CYCLES;
 use crate::table::INSTRUCTIONS;
 
 #[derive(Clone, Copy)]
@@ -154,6 +155,7 @@ impl Instruction {
             }
             Instruction::Prefix => {
                 let opcode = cpu.fetch_next_8bits_pc() as usize;
+                cpu.add_cycles(CYCLES[opcode + 256] as u32);
                 INSTRUCTIONS[opcode + 256].execute(cpu);
             }
             Instruction::Invalid => {
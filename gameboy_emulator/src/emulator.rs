@@ -1,20 +1,283 @@
+use crate::bess;
+use crate::boot_rom::BootRom;
+use crate::builder::EmulatorBuilder;
 use crate::cpu::CPU;
+use crate::debug::DebugHook;
+use crate::model::Model;
+use crate::png;
+use crate::ppu;
+use crate::rewind::RewindBuffer;
+use crate::screenshot::Screenshot;
+use crate::slot::Slot;
+use crate::state::{self, StateReader, StateWriter};
 use std::fs;
-use std::path::Path;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Why one of the `run_*` methods returned.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    /// A new frame was drawn: the PPU entered VBlank.
+    FrameDone,
+    /// A frame's worth of cycles went by with the LCD off.
+    LcdOff,
+    /// The requested number of cycles ran.
+    CyclesDone,
+    /// The `run_until` condition held.
+    ConditionMet,
+    /// The CPU can't resume by itself, so the condition would never hold.
+    Locked,
+}
+
+/// The rate audio is produced at unless configured otherwise.
+pub const DEFAULT_SAMPLE_RATE: u32 = 48_000;
 
 pub struct Emulator {
     pub cpu: Box<CPU>,
+    /// Where the cartridge came from, so save slots can go next to it.
+    pub rom_path: Option<PathBuf>,
+    /// Where save slots go instead of next to the ROM.
+    pub save_directory: Option<PathBuf>,
+    /// Snapshots for `rewind`, when enabled.
+    pub rewind_buffer: Option<RewindBuffer>,
+    /// Samples per second of the audio output. Nothing makes sound until
+    /// there is an APU, but recordings already keep a track at this rate.
+    pub sample_rate: u32,
+    pub debug_hooks: Vec<Box<dyn DebugHook>>,
 }
 
 impl Default for Emulator {
     fn default() -> Self {
-        Emulator { cpu: CPU::new() }
+        Emulator {
+            cpu: CPU::new(),
+            rom_path: None,
+            save_directory: None,
+            rewind_buffer: None,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            debug_hooks: Vec::new(),
+        }
     }
 }
 
 impl Emulator {
+    /// An emulator of the given model, whatever cartridge gets inserted.
+    pub fn new(model: Model) -> Self {
+        let mut emulator = Emulator::default();
+        emulator.cpu.set_model(model);
+        emulator
+    }
+
+    /// Configure an emulator step by step, reporting errors instead of
+    /// panicking.
+    pub fn builder() -> EmulatorBuilder {
+        EmulatorBuilder::new()
+    }
+
     pub fn init_rom(&mut self, rom_path: &Path) {
         let rom_bytes = fs::read(rom_path).expect("Error reading rom");
         self.cpu.load_rom(&rom_bytes);
+        self.rom_path = Some(rom_path.to_path_buf());
+    }
+
+    /// Run the boot ROM at `boot_rom_path` before the cartridge, instead of
+    /// skipping straight to it.
+    pub fn init_boot_rom(&mut self, boot_rom_path: &Path) -> io::Result<()> {
+        let bytes = fs::read(boot_rom_path)?;
+        let boot_rom = BootRom::new(&bytes).ok_or_else(|| {
+            io::Error::new(ErrorKind::InvalidData, "a boot ROM is 256 bytes (DMG) or 2304 bytes (CGB) long")
+        })?;
+        self.cpu.load_boot_rom(boot_rom);
+        Ok(())
+    }
+
+    /// Execute one instruction, or wait out one step of HALT or STOP, calling
+    /// the debug hooks and taking a rewind snapshot if a frame ended. Returns
+    /// the elapsed cycles, at normal speed.
+    pub fn step(&mut self) -> u32 {
+        for hook in &mut self.debug_hooks {
+            hook.before_instruction(&self.cpu);
+        }
+        let frame = self.cpu.ppu.frame_count();
+        let cycles = self.cpu.execute_instruction();
+        if self.cpu.ppu.frame_count() != frame {
+            for hook in &mut self.debug_hooks {
+                hook.frame_done(&self.cpu);
+            }
+            self.record_rewind();
+        }
+        cycles
+    }
+
+    /// Run until the next VBlank, or for the length of a frame while the LCD
+    /// is off.
+    pub fn run_frame(&mut self) -> StopReason {
+        let frame = self.cpu.ppu.frame_count();
+        let mut cycles = 0;
+        while self.cpu.ppu.frame_count() == frame {
+            if !self.cpu.ppu.lcd_enabled() && cycles >= ppu::DOTS_PER_FRAME as u64 {
+                return StopReason::LcdOff;
+            }
+            cycles += self.step() as u64;
+        }
+        StopReason::FrameDone
+    }
+
+    /// Run for at least `cycles` normal-speed cycles; the last instruction
+    /// may go past.
+    pub fn run_cycles(&mut self, cycles: u64) -> StopReason {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            elapsed += self.step() as u64;
+        }
+        StopReason::CyclesDone
+    }
+
+    /// Run until `condition` holds, checking it before every instruction, or
+    /// until the CPU locks up for good.
+    pub fn run_until(&mut self, mut condition: impl FnMut(&Emulator) -> bool) -> StopReason {
+        loop {
+            if condition(self) {
+                return StopReason::ConditionMet;
+            }
+            if self.cpu.is_locked() {
+                return StopReason::Locked;
+            }
+            self.step();
+        }
+    }
+
+    /// Snapshot the whole machine, cartridge RAM included, in the versioned
+    /// format of `state`.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        self.cpu.save_state(&mut state);
+        state.into_bytes()
+    }
+
+    /// Restore a snapshot taken by `save_state`, possibly by an earlier
+    /// release, with the same cartridge inserted. If the state can't be
+    /// loaded the machine is left as it was.
+    pub fn load_state(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let mut reader = StateReader::new(bytes)?;
+        self.restore_on_error(|cpu| {
+            cpu.load_state(&mut reader)?;
+            if reader.is_finished() { Ok(()) } else { Err(state::invalid("save state has trailing data")) }
+        })
+    }
+
+    /// Write the current frame to `path` as PNG, as displayed.
+    pub fn screenshot(&self, path: &Path) -> io::Result<()> {
+        self.screenshot_with(path, &Screenshot::default())
+    }
+
+    /// Write the current frame to `path` as PNG, in another DMG palette or
+    /// scaled up.
+    pub fn screenshot_with(&self, path: &Path, screenshot: &Screenshot) -> io::Result<()> {
+        screenshot.write(path, &self.cpu)
+    }
+
+    /// Save the machine in the BESS format, to be loaded by other emulators.
+    pub fn export_bess(&self) -> Vec<u8> {
+        bess::export(&self.cpu)
+    }
+
+    /// Load a BESS state saved by another emulator for the same game. If the
+    /// state can't be loaded the machine is left as it was.
+    pub fn import_bess(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.restore_on_error(|cpu| bess::import(cpu, bytes))
+    }
+
+    /// CRC-32 of the whole ROM, which tells games apart even when their
+    /// headers match.
+    pub fn rom_checksum(&self) -> u32 {
+        png::crc32(&self.cpu.cartridge.rom)
+    }
+
+    /// Save the machine in slot `number`, next to the ROM or in the save
+    /// directory, with a thumbnail of the current frame.
+    pub fn save_slot(&self, number: u8) -> io::Result<()> {
+        let slot = Slot {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs()),
+            rom_checksum: self.rom_checksum(),
+            thumbnail: self.cpu.ppu.frame().to_vec(),
+            state: self.save_state(),
+        };
+        slot.write(&self.slot_path(number)?)
+    }
+
+    /// What slot `number` holds, to show its thumbnail and time.
+    pub fn read_slot(&self, number: u8) -> io::Result<Slot> {
+        Slot::read(&self.slot_path(number)?)
+    }
+
+    /// Load slot `number`. A slot saved with another ROM is refused and the
+    /// machine is left as it was.
+    pub fn load_slot(&mut self, number: u8) -> io::Result<()> {
+        let slot = self.read_slot(number)?;
+        if slot.rom_checksum != self.rom_checksum() {
+            return Err(state::invalid("save state is for another game"));
+        }
+        self.load_state(&slot.state)
+    }
+
+    /// In the save directory, slots are named after the ROM file, or the
+    /// game's title for a ROM given as bytes.
+    fn slot_path(&self, number: u8) -> io::Result<PathBuf> {
+        let base = match (&self.save_directory, &self.rom_path) {
+            (Some(directory), Some(rom_path)) => directory.join(rom_path.file_name().unwrap_or_default()),
+            (Some(directory), None) => {
+                let title = self.cpu.cartridge.header.title.replace(['/', '\\'], "_");
+                directory.join(if title.is_empty() { "untitled" } else { &title })
+            }
+            (None, Some(rom_path)) => rom_path.clone(),
+            (None, None) => {
+                return Err(io::Error::new(
+                    ErrorKind::NotFound,
+                    "save slots need a ROM loaded from a file or a save directory",
+                ))
+            }
+        };
+        Ok(Slot::path(&base, number))
+    }
+
+    /// Keep a snapshot every `interval` frames for `rewind`, in at most
+    /// `budget` bytes, starting with the current frame.
+    pub fn enable_rewind(&mut self, interval: u64, budget: usize) {
+        self.rewind_buffer = Some(RewindBuffer::new(interval, budget));
+        self.record_rewind();
+    }
+
+    /// Take a rewind snapshot if one is due. `step` calls this whenever a
+    /// frame ends.
+    pub fn record_rewind(&mut self) {
+        let frame = self.cpu.ppu.frame_count();
+        if self.rewind_buffer.as_ref().is_some_and(|buffer| buffer.is_due(frame)) {
+            let state = self.save_state();
+            if let Some(buffer) = &mut self.rewind_buffer {
+                buffer.push(frame, state);
+            }
+        }
+    }
+
+    /// Go back `frames` frames, to the last snapshot taken by then or the
+    /// oldest one kept. Returns how many frames were actually undone.
+    pub fn rewind(&mut self, frames: u64) -> u64 {
+        let current = self.cpu.ppu.frame_count();
+        let Some(buffer) = &mut self.rewind_buffer else { return 0 };
+        let Some(state) = buffer.pop_to(current.saturating_sub(frames)) else { return 0 };
+        self.load_state(&state).expect("rewind snapshots are own save states");
+        current.saturating_sub(self.cpu.ppu.frame_count())
+    }
+
+    /// Run `load`, putting the machine back as it was if it fails halfway.
+    fn restore_on_error(&mut self, load: impl FnOnce(&mut CPU) -> io::Result<()>) -> io::Result<()> {
+        let backup = self.save_state();
+        let result = load(&mut self.cpu);
+        if result.is_err() {
+            let mut backup = StateReader::new(&backup).expect("own save state");
+            self.cpu.load_state(&mut backup).expect("own save state");
+        }
+        result
     }
 }
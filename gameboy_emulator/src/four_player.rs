@@ -0,0 +1,280 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::Emulator;
+use crate::serial::SerialEndpoint;
+
+pub const MAX_PLAYERS: usize = 4;
+
+const PING_HEADER: u8 = 0xFE;
+const ACK: u8 = 0x88;
+/// Sent by player 1 for a whole ping packet to start the transmission phase.
+const START_TRANSMISSION: u8 = 0xAA;
+/// Sent by the adapter for a whole packet when switching phases.
+const TRANSITION: u8 = 0xCC;
+/// Sent by player 1 for a whole packet to go back to the ping phase.
+const RESTART: u8 = 0xFF;
+const PING_PACKET_SIZE: usize = 4;
+
+/// The adapter's byte rate is not documented precisely; these give roughly
+/// 250 ping bytes a second and let RATE slow the transmission phase down.
+const PING_INTERVAL_CYCLES: u64 = 16384;
+const TRANSMISSION_BASE_CYCLES: u64 = 4096;
+const TRANSMISSION_RATE_CYCLES: u64 = 1024;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Ping,
+    Transition,
+    Transmission,
+}
+
+/// The adapter itself, which drives the clock of every connected Game Boy.
+struct Hub {
+    /// SB of each player currently waiting on the external clock.
+    waiting: [Option<u8>; MAX_PLAYERS],
+    /// Byte clocked into each player, not yet picked up.
+    delivered: [Option<u8>; MAX_PLAYERS],
+    phase: Phase,
+    position: usize,
+    connected: [bool; MAX_PLAYERS],
+    replies: [[u8; PING_PACKET_SIZE]; MAX_PLAYERS],
+    rate: u8,
+    size: usize,
+    /// Packets received from every player during the current round.
+    incoming: Vec<u8>,
+    /// Packets received during the previous round, sent to everyone.
+    outgoing: Vec<u8>,
+}
+
+impl Hub {
+    fn new() -> Self {
+        Hub {
+            waiting: [None; MAX_PLAYERS],
+            delivered: [None; MAX_PLAYERS],
+            phase: Phase::Ping,
+            position: 0,
+            connected: [false; MAX_PLAYERS],
+            replies: [[0; PING_PACKET_SIZE]; MAX_PLAYERS],
+            rate: 0,
+            size: 1,
+            incoming: Vec::new(),
+            outgoing: Vec::new(),
+        }
+    }
+
+    fn interval(&self) -> u64 {
+        match self.phase {
+            Phase::Ping | Phase::Transition => PING_INTERVAL_CYCLES,
+            Phase::Transmission => {
+                TRANSMISSION_BASE_CYCLES + (self.rate & 0x0F) as u64 * TRANSMISSION_RATE_CYCLES
+            }
+        }
+    }
+
+    fn packet_size(&self) -> usize {
+        match self.phase {
+            Phase::Ping | Phase::Transition => PING_PACKET_SIZE,
+            Phase::Transmission => self.size * MAX_PLAYERS,
+        }
+    }
+
+    fn connected_mask(&self) -> u8 {
+        self.connected
+            .iter()
+            .enumerate()
+            .filter(|(_, &connected)| connected)
+            .fold(0, |mask, (player, _)| mask | (0x10 << player))
+    }
+
+    /// The byte the adapter shifts out to `player` at the current position.
+    fn byte_for(&self, player: usize) -> u8 {
+        match self.phase {
+            Phase::Ping if self.position == 0 => PING_HEADER,
+            Phase::Ping => self.connected_mask() | (player as u8 + 1),
+            Phase::Transition => TRANSITION,
+            Phase::Transmission => self.outgoing[self.position],
+        }
+    }
+
+    /// Clock one byte to and from every player.
+    fn clock(&mut self) {
+        let mut received = [0xFF; MAX_PLAYERS];
+        for (player, received) in received.iter_mut().enumerate() {
+            if let Some(byte) = self.waiting[player].take() {
+                *received = byte;
+                self.delivered[player] = Some(self.byte_for(player));
+            }
+        }
+
+        match self.phase {
+            Phase::Ping => {
+                for (replies, &byte) in self.replies.iter_mut().zip(received.iter()) {
+                    replies[self.position] = byte;
+                }
+            }
+            Phase::Transition => {}
+            Phase::Transmission => {
+                if self.position < self.size {
+                    for (player, &byte) in received.iter().enumerate() {
+                        let byte = if self.connected[player] { byte } else { 0x00 };
+                        self.incoming[player * self.size + self.position] = byte;
+                    }
+                }
+            }
+        }
+
+        self.position += 1;
+        if self.position == self.packet_size() {
+            self.position = 0;
+            self.end_packet();
+        }
+    }
+
+    fn end_packet(&mut self) {
+        match self.phase {
+            Phase::Ping => {
+                if self.replies[0].iter().all(|&byte| byte == START_TRANSMISSION) {
+                    self.phase = Phase::Transition;
+                    return;
+                }
+                for player in 0..MAX_PLAYERS {
+                    let [ack1, ack2, _, _] = self.replies[player];
+                    self.connected[player] = ack1 == ACK && ack2 == ACK;
+                }
+                if self.connected[0] {
+                    self.rate = self.replies[0][2];
+                    self.size = (self.replies[0][3] as usize).max(1);
+                }
+            }
+            Phase::Transition => {
+                self.phase = Phase::Transmission;
+                self.incoming = vec![0; self.size * MAX_PLAYERS];
+                self.outgoing = vec![0; self.size * MAX_PLAYERS];
+            }
+            Phase::Transmission => {
+                let restart = self.incoming[..self.size].iter().all(|&byte| byte == RESTART);
+                self.outgoing = std::mem::replace(&mut self.incoming, vec![0; self.size * MAX_PLAYERS]);
+                if restart {
+                    self.phase = Phase::Ping;
+                }
+            }
+        }
+    }
+}
+
+/// The adapter port a single Game Boy is plugged into.
+pub struct AdapterPort {
+    hub: Rc<RefCell<Hub>>,
+    player: usize,
+}
+
+impl SerialEndpoint for AdapterPort {
+    fn transfer(&mut self, _outgoing: u8) -> u8 {
+        // The adapter always drives the clock; a Game Boy trying to do the
+        // same reads nothing back.
+        0xFF
+    }
+
+    fn poll_external(&mut self, outgoing: u8) -> Option<u8> {
+        let mut hub = self.hub.borrow_mut();
+        match hub.delivered[self.player].take() {
+            Some(incoming) => {
+                hub.waiting[self.player] = None;
+                Some(incoming)
+            }
+            None => {
+                hub.waiting[self.player] = Some(outgoing);
+                None
+            }
+        }
+    }
+
+    fn idle(&mut self) {
+        self.hub.borrow_mut().waiting[self.player] = None;
+    }
+}
+
+/// The DMG-07 Four Player Adapter with up to four emulators plugged in.
+///
+/// In the ping phase the adapter sends `FE` followed by three status bytes
+/// (connected players in the high nibble, the receiving player's number in
+/// the low one), and every Game Boy answers `88 88 RATE SIZE`. Once player 1
+/// answers a whole ping packet with `AA`, the adapter sends a packet of `CC`
+/// and enters the transmission phase, where each round it collects SIZE
+/// bytes from every player and sends the previous round's 4 * SIZE bytes back
+/// to all of them. Player 1 returns to the ping phase by sending `FF`.
+///
+/// Like `LinkedPair`, the emulator that is behind always runs next, and the
+/// adapter only clocks a byte once every player has caught up with it.
+pub struct FourPlayerAdapter {
+    pub players: Vec<Emulator>,
+    cycles: Vec<u64>,
+    next_clock: u64,
+    hub: Rc<RefCell<Hub>>,
+}
+
+impl FourPlayerAdapter {
+    pub fn new(mut players: Vec<Emulator>) -> Self {
+        assert!(
+            (1..=MAX_PLAYERS).contains(&players.len()),
+            "the adapter takes between 1 and {MAX_PLAYERS} players"
+        );
+
+        let hub = Rc::new(RefCell::new(Hub::new()));
+        for (player, emulator) in players.iter_mut().enumerate() {
+            emulator.cpu.serial.connect(Box::new(AdapterPort { hub: hub.clone(), player }));
+        }
+
+        let next_clock = hub.borrow().interval();
+        let cycles = vec![0; players.len()];
+        FourPlayerAdapter { players, cycles, next_clock, hub }
+    }
+
+    /// Unplug every player and hand the emulators back.
+    pub fn disconnect(mut self) -> Vec<Emulator> {
+        for emulator in self.players.iter_mut() {
+            emulator.cpu.serial.disconnect();
+        }
+        self.players
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.hub.borrow().phase
+    }
+
+    /// Cycles run so far by the given player (0 to 3).
+    pub fn cycles(&self, player: usize) -> u64 {
+        self.cycles[player]
+    }
+
+    /// Execute one instruction on whichever player is behind, clocking the
+    /// adapter first if every player has reached its next byte. Returns the
+    /// player that ran.
+    pub fn step(&mut self) -> usize {
+        let (player, &behind) = self
+            .cycles
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &cycles)| cycles)
+            .expect("at least one player");
+
+        if behind >= self.next_clock {
+            let mut hub = self.hub.borrow_mut();
+            hub.clock();
+            self.next_clock += hub.interval();
+        }
+
+        let cycles = self.players[player].step();
+        self.cycles[player] += cycles as u64;
+        player
+    }
+
+    /// Run every player until each has advanced by at least `cycles` cycles.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let target = self.cycles.iter().copied().max().unwrap_or(0) + cycles;
+        while self.cycles.iter().any(|&elapsed| elapsed < target) {
+            self.step();
+        }
+    }
+}
@@ -0,0 +1,230 @@
+use crate::cpu::CPU;
+use crate::ppu::{color_to_rgb, DOTS_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::screenshot::DmgPalette;
+use crate::sgb::{SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// The CPU clock; a frame lasts `DOTS_PER_FRAME` of its cycles.
+const CYCLES_PER_SECOND: u64 = 4_194_304;
+const MAX_CODE_SIZE: u8 = 12;
+/// The first code that can't be added, so the table is cleared there.
+const MAX_CODE: u16 = 4095;
+const MAX_SUB_BLOCK: usize = 255;
+
+/// Records frames as an animated GIF that loops forever. DMG games are
+/// drawn with four colors from a `DmgPalette`; color games keep theirs,
+/// reduced to 8 bits per pixel when a frame has more than 256. On the SGB
+/// the whole 256x224 picture is recorded, border included.
+///
+/// Browsers play delays under 2/100 s slowly, so keeping every frame makes
+/// the GIF look slower than the game; a frame skip of 1 or more avoids that.
+pub struct GifRecorder {
+    writer: Box<dyn Write>,
+    palette: DmgPalette,
+    /// Frames left out after each captured one.
+    frame_skip: u32,
+    /// Frames seen, captured or not.
+    frames: u64,
+    captured: u64,
+    /// Whether the header is written, which waits for the first frame as
+    /// its size depends on the model.
+    started: bool,
+}
+
+impl GifRecorder {
+    /// Get ready to write frames to `writer`.
+    pub fn new(writer: impl Write + 'static, palette: DmgPalette, frame_skip: u32) -> io::Result<Self> {
+        Ok(GifRecorder { writer: Box::new(writer), palette, frame_skip, frames: 0, captured: 0, started: false })
+    }
+
+    fn write_header(&mut self, width: usize, height: usize) -> io::Result<()> {
+        self.writer.write_all(b"GIF89a")?;
+        self.writer.write_all(&(width as u16).to_le_bytes())?;
+        self.writer.write_all(&(height as u16).to_le_bytes())?;
+        // No global color table, each frame has its own; background color 0
+        // and square pixels.
+        self.writer.write_all(&[0x00, 0x00, 0x00])?;
+        // Loop forever.
+        self.writer.write_all(&[0x21, 0xFF, 0x0B])?;
+        self.writer.write_all(b"NETSCAPE2.0")?;
+        self.writer.write_all(&[0x03, 0x01, 0x00, 0x00, 0x00])?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Frames written to the GIF so far.
+    pub fn captured(&self) -> u64 {
+        self.captured
+    }
+
+    /// Offer the frame `cpu` just drew, captured unless it's skipped.
+    pub fn add_frame(&mut self, cpu: &CPU) -> io::Result<()> {
+        let step = self.frame_skip as u64 + 1;
+        let skipped = !self.frames.is_multiple_of(step);
+        self.frames += 1;
+        if skipped {
+            return Ok(());
+        }
+
+        let ppu = &cpu.ppu;
+        let ((width, height), (colors, indices)) = if let Some(sgb) = &cpu.sgb {
+            ((SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT), index_colors(sgb.frame()))
+        } else if ppu.cgb_mode || ppu.dmg_compatibility {
+            ((SCREEN_WIDTH, SCREEN_HEIGHT), index_colors(ppu.frame()))
+        } else {
+            let shades = ppu.shades().iter().map(|&shade| shade & 3).collect();
+            ((SCREEN_WIDTH, SCREEN_HEIGHT), (self.palette.colors().to_vec(), shades))
+        };
+        if !self.started {
+            self.write_header(width, height)?;
+        }
+        // The table has 2^(bits) entries, at least 4 as LZW needs 2 bits.
+        let bits = (colors.len().next_power_of_two().trailing_zeros() as u8).max(2);
+
+        // A frame lasts until the next captured one, rounded to 1/100 s
+        // without drifting.
+        let start = centiseconds(self.captured * step);
+        let delay = centiseconds((self.captured + 1) * step) - start;
+        self.writer.write_all(&[0x21, 0xF9, 0x04, 0x00])?;
+        self.writer.write_all(&(delay as u16).to_le_bytes())?;
+        self.writer.write_all(&[0x00, 0x00])?;
+
+        self.writer.write_all(&[0x2C, 0x00, 0x00, 0x00, 0x00])?;
+        self.writer.write_all(&(width as u16).to_le_bytes())?;
+        self.writer.write_all(&(height as u16).to_le_bytes())?;
+        // A local color table of 2^bits entries.
+        self.writer.write_all(&[0x80 | (bits - 1)])?;
+        for index in 0..1 << bits {
+            self.writer.write_all(&colors.get(index).copied().unwrap_or_default())?;
+        }
+
+        self.writer.write_all(&[bits])?;
+        for block in lzw_encode(&indices, bits).chunks(MAX_SUB_BLOCK) {
+            self.writer.write_all(&[block.len() as u8])?;
+            self.writer.write_all(block)?;
+        }
+        self.writer.write_all(&[0x00])?;
+        self.captured += 1;
+        Ok(())
+    }
+
+    /// End the GIF and flush it.
+    pub fn finish(mut self) -> io::Result<()> {
+        if !self.started {
+            self.write_header(SCREEN_WIDTH, SCREEN_HEIGHT)?;
+        }
+        self.writer.write_all(&[0x3B])?;
+        self.writer.flush()
+    }
+}
+
+fn centiseconds(frames: u64) -> u64 {
+    (frames * DOTS_PER_FRAME as u64 * 100 + CYCLES_PER_SECOND / 2) / CYCLES_PER_SECOND
+}
+
+/// A color table and each pixel's index into it. Past 256 colors, which
+/// only mid-frame palette changes can cause, colors are cut to 3 bits of red
+/// and green and 2 of blue.
+fn index_colors(frame: &[u16]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let mut table: HashMap<u16, u8> = HashMap::new();
+    let mut colors = Vec::new();
+    let mut indices = Vec::with_capacity(frame.len());
+    for &color in frame {
+        let next = colors.len();
+        if next > 0xFF && !table.contains_key(&color) {
+            return quantize(frame);
+        }
+        let index = *table.entry(color).or_insert_with(|| {
+            colors.push(color_to_rgb(color));
+            next as u8
+        });
+        indices.push(index);
+    }
+    (colors, indices)
+}
+
+fn quantize(frame: &[u16]) -> (Vec<[u8; 3]>, Vec<u8>) {
+    let colors = (0..=0xFFu16)
+        .map(|index| {
+            let expand = |value: u16, bits: u32| (value * 0xFF / ((1 << bits) - 1)) as u8;
+            [expand(index >> 5, 3), expand((index >> 2) & 7, 3), expand(index & 3, 2)]
+        })
+        .collect();
+    let indices = frame
+        .iter()
+        .map(|&color| {
+            let [red, green, blue] = color_to_rgb(color);
+            (red & 0xE0) | ((green & 0xE0) >> 3) | (blue >> 6)
+        })
+        .collect();
+    (colors, indices)
+}
+
+/// Compress `indices`, each under 2^`min_code_size`, with GIF's variable
+/// width LZW, packing the codes from the least significant bit.
+pub fn lzw_encode(indices: &[u8], min_code_size: u8) -> Vec<u8> {
+    let clear = 1u16 << min_code_size;
+    let end = clear + 1;
+    let mut output = BitWriter { bytes: Vec::new(), buffer: 0, bits: 0, code_size: min_code_size + 1 };
+    let mut table: HashMap<(u16, u8), u16> = HashMap::new();
+    let mut next = end + 1;
+
+    output.write(clear, next);
+    let Some((&first, rest)) = indices.split_first() else {
+        output.write(end, next);
+        return output.finish();
+    };
+    let mut prefix = first as u16;
+    for &index in rest {
+        if let Some(&code) = table.get(&(prefix, index)) {
+            prefix = code;
+            continue;
+        }
+        output.write(prefix, next);
+        if next >= MAX_CODE {
+            output.write(clear, next);
+            table.clear();
+            next = end + 1;
+            output.code_size = min_code_size + 1;
+        } else {
+            table.insert((prefix, index), next);
+            next += 1;
+        }
+        prefix = index as u16;
+    }
+    output.write(prefix, next);
+    output.write(end, next);
+    output.finish()
+}
+
+struct BitWriter {
+    bytes: Vec<u8>,
+    buffer: u32,
+    bits: u8,
+    code_size: u8,
+}
+
+impl BitWriter {
+    /// Write `code`, then widen the codes if `next`, the code about to be
+    /// added, no longer fits.
+    fn write(&mut self, code: u16, next: u16) {
+        self.buffer |= (code as u32) << self.bits;
+        self.bits += self.code_size;
+        while self.bits >= 8 {
+            self.bytes.push(self.buffer as u8);
+            self.buffer >>= 8;
+            self.bits -= 8;
+        }
+        if next >= 1 << self.code_size && self.code_size < MAX_CODE_SIZE {
+            self.code_size += 1;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.bytes.push(self.buffer as u8);
+        }
+        self.bytes
+    }
+}
@@ -0,0 +1,120 @@
+use crate::bitwise;
+use crate::state::{StateReader, StateWriter};
+use std::io;
+
+pub const HDMA1_ADDRESS: u16 = 0xFF51;
+pub const HDMA2_ADDRESS: u16 = 0xFF52;
+pub const HDMA3_ADDRESS: u16 = 0xFF53;
+pub const HDMA4_ADDRESS: u16 = 0xFF54;
+pub const HDMA5_ADDRESS: u16 = 0xFF55;
+
+pub const BLOCK_SIZE: u16 = 0x10;
+
+const HDMA5_MODE: usize = 7;
+const HDMA5_LENGTH_MASK: u8 = 0x7F;
+const SOURCE_LOW_MASK: u8 = 0xF0;
+const DESTINATION_HIGH_MASK: u8 = 0x1F;
+const DESTINATION_MASK: u16 = 0x1FF0;
+
+/// What a write to HDMA5 asks for.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Request {
+    /// Copy every block now, with the CPU halted.
+    General,
+    /// Copy one block at the start of each HBlank.
+    HBlank,
+    /// Stop the HBlank transfer in progress.
+    Cancel,
+}
+
+/// The CGB VRAM DMA registers. The source can be anywhere in ROM or RAM and
+/// the destination is always in VRAM; both advance one 16-byte block at a
+/// time.
+#[derive(Default)]
+pub struct Hdma {
+    pub source: u16,
+    /// Offset into the current VRAM bank, 0x0000-0x1FF0.
+    pub destination: u16,
+    /// Blocks left to copy.
+    pub blocks: u8,
+    pub hblank_active: bool,
+}
+
+impl Hdma {
+    /// HDMA1-4 are write-only. HDMA5 reads the blocks left minus one, with
+    /// bit 7 set unless an HBlank transfer is active, so it reads 0xFF once a
+    /// transfer has completed.
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            HDMA5_ADDRESS => {
+                let mut value = self.blocks.wrapping_sub(1) & HDMA5_LENGTH_MASK;
+                bitwise::assign_bit(&mut value, HDMA5_MODE, !self.hblank_active);
+                value
+            }
+            _ => 0xFF,
+        }
+    }
+
+    /// Write a DMA register. Returns the transfer requested by a write to
+    /// HDMA5, which the CPU carries out.
+    pub fn write_register(&mut self, address: u16, value: u8) -> Option<Request> {
+        match address {
+            HDMA1_ADDRESS => self.source = (self.source & 0x00FF) | (value as u16) << 8,
+            HDMA2_ADDRESS => self.source = (self.source & 0xFF00) | (value & SOURCE_LOW_MASK) as u16,
+            HDMA3_ADDRESS => {
+                self.destination = (self.destination & 0x00FF) | ((value & DESTINATION_HIGH_MASK) as u16) << 8
+            }
+            HDMA4_ADDRESS => {
+                self.destination = (self.destination & 0xFF00) | (value & SOURCE_LOW_MASK) as u16
+            }
+            HDMA5_ADDRESS => {
+                let hblank = bitwise::get_bit(value, HDMA5_MODE);
+                if self.hblank_active && !hblank {
+                    self.hblank_active = false;
+                    return Some(Request::Cancel);
+                }
+
+                self.blocks = (value & HDMA5_LENGTH_MASK) + 1;
+                self.hblank_active = hblank;
+                return Some(if hblank { Request::HBlank } else { Request::General });
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Take the next block to copy, as a source address and a VRAM address,
+    /// and advance past it. The transfer ends early if the destination runs
+    /// past the end of VRAM.
+    pub fn next_block(&mut self) -> Option<(u16, u16)> {
+        if self.blocks == 0 {
+            self.hblank_active = false;
+            return None;
+        }
+
+        let block = (self.source, 0x8000 | self.destination);
+        self.source = self.source.wrapping_add(BLOCK_SIZE);
+        self.destination = (self.destination + BLOCK_SIZE) & DESTINATION_MASK;
+        self.blocks -= 1;
+        if self.blocks == 0 || self.destination == 0 {
+            self.blocks = 0;
+            self.hblank_active = false;
+        }
+        Some(block)
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.source);
+        state.write_u16(self.destination);
+        state.write_u8(self.blocks);
+        state.write_bool(self.hblank_active);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.source = state.read_u16()?;
+        self.destination = state.read_u16()? & DESTINATION_MASK;
+        self.blocks = state.read_u8()?;
+        self.hblank_active = state.read_bool()?;
+        Ok(())
+    }
+}
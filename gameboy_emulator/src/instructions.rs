@@ -433,23 +433,27 @@ pub fn jp(cpu: &mut CPU, operand1: Operand16bit) {
 
 pub fn jpnz(cpu: &mut CPU, operand1: Operand16bit) {
     if !cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(4);
         jp(cpu, operand1);
     }
 }
 pub fn jpz(cpu: &mut CPU, operand1: Operand16bit) {
     if cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(4);
         jp(cpu, operand1);
     }
 }
 
 pub fn jpnc(cpu: &mut CPU, operand1: Operand16bit) {
     if !cpu.get_flag(Flag::C) {
+        cpu.add_cycles(4);
         jp(cpu, operand1);
     }
 }
 
 pub fn jpc(cpu: &mut CPU, operand1: Operand16bit) {
     if cpu.get_flag(Flag::C) {
+        cpu.add_cycles(4);
         jp(cpu, operand1);
     }
 }
@@ -466,24 +470,28 @@ pub fn jr(cpu: &mut CPU, operand1: Operand8bit) {
 
 pub fn jrnz(cpu: &mut CPU, operand1: Operand8bit) {
     if !cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(4);
         jr(cpu, operand1);
     }
 }
 
 pub fn jrz(cpu: &mut CPU, operand1: Operand8bit) {
     if cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(4);
         jr(cpu, operand1);
     }
 }
 
 pub fn jrnc(cpu: &mut CPU, operand1: Operand8bit) {
     if !cpu.get_flag(Flag::C) {
+        cpu.add_cycles(4);
         jr(cpu, operand1);
     }
 }
 
 pub fn jrc(cpu: &mut CPU, operand1: Operand8bit) {
     if cpu.get_flag(Flag::C) {
+        cpu.add_cycles(4);
         jr(cpu, operand1);
     }
 }
@@ -495,23 +503,27 @@ pub fn call(cpu: &mut CPU, operand1: Operand16bit) {
 
 pub fn callnz(cpu: &mut CPU, operand1: Operand16bit) {
     if !cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(12);
         call(cpu, operand1);
     }
 }
 pub fn callz(cpu: &mut CPU, operand1: Operand16bit) {
     if cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(12);
         call(cpu, operand1);
     }
 }
 
 pub fn callnc(cpu: &mut CPU, operand1: Operand16bit) {
     if !cpu.get_flag(Flag::C) {
+        cpu.add_cycles(12);
         call(cpu, operand1);
     }
 }
 
 pub fn callc(cpu: &mut CPU, operand1: Operand16bit) {
     if cpu.get_flag(Flag::C) {
+        cpu.add_cycles(12);
         call(cpu, operand1);
     }
 }
@@ -523,23 +535,27 @@ pub fn ret(cpu: &mut CPU) {
 
 pub fn retnz(cpu: &mut CPU) {
     if !cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(12);
         ret(cpu);
     }
 }
 pub fn retz(cpu: &mut CPU) {
     if cpu.get_flag(Flag::Z) {
+        cpu.add_cycles(12);
         ret(cpu);
     }
 }
 
 pub fn retnc(cpu: &mut CPU) {
     if !cpu.get_flag(Flag::C) {
+        cpu.add_cycles(12);
         ret(cpu);
     }
 }
 
 pub fn retc(cpu: &mut CPU) {
     if cpu.get_flag(Flag::C) {
+        cpu.add_cycles(12);
         ret(cpu);
     }
 }
@@ -550,23 +566,20 @@ pub fn rst(cpu: &mut CPU, operand1: Operand16bit) {
 
 pub fn ei(cpu: &mut CPU) {
     cpu.set_ime_flag();
-    println!("Interrupts enabled!");
 }
 
 pub fn di(cpu: &mut CPU) {
     cpu.reset_ime_flag();
-    println!("Interrupts disabled!");
 }
 
 pub fn nop(_: &mut CPU) {}
 
-pub fn stop(_cpu: &mut CPU, _: Operand8bit) {
-    print!("Stop intruction");
-    //todo!("Stop instruction");
+pub fn stop(cpu: &mut CPU, _: Operand8bit) {
+    cpu.stop();
 }
 
-pub fn halt(_cpu: &mut CPU) {
-    todo!("Halt instruction");
+pub fn halt(cpu: &mut CPU) {
+    cpu.halt();
 }
 
 pub fn reti(cpu: &mut CPU) {
@@ -0,0 +1,126 @@
+use crate::bitwise;
+use crate::state::{StateReader, StateWriter};
+use std::io;
+
+pub const JOYP_ADDRESS: u16 = 0xFF00;
+
+const SELECT_DIRECTIONS: usize = 4;
+const SELECT_BUTTONS: usize = 5;
+const SELECT_MASK: u8 = 0x30;
+const UNUSED_MASK: u8 = 0xC0;
+
+/// The eight keys, numbered so that the directions fill the low nibble and
+/// the buttons the high one, each in the bit order JOYP reports them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Button {
+    Right = 0,
+    Left = 1,
+    Up = 2,
+    Down = 3,
+    A = 4,
+    B = 5,
+    Select = 6,
+    Start = 7,
+}
+
+impl Button {
+    pub const ALL: [Button; 8] = [
+        Button::Right,
+        Button::Left,
+        Button::Up,
+        Button::Down,
+        Button::A,
+        Button::B,
+        Button::Select,
+        Button::Start,
+    ];
+
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Button::Right => "right",
+            Button::Left => "left",
+            Button::Up => "up",
+            Button::Down => "down",
+            Button::A => "a",
+            Button::B => "b",
+            Button::Select => "select",
+            Button::Start => "start",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Button::ALL.into_iter().find(|button| button.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// The JOYP register at 0xFF00. The program selects the directions and/or
+/// the buttons through bits 4 and 5 and reads the selected keys back in the
+/// low nibble, where a pressed key reads as 0.
+pub struct Joypad {
+    select: u8,
+    pressed: u8,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad { select: SELECT_MASK, pressed: 0 }
+    }
+}
+
+impl Joypad {
+    pub fn read_register(&self) -> u8 {
+        self.read_register_with(self.pressed)
+    }
+
+    /// JOYP as if the keys held were `pressed`, for the SGB's other joypads.
+    pub fn read_register_with(&self, pressed: u8) -> u8 {
+        let mut keys = 0x0F;
+        if !bitwise::get_bit(self.select, SELECT_DIRECTIONS) {
+            keys &= !(pressed & 0x0F);
+        }
+        if !bitwise::get_bit(self.select, SELECT_BUTTONS) {
+            keys &= !(pressed >> 4);
+        }
+        UNUSED_MASK | self.select | keys
+    }
+
+    pub fn write_register(&mut self, value: u8) {
+        self.select = value & SELECT_MASK;
+    }
+
+    /// The keys currently held, as a mask of `Button` bits.
+    pub fn pressed(&self) -> u8 {
+        self.pressed
+    }
+
+    pub fn is_pressed(&self, button: Button) -> bool {
+        self.pressed & button.mask() != 0
+    }
+
+    /// Press a key. Returns `true` if it was not already held, in which case
+    /// the joypad interrupt should be requested.
+    pub fn press(&mut self, button: Button) -> bool {
+        let newly_pressed = !self.is_pressed(button);
+        self.pressed |= button.mask();
+        newly_pressed
+    }
+
+    pub fn release(&mut self, button: Button) {
+        self.pressed &= !button.mask();
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.select);
+        state.write_u8(self.pressed);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.select = state.read_u8()? & SELECT_MASK;
+        self.pressed = state.read_u8()?;
+        Ok(())
+    }
+}
@@ -1,6 +1,34 @@
+pub mod cartridge;
+pub mod cli;
+pub mod compatibility;
+pub mod compression;
 pub mod emulator;
+pub mod four_player;
+pub mod gif;
+pub mod hdma;
 pub mod dispatch;
 pub mod cpu;
+pub mod debug;
 pub mod bitwise;
+pub mod batch;
+pub mod bess;
+pub mod boot_rom;
+pub mod builder;
 pub mod instructions;
-pub mod table;
\ No newline at end of file
+pub mod joypad;
+pub mod link;
+pub mod model;
+pub mod png;
+pub mod ppu;
+pub mod printer;
+pub mod recording;
+pub mod rewind;
+pub mod screenshot;
+pub mod serial;
+pub mod sgb;
+pub mod slot;
+pub mod state;
+pub mod table;
+pub mod tcp_link;
+pub mod terminal;
+pub mod timer;
\ No newline at end of file
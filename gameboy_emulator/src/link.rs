@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::emulator::Emulator;
+use crate::serial::SerialEndpoint;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    Left = 0,
+    Right = 1,
+}
+
+impl Side {
+    fn other(self) -> Side {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+        }
+    }
+}
+
+/// State of the wire shared by both ends of the cable.
+#[derive(Default)]
+struct Cable {
+    /// SB of a side that is waiting on an external clock.
+    waiting: [Option<u8>; 2],
+    /// Byte clocked into a waiting side by the other one, not yet picked up.
+    delivered: [Option<u8>; 2],
+}
+
+/// One end of a cable connecting two emulators in the same process.
+pub struct LinkPort {
+    cable: Rc<RefCell<Cable>>,
+    side: Side,
+}
+
+impl SerialEndpoint for LinkPort {
+    fn transfer(&mut self, outgoing: u8) -> u8 {
+        let mut cable = self.cable.borrow_mut();
+        let other = self.side.other() as usize;
+
+        match cable.waiting[other].take() {
+            Some(incoming) => {
+                cable.delivered[other] = Some(outgoing);
+                incoming
+            }
+            // The other side is not listening, so nothing is driving the line.
+            None => 0xFF,
+        }
+    }
+
+    fn poll_external(&mut self, outgoing: u8) -> Option<u8> {
+        let mut cable = self.cable.borrow_mut();
+        let side = self.side as usize;
+
+        match cable.delivered[side].take() {
+            Some(incoming) => {
+                cable.waiting[side] = None;
+                Some(incoming)
+            }
+            None => {
+                cable.waiting[side] = Some(outgoing);
+                None
+            }
+        }
+    }
+
+    fn idle(&mut self) {
+        self.cable.borrow_mut().waiting[self.side as usize] = None;
+    }
+}
+
+/// Two emulators connected by a link cable, stepped in lockstep.
+///
+/// The emulator that is behind always runs next, so neither side ever gets
+/// more than one instruction ahead of the other.
+pub struct LinkedPair {
+    pub left: Emulator,
+    pub right: Emulator,
+    cycles: [u64; 2],
+}
+
+impl LinkedPair {
+    pub fn new(mut left: Emulator, mut right: Emulator) -> Self {
+        let cable = Rc::new(RefCell::new(Cable::default()));
+        left.cpu.serial.connect(Box::new(LinkPort { cable: cable.clone(), side: Side::Left }));
+        right.cpu.serial.connect(Box::new(LinkPort { cable, side: Side::Right }));
+
+        LinkedPair { left, right, cycles: [0, 0] }
+    }
+
+    /// Unplug the cable and hand both emulators back.
+    pub fn disconnect(mut self) -> (Emulator, Emulator) {
+        self.left.cpu.serial.disconnect();
+        self.right.cpu.serial.disconnect();
+        (self.left, self.right)
+    }
+
+    pub fn get(&mut self, side: Side) -> &mut Emulator {
+        match side {
+            Side::Left => &mut self.left,
+            Side::Right => &mut self.right,
+        }
+    }
+
+    /// Cycles run so far by the given side.
+    pub fn cycles(&self, side: Side) -> u64 {
+        self.cycles[side as usize]
+    }
+
+    /// Execute one instruction on whichever side is behind. Returns the side
+    /// that ran.
+    pub fn step(&mut self) -> Side {
+        let side = if self.cycles[Side::Right as usize] < self.cycles[Side::Left as usize] {
+            Side::Right
+        } else {
+            Side::Left
+        };
+        let cycles = self.get(side).step();
+        self.cycles[side as usize] += cycles as u64;
+        side
+    }
+
+    /// Run both sides until each has advanced by at least `cycles` cycles.
+    pub fn run_cycles(&mut self, cycles: u64) {
+        let target = self.cycles[0].max(self.cycles[1]) + cycles;
+        while self.cycles[0] < target || self.cycles[1] < target {
+            self.step();
+        }
+    }
+
+    /// Step until `predicate` holds or `max_cycles` have elapsed on both
+    /// sides. Returns whether the predicate was met.
+    pub fn run_until<P>(&mut self, max_cycles: u64, mut predicate: P) -> bool
+    where
+        P: FnMut(&LinkedPair) -> bool,
+    {
+        let limit = self.cycles[0].max(self.cycles[1]) + max_cycles;
+        while self.cycles[0] < limit || self.cycles[1] < limit {
+            if predicate(self) {
+                return true;
+            }
+            self.step();
+        }
+        predicate(self)
+    }
+}
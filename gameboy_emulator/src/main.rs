@@ -1,21 +1,59 @@
-use crate::emulator::Emulator;
-
+pub mod cartridge;
+pub mod cli;
+pub mod compatibility;
+pub mod compression;
 pub mod emulator;
+pub mod four_player;
+pub mod gif;
+pub mod hdma;
 // use emulator::Emulator;
 pub mod dispatch;
 pub mod cpu;
+pub mod debug;
 pub mod bitwise;
+pub mod batch;
+pub mod bess;
+pub mod boot_rom;
+pub mod builder;
 pub mod instructions;
+pub mod joypad;
+pub mod link;
+pub mod model;
+pub mod png;
+pub mod ppu;
+pub mod printer;
+pub mod recording;
+pub mod rewind;
+pub mod screenshot;
+pub mod serial;
+pub mod sgb;
+pub mod slot;
+pub mod state;
 pub mod table;
+pub mod tcp_link;
+pub mod terminal;
+pub mod timer;
 
 
-use std::path::Path;
-
+use std::process;
 
 fn main() {
-    let mut emulator = Emulator::default();
-
-    emulator.init_rom(Path::new("./tests/blargg-test-roms/cpu_instrs/individual/03-op sp,hl.gb"));
-    
-    print!("Oi");
-}
\ No newline at end of file
+    let code = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Help) => {
+            print!("{}", cli::USAGE);
+            cli::EXIT_SUCCESS
+        }
+        Ok(cli::Command::Run(options)) => match cli::run(&options) {
+            Ok(()) => cli::EXIT_SUCCESS,
+            Err(error) => {
+                eprintln!("error: {}", error);
+                cli::EXIT_FAILURE
+            }
+        },
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, cli::USAGE);
+            cli::EXIT_USAGE
+        }
+    };
+    process::exit(code);
+}
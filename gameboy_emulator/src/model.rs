@@ -0,0 +1,133 @@
+use crate::cartridge::Header;
+use crate::compatibility;
+
+const NR52_ADDRESS: u16 = 0xFF26;
+const IF_ADDRESS: u16 = 0xFF0F;
+const JOYP_ADDRESS: u16 = 0xFF00;
+
+/// I/O registers as every boot ROM leaves them. Sound registers are kept in
+/// plain memory.
+const POST_BOOT_IO: [(u16, u8); 31] = [
+    (0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8),
+    (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0xBF),
+    (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF),
+    (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF), (0xFF1E, 0xBF),
+    (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00), (0xFF23, 0xBF),
+    (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1),
+    (0xFF40, 0x91), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF45, 0x00), (0xFF47, 0xFC),
+    (0xFF4A, 0x00), (0xFF4B, 0x00),
+];
+const POST_BOOT_IF: u8 = 0xE1;
+
+/// The Game Boy hardware being emulated. Besides CGB features and the SGB,
+/// the models differ in the CPU and I/O state their boot ROMs leave behind,
+/// which some games check to detect the hardware they run on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Model {
+    /// The original Game Boy with its first boot ROM revision.
+    Dmg0,
+    Dmg,
+    /// Game Boy Pocket and Light.
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    /// Game Boy Advance, running Game Boy and Game Boy Color games.
+    Agb,
+}
+
+impl Model {
+    pub const ALL: [Model; 7] = [Model::Dmg0, Model::Dmg, Model::Mgb, Model::Sgb, Model::Sgb2, Model::Cgb, Model::Agb];
+
+    /// The model a cartridge is best played on: a CGB for anything with CGB
+    /// support, the original Game Boy otherwise.
+    pub fn from_header(header: &Header) -> Self {
+        if header.supports_cgb() { Model::Cgb } else { Model::Dmg }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Model::Dmg0 => "dmg0",
+            Model::Dmg => "dmg",
+            Model::Mgb => "mgb",
+            Model::Sgb => "sgb",
+            Model::Sgb2 => "sgb2",
+            Model::Cgb => "cgb",
+            Model::Agb => "agb",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Model::ALL.into_iter().find(|model| model.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn is_sgb(self) -> bool {
+        matches!(self, Model::Sgb | Model::Sgb2)
+    }
+
+    /// A, F, B, C, D, E, H and L as the boot ROM leaves them for `header`.
+    pub fn post_boot_registers(self, header: &Header) -> [u8; 8] {
+        // The DMG boot ROM ends with the header checksum comparison, so H and
+        // C are set unless the checksum byte is 0.
+        let checksum_flags = if header.header_checksum == 0 { 0x00 } else { 0x30 };
+
+        match self {
+            Model::Dmg0 => [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+            Model::Dmg => [0x01, 0x80 | checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Mgb => [0xFF, 0x80 | checksum_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::Sgb2 => [0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::Cgb | Model::Agb if header.supports_cgb() => {
+                let (b, f) = if self == Model::Agb { agb_increment(0x00) } else { (0x00, 0x80) };
+                [0x11, f, b, 0x00, 0xFF, 0x56, 0x00, 0x0D]
+            }
+            Model::Cgb | Model::Agb => {
+                // B is left holding the title checksum used to pick the
+                // compatibility palette, and HL points into the logo map for
+                // the two titles that need a special one.
+                let checksum = if compatibility::licensed_by_nintendo(header) { header.title_checksum } else { 0x00 };
+                let [h, l] = if matches!(checksum, 0x43 | 0x58) { [0x99, 0x1A] } else { [0x00, 0x7C] };
+                let (b, f) = if self == Model::Agb { agb_increment(checksum) } else { (checksum, 0x80) };
+                [0x11, f, b, 0x00, 0x00, 0x08, h, l]
+            }
+        }
+    }
+
+    /// I/O registers and the values the boot ROM leaves in them, in the order
+    /// to write them. DMA, which can't be written without starting a
+    /// transfer, is left out.
+    pub fn post_boot_io(self) -> Vec<(u16, u8)> {
+        let mut io = POST_BOOT_IO.to_vec();
+        if self.is_sgb() {
+            // The SGB BIOS leaves channel 1 off.
+            io.push((NR52_ADDRESS, 0xF0));
+        }
+        io.push((IF_ADDRESS, POST_BOOT_IF));
+        // Both key groups are left selected, except by the SGB BIOS.
+        io.push((JOYP_ADDRESS, if self.is_sgb() { 0x30 } else { 0x00 }));
+        io
+    }
+
+    /// The internal timer counter when the boot ROM hands over, DIV being its
+    /// upper byte. It depends on how long each boot ROM runs.
+    pub fn post_boot_divider(self) -> u16 {
+        match self {
+            Model::Dmg0 => 0x1830,
+            Model::Dmg | Model::Mgb => 0xABCC,
+            Model::Sgb | Model::Sgb2 => 0xD85C,
+            Model::Cgb | Model::Agb => 0x267C,
+        }
+    }
+}
+
+/// The AGB boot ROM ends with INC B, which leaves Z and H set accordingly.
+fn agb_increment(b: u8) -> (u8, u8) {
+    let b = b.wrapping_add(1);
+    let zero = if b == 0 { 0x80 } else { 0x00 };
+    let half_carry = if b & 0x0F == 0 { 0x20 } else { 0x00 };
+    (b, zero | half_carry)
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+/// Largest payload of a stored (uncompressed) deflate block.
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ColorType {
+    /// One byte per pixel.
+    Grayscale,
+    /// Three bytes per pixel.
+    Rgb,
+}
+
+impl ColorType {
+    fn bytes_per_pixel(self) -> usize {
+        match self {
+            ColorType::Grayscale => 1,
+            ColorType::Rgb => 3,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            ColorType::Grayscale => 0,
+            ColorType::Rgb => 2,
+        }
+    }
+}
+
+/// Encode 8-bit `pixels`, row by row, as a PNG file. The image data is
+/// stored uncompressed, which keeps the encoder tiny; Game Boy sized images
+/// are small enough for that not to matter.
+pub fn encode(width: u32, height: u32, color_type: ColorType, pixels: &[u8]) -> Vec<u8> {
+    let stride = width as usize * color_type.bytes_per_pixel();
+    assert_eq!(pixels.len(), stride * height as usize, "pixel buffer does not match image size");
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // Bit depth, color type, compression, filter and interlace methods.
+    header.extend_from_slice(&[8, color_type.code(), 0, 0, 0]);
+
+    // Every scanline is prefixed with filter type 0 (none).
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in pixels.chunks(stride.max(1)).take(height as usize) {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+pub fn write(path: &Path, width: u32, height: u32, color_type: ColorType, pixels: &[u8]) -> io::Result<()> {
+    fs::write(path, encode(width, height, color_type, pixels))
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Wrap `data` in a zlib stream made of stored deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_STORED_BLOCK).peekable();
+
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[0x01, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none();
+        let length = block.len() as u16;
+        stream.push(last as u8);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    const MODULO: u32 = 65521;
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % MODULO;
+        b = (b + a) % MODULO;
+    }
+    (b << 16) | a
+}
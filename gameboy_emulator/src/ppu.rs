@@ -0,0 +1,683 @@
+use crate::bitwise;
+use crate::cpu::Interrupt;
+use crate::state::{StateReader, StateWriter};
+use std::io;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+pub const LCDC_ADDRESS: u16 = 0xFF40;
+pub const STAT_ADDRESS: u16 = 0xFF41;
+pub const SCY_ADDRESS: u16 = 0xFF42;
+pub const SCX_ADDRESS: u16 = 0xFF43;
+pub const LY_ADDRESS: u16 = 0xFF44;
+pub const LYC_ADDRESS: u16 = 0xFF45;
+pub const BGP_ADDRESS: u16 = 0xFF47;
+pub const OBP0_ADDRESS: u16 = 0xFF48;
+pub const OBP1_ADDRESS: u16 = 0xFF49;
+pub const WY_ADDRESS: u16 = 0xFF4A;
+pub const WX_ADDRESS: u16 = 0xFF4B;
+pub const VBK_ADDRESS: u16 = 0xFF4F;
+pub const BCPS_ADDRESS: u16 = 0xFF68;
+pub const BCPD_ADDRESS: u16 = 0xFF69;
+pub const OCPS_ADDRESS: u16 = 0xFF6A;
+pub const OCPD_ADDRESS: u16 = 0xFF6B;
+
+const VRAM_BANK_SIZE: usize = 0x2000;
+const OAM_SIZE: usize = 0xA0;
+const SPRITE_COUNT: usize = 40;
+const SPRITES_PER_LINE: usize = 10;
+
+const DOTS_PER_LINE: u32 = 456;
+const OAM_SCAN_DOTS: u32 = 80;
+const TRANSFER_DOTS: u32 = 172;
+const HBLANK_DOTS: u32 = DOTS_PER_LINE - OAM_SCAN_DOTS - TRANSFER_DOTS;
+const VBLANK_LINE: u8 = 144;
+const LINES_PER_FRAME: u8 = 154;
+/// How long a frame takes, also while the LCD is off.
+pub const DOTS_PER_FRAME: u32 = DOTS_PER_LINE * LINES_PER_FRAME as u32;
+/// Frames per second, about 59.7, from the 4.19 MHz clock.
+pub const FRAME_RATE: f64 = 4_194_304.0 / DOTS_PER_FRAME as f64;
+
+const LCDC_BG_ENABLE: usize = 0;
+const LCDC_OBJ_ENABLE: usize = 1;
+const LCDC_OBJ_SIZE: usize = 2;
+const LCDC_BG_MAP: usize = 3;
+const LCDC_TILE_DATA: usize = 4;
+const LCDC_WINDOW_ENABLE: usize = 5;
+const LCDC_WINDOW_MAP: usize = 6;
+const LCDC_LCD_ENABLE: usize = 7;
+
+const STAT_COINCIDENCE: usize = 2;
+const STAT_HBLANK_INTERRUPT: usize = 3;
+const STAT_VBLANK_INTERRUPT: usize = 4;
+const STAT_OAM_INTERRUPT: usize = 5;
+const STAT_COINCIDENCE_INTERRUPT: usize = 6;
+const STAT_WRITABLE_MASK: u8 = 0x78;
+
+const ATTRIBUTE_PALETTE_MASK: u8 = 0x07;
+const ATTRIBUTE_BANK: usize = 3;
+const ATTRIBUTE_DMG_PALETTE: usize = 4;
+const ATTRIBUTE_X_FLIP: usize = 5;
+const ATTRIBUTE_Y_FLIP: usize = 6;
+const ATTRIBUTE_PRIORITY: usize = 7;
+
+/// The four DMG shades as 15-bit colors, from lightest to darkest.
+pub const DMG_COLORS: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+/// What LY always reads as in the logs Gameboy Doctor compares against.
+pub const GAMEBOY_DOCTOR_LY: u8 = 0x90;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Transfer = 3,
+}
+
+impl Mode {
+    /// In the order of their STAT values.
+    pub const ALL: [Mode; 4] = [Mode::HBlank, Mode::VBlank, Mode::OamScan, Mode::Transfer];
+}
+
+/// CGB palette memory: 8 palettes of 4 little-endian 15-bit colors, accessed
+/// through an index register (BCPS/OCPS) and a data register (BCPD/OCPD).
+#[derive(Clone)]
+pub struct PaletteRam {
+    pub data: [u8; 64],
+    pub index: u8,
+    pub auto_increment: bool,
+}
+
+impl PaletteRam {
+    fn new() -> Self {
+        PaletteRam { data: [0xFF; 64], index: 0, auto_increment: false }
+    }
+
+    pub fn get_spec(&self) -> u8 {
+        (self.auto_increment as u8) << 7 | 0x40 | self.index
+    }
+
+    pub fn set_spec(&mut self, value: u8) {
+        self.index = value & 0x3F;
+        self.auto_increment = bitwise::get_bit(value, 7);
+    }
+
+    pub fn get_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    pub fn set_data(&mut self, value: u8) {
+        self.data[self.index as usize] = value;
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    pub fn set_color(&mut self, palette: u8, color: u8, value: u16) {
+        let index = (palette as usize * 4 + color as usize) * 2;
+        self.data[index..index + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn color(&self, palette: u8, color: u8) -> u16 {
+        let index = (palette as usize * 4 + color as usize) * 2;
+        u16::from_le_bytes([self.data[index], self.data[index + 1]]) & 0x7FFF
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+        state.write_u8(self.get_spec());
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.read_bytes(&mut self.data)?;
+        self.set_spec(state.read_u8()?);
+        Ok(())
+    }
+}
+
+/// A sprite pixel that won the per-pixel priority among sprites.
+#[derive(Clone, Copy)]
+struct SpritePixel {
+    color: u8,
+    palette: u8,
+    behind_bg: bool,
+}
+
+/// The picture processing unit: VRAM, OAM, the LCD registers and the
+/// framebuffer, rendered one scanline at a time at the start of HBlank.
+pub struct PPU {
+    pub vram: [u8; 2 * VRAM_BANK_SIZE],
+    pub oam: [u8; OAM_SIZE],
+    pub lcdc: u8,
+    pub stat: u8,
+    pub scy: u8,
+    pub scx: u8,
+    pub ly: u8,
+    pub lyc: u8,
+    pub bgp: u8,
+    pub obp0: u8,
+    pub obp1: u8,
+    pub wy: u8,
+    pub wx: u8,
+    pub vram_bank: u8,
+    pub bg_palettes: PaletteRam,
+    pub obj_palettes: PaletteRam,
+    pub cgb_mode: bool,
+    /// DMG compatibility mode of the CGB: rendering works as on the DMG, but
+    /// the shades picked by BGP/OBP0/OBP1 index CGB palettes 0 (and 1 for
+    /// OBP1) instead of the fixed DMG colors.
+    pub dmg_compatibility: bool,
+    ly_stub: Option<u8>,
+    mode: Mode,
+    dots: u32,
+    window_line: u8,
+    /// Each pixel as a 15-bit BGR color (red in the low bits).
+    frame: Vec<u16>,
+    /// Each pixel as a DMG shade (0-3) after BGP/OBP, for DMG palettes.
+    shades: Vec<u8>,
+    frame_count: u64,
+    /// HBlank periods entered and not yet seen by `take_hblanks`.
+    hblanks: u32,
+}
+
+impl Default for PPU {
+    fn default() -> Self {
+        PPU {
+            vram: [0x00; 2 * VRAM_BANK_SIZE],
+            oam: [0x00; OAM_SIZE],
+            lcdc: 0x00,
+            stat: 0x00,
+            scy: 0x00,
+            scx: 0x00,
+            ly: 0x00,
+            lyc: 0x00,
+            bgp: 0x00,
+            obp0: 0x00,
+            obp1: 0x00,
+            wy: 0x00,
+            wx: 0x00,
+            vram_bank: 0,
+            bg_palettes: PaletteRam::new(),
+            obj_palettes: PaletteRam::new(),
+            cgb_mode: false,
+            dmg_compatibility: false,
+            ly_stub: None,
+            mode: Mode::HBlank,
+            dots: 0,
+            window_line: 0,
+            frame: vec![DMG_COLORS[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            shades: vec![0; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_count: 0,
+            hblanks: 0,
+        }
+    }
+}
+
+impl PPU {
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn lcd_enabled(&self) -> bool {
+        bitwise::get_bit(self.lcdc, LCDC_LCD_ENABLE)
+    }
+
+    /// When set, LY reads as this value whatever line is being drawn, as
+    /// Gameboy Doctor expects (see `GAMEBOY_DOCTOR_LY`). Only reads change.
+    pub fn set_ly_stub(&mut self, ly: Option<u8>) {
+        self.ly_stub = ly;
+    }
+
+    /// The framebuffer as 15-bit colors, row by row. It holds a complete
+    /// frame from the start of VBlank until line 0 is drawn again.
+    pub fn frame(&self) -> &[u16] {
+        &self.frame
+    }
+
+    /// The framebuffer as DMG shades (0 = lightest, 3 = darkest).
+    pub fn shades(&self) -> &[u8] {
+        &self.shades
+    }
+
+    /// Frames completed since power on; increments on entering VBlank.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The number of HBlank periods entered since the last call, which
+    /// clock the CGB's HBlank DMA.
+    pub fn take_hblanks(&mut self) -> u32 {
+        std::mem::take(&mut self.hblanks)
+    }
+
+    /// The first 256 8x8 tiles of the screen, 20 per row, as tile data of
+    /// the background scrolled by SCX and SCY. This is how the SGB receives
+    /// VRAM transfers.
+    pub fn screen_tiles(&self) -> Vec<u8> {
+        let bg_map = if bitwise::get_bit(self.lcdc, LCDC_BG_MAP) { 0x9C00 } else { 0x9800 };
+        (0..256usize)
+            .flat_map(|index| {
+                let (left, top) = (index % 20 * 8, index / 20 * 8);
+                (0..8).flat_map(move |row| {
+                    let y = self.scy.wrapping_add((top + row) as u8);
+                    let (low, high) = (0..8).fold((0u8, 0u8), |(low, high), column| {
+                        let (color, _, _) = self.map_pixel(bg_map, self.scx.wrapping_add((left + column) as u8), y);
+                        (low << 1 | color & 1, high << 1 | color >> 1)
+                    });
+                    [low, high]
+                })
+            })
+            .collect()
+    }
+
+    /// The framebuffer as 24-bit RGB, row by row.
+    pub fn frame_rgb(&self) -> Vec<u8> {
+        self.frame.iter().flat_map(|&color| color_to_rgb(color)).collect()
+    }
+
+    pub fn read_vram(&self, address: u16) -> u8 {
+        self.vram[self.vram_offset(address)]
+    }
+
+    pub fn write_vram(&mut self, address: u16, value: u8) {
+        let offset = self.vram_offset(address);
+        self.vram[offset] = value;
+    }
+
+    pub fn read_oam(&self, address: u16) -> u8 {
+        self.oam[(address - 0xFE00) as usize]
+    }
+
+    pub fn write_oam(&mut self, address: u16, value: u8) {
+        self.oam[(address - 0xFE00) as usize] = value;
+    }
+
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            LCDC_ADDRESS => self.lcdc,
+            STAT_ADDRESS => {
+                let mode = if self.lcd_enabled() { self.mode as u8 } else { 0 };
+                0x80 | (self.stat & STAT_WRITABLE_MASK) | (self.stat & (1 << STAT_COINCIDENCE)) | mode
+            }
+            SCY_ADDRESS => self.scy,
+            SCX_ADDRESS => self.scx,
+            LY_ADDRESS => self.ly_stub.unwrap_or(self.ly),
+            LYC_ADDRESS => self.lyc,
+            BGP_ADDRESS => self.bgp,
+            OBP0_ADDRESS => self.obp0,
+            OBP1_ADDRESS => self.obp1,
+            WY_ADDRESS => self.wy,
+            WX_ADDRESS => self.wx,
+            VBK_ADDRESS if self.cgb_mode => 0xFE | self.vram_bank,
+            BCPS_ADDRESS if self.cgb_mode => self.bg_palettes.get_spec(),
+            BCPD_ADDRESS if self.cgb_mode => self.bg_palettes.get_data(),
+            OCPS_ADDRESS if self.cgb_mode => self.obj_palettes.get_spec(),
+            OCPD_ADDRESS if self.cgb_mode => self.obj_palettes.get_data(),
+            _ => 0xFF,
+        }
+    }
+
+    /// Write an LCD register. Returns the interrupts requested by the write,
+    /// as a mask of `Interrupt` bits.
+    pub fn write_register(&mut self, address: u16, value: u8) -> u8 {
+        match address {
+            LCDC_ADDRESS => return self.set_lcdc(value),
+            STAT_ADDRESS => self.stat = (self.stat & !STAT_WRITABLE_MASK) | (value & STAT_WRITABLE_MASK),
+            SCY_ADDRESS => self.scy = value,
+            SCX_ADDRESS => self.scx = value,
+            LY_ADDRESS => {}
+            LYC_ADDRESS => {
+                self.lyc = value;
+                if self.lcd_enabled() {
+                    return self.compare_lyc();
+                }
+            }
+            BGP_ADDRESS => self.bgp = value,
+            OBP0_ADDRESS => self.obp0 = value,
+            OBP1_ADDRESS => self.obp1 = value,
+            WY_ADDRESS => self.wy = value,
+            WX_ADDRESS => self.wx = value,
+            VBK_ADDRESS if self.cgb_mode => self.vram_bank = value & 0x01,
+            BCPS_ADDRESS if self.cgb_mode => self.bg_palettes.set_spec(value),
+            BCPD_ADDRESS if self.cgb_mode => self.bg_palettes.set_data(value),
+            OCPS_ADDRESS if self.cgb_mode => self.obj_palettes.set_spec(value),
+            OCPD_ADDRESS if self.cgb_mode => self.obj_palettes.set_data(value),
+            _ => {}
+        }
+        0
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.vram);
+        state.write_bytes(&self.oam);
+        for register in [
+            self.lcdc, self.stat, self.scy, self.scx, self.ly, self.lyc, self.bgp, self.obp0, self.obp1, self.wy,
+            self.wx, self.vram_bank,
+        ] {
+            state.write_u8(register);
+        }
+        self.bg_palettes.save_state(state);
+        self.obj_palettes.save_state(state);
+        state.write_bool(self.cgb_mode);
+        state.write_bool(self.dmg_compatibility);
+        state.write_u8(self.mode as u8);
+        state.write_u32(self.dots);
+        state.write_u8(self.window_line);
+        state.write_u16s(&self.frame);
+        state.write_bytes(&self.shades);
+        state.write_u64(self.frame_count);
+        state.write_u32(self.hblanks);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        state.read_bytes(&mut self.vram)?;
+        state.read_bytes(&mut self.oam)?;
+        for register in [
+            &mut self.lcdc,
+            &mut self.stat,
+            &mut self.scy,
+            &mut self.scx,
+            &mut self.ly,
+            &mut self.lyc,
+            &mut self.bgp,
+            &mut self.obp0,
+            &mut self.obp1,
+            &mut self.wy,
+            &mut self.wx,
+            &mut self.vram_bank,
+        ] {
+            *register = state.read_u8()?;
+        }
+        self.vram_bank &= 0x01;
+        self.bg_palettes.load_state(state)?;
+        self.obj_palettes.load_state(state)?;
+        self.cgb_mode = state.read_bool()?;
+        self.dmg_compatibility = state.read_bool()?;
+        self.mode = Mode::ALL[state.read_index(Mode::ALL.len())?];
+        self.dots = state.read_u32()?;
+        self.window_line = state.read_u8()?;
+        state.read_u16s(&mut self.frame)?;
+        state.read_bytes(&mut self.shades)?;
+        self.frame_count = state.read_u64()?;
+        self.hblanks = state.read_u32()?;
+        Ok(())
+    }
+
+    /// Restart at the beginning of line `ly`, for states that record LY but
+    /// not where the PPU was within the line.
+    pub fn start_line(&mut self, ly: u8) {
+        self.ly = ly % LINES_PER_FRAME;
+        self.dots = 0;
+        self.window_line = 0;
+        self.mode = if !self.lcd_enabled() {
+            Mode::HBlank
+        } else if self.ly >= VBLANK_LINE {
+            Mode::VBlank
+        } else {
+            Mode::OamScan
+        };
+    }
+
+    /// Advance by `cycles` dots. Returns the interrupts requested, as a mask
+    /// of `Interrupt` bits.
+    pub fn step(&mut self, cycles: u32) -> u8 {
+        if !self.lcd_enabled() {
+            return 0;
+        }
+
+        let mut interrupts = 0;
+        self.dots += cycles;
+        loop {
+            match self.mode {
+                Mode::OamScan if self.dots >= OAM_SCAN_DOTS => {
+                    self.dots -= OAM_SCAN_DOTS;
+                    self.mode = Mode::Transfer;
+                }
+                Mode::Transfer if self.dots >= TRANSFER_DOTS => {
+                    self.dots -= TRANSFER_DOTS;
+                    self.render_line();
+                    self.hblanks += 1;
+                    interrupts |= self.enter_mode(Mode::HBlank);
+                }
+                Mode::HBlank if self.dots >= HBLANK_DOTS => {
+                    self.dots -= HBLANK_DOTS;
+                    self.ly += 1;
+                    if self.ly == VBLANK_LINE {
+                        self.frame_count += 1;
+                        interrupts |= Interrupt::VBLank.mask();
+                        interrupts |= self.enter_mode(Mode::VBlank);
+                    } else {
+                        interrupts |= self.enter_mode(Mode::OamScan);
+                    }
+                    interrupts |= self.compare_lyc();
+                }
+                Mode::VBlank if self.dots >= DOTS_PER_LINE => {
+                    self.dots -= DOTS_PER_LINE;
+                    self.ly += 1;
+                    if self.ly == LINES_PER_FRAME {
+                        self.ly = 0;
+                        self.window_line = 0;
+                        interrupts |= self.enter_mode(Mode::OamScan);
+                    }
+                    interrupts |= self.compare_lyc();
+                }
+                _ => break,
+            }
+        }
+
+        interrupts
+    }
+
+    fn set_lcdc(&mut self, value: u8) -> u8 {
+        let was_enabled = self.lcd_enabled();
+        self.lcdc = value;
+
+        match (was_enabled, self.lcd_enabled()) {
+            (true, false) => {
+                self.ly = 0;
+                self.dots = 0;
+                self.window_line = 0;
+                self.mode = Mode::HBlank;
+                0
+            }
+            (false, true) => {
+                self.mode = Mode::OamScan;
+                self.compare_lyc()
+            }
+            _ => 0,
+        }
+    }
+
+    fn enter_mode(&mut self, mode: Mode) -> u8 {
+        self.mode = mode;
+        let source = match mode {
+            Mode::HBlank => STAT_HBLANK_INTERRUPT,
+            Mode::VBlank => STAT_VBLANK_INTERRUPT,
+            Mode::OamScan => STAT_OAM_INTERRUPT,
+            Mode::Transfer => return 0,
+        };
+        if bitwise::get_bit(self.stat, source) { Interrupt::LCDStatus.mask() } else { 0 }
+    }
+
+    fn compare_lyc(&mut self) -> u8 {
+        let coincidence = self.ly == self.lyc;
+        bitwise::assign_bit(&mut self.stat, STAT_COINCIDENCE, coincidence);
+        if coincidence && bitwise::get_bit(self.stat, STAT_COINCIDENCE_INTERRUPT) {
+            Interrupt::LCDStatus.mask()
+        } else {
+            0
+        }
+    }
+
+    fn vram_offset(&self, address: u16) -> usize {
+        self.vram_bank as usize * VRAM_BANK_SIZE + (address as usize - 0x8000)
+    }
+
+    /// Color index (0-3) of one pixel of a tile.
+    fn tile_pixel(&self, bank: usize, tile_address: u16, row: u8, column: u8) -> u8 {
+        let offset = bank * VRAM_BANK_SIZE + (tile_address as usize - 0x8000) + row as usize * 2;
+        let low = self.vram[offset];
+        let high = self.vram[offset + 1];
+        let bit = 7 - column as usize;
+        (bitwise::get_bit(high, bit) as u8) << 1 | bitwise::get_bit(low, bit) as u8
+    }
+
+    fn bg_tile_address(&self, tile: u8) -> u16 {
+        if bitwise::get_bit(self.lcdc, LCDC_TILE_DATA) {
+            0x8000 + tile as u16 * 16
+        } else {
+            0x9000u16.wrapping_add((tile as i8 as i16 * 16) as u16)
+        }
+    }
+
+    /// Fetch the background or window pixel at (`x`, `y`) of the given tile
+    /// map. Returns its color index, CGB palette and BG-to-OAM priority.
+    fn map_pixel(&self, map_address: u16, x: u8, y: u8) -> (u8, u8, bool) {
+        let map_offset = (map_address - 0x8000) as usize + (y as usize / 8) * 32 + x as usize / 8;
+        let tile = self.vram[map_offset];
+        let attributes = if self.cgb_mode { self.vram[VRAM_BANK_SIZE + map_offset] } else { 0 };
+
+        let mut row = y % 8;
+        let mut column = x % 8;
+        if bitwise::get_bit(attributes, ATTRIBUTE_Y_FLIP) {
+            row = 7 - row;
+        }
+        if bitwise::get_bit(attributes, ATTRIBUTE_X_FLIP) {
+            column = 7 - column;
+        }
+
+        let bank = bitwise::get_bit(attributes, ATTRIBUTE_BANK) as usize;
+        let color = self.tile_pixel(bank, self.bg_tile_address(tile), row, column);
+        (color, attributes & ATTRIBUTE_PALETTE_MASK, bitwise::get_bit(attributes, ATTRIBUTE_PRIORITY))
+    }
+
+    fn render_line(&mut self) {
+        let line = self.ly;
+        let mut bg = [(0u8, 0u8, false); SCREEN_WIDTH];
+        let bg_enabled = bitwise::get_bit(self.lcdc, LCDC_BG_ENABLE);
+
+        // On the CGB, LCDC bit 0 only removes the background's priority.
+        if self.cgb_mode || bg_enabled {
+            self.render_background(line, &mut bg);
+        }
+        let sprites = if bitwise::get_bit(self.lcdc, LCDC_OBJ_ENABLE) {
+            self.render_sprites(line)
+        } else {
+            [None; SCREEN_WIDTH]
+        };
+
+        let bg_master_priority = !self.cgb_mode || bg_enabled;
+        let row = line as usize * SCREEN_WIDTH;
+
+        for x in 0..SCREEN_WIDTH {
+            let (bg_color, bg_palette, bg_priority) = bg[x];
+            let sprite = sprites[x].filter(|sprite| {
+                !bg_master_priority || bg_color == 0 || !(sprite.behind_bg || bg_priority)
+            });
+
+            let (color, shade) = match (sprite, self.cgb_mode) {
+                (Some(sprite), true) => (self.obj_palettes.color(sprite.palette, sprite.color), sprite.color),
+                (Some(sprite), false) => {
+                    let palette = if sprite.palette == 0 { self.obp0 } else { self.obp1 };
+                    let shade = (palette >> (sprite.color * 2)) & 0x03;
+                    (self.dmg_color(&self.obj_palettes, sprite.palette, shade), shade)
+                }
+                (None, true) => (self.bg_palettes.color(bg_palette, bg_color), bg_color),
+                // Without the background, the DMG shows shade 0 whatever BGP says.
+                (None, false) if !bg_enabled => (self.dmg_color(&self.bg_palettes, 0, 0), 0),
+                (None, false) => {
+                    let shade = (self.bgp >> (bg_color * 2)) & 0x03;
+                    (self.dmg_color(&self.bg_palettes, 0, shade), shade)
+                }
+            };
+
+            self.frame[row + x] = color;
+            self.shades[row + x] = shade;
+        }
+    }
+
+    fn dmg_color(&self, palettes: &PaletteRam, palette: u8, shade: u8) -> u16 {
+        if self.dmg_compatibility { palettes.color(palette, shade) } else { DMG_COLORS[shade as usize] }
+    }
+
+    fn render_background(&mut self, line: u8, bg: &mut [(u8, u8, bool); SCREEN_WIDTH]) {
+        let bg_map = if bitwise::get_bit(self.lcdc, LCDC_BG_MAP) { 0x9C00 } else { 0x9800 };
+        let window_map = if bitwise::get_bit(self.lcdc, LCDC_WINDOW_MAP) { 0x9C00 } else { 0x9800 };
+        let window_visible = bitwise::get_bit(self.lcdc, LCDC_WINDOW_ENABLE) && self.wy <= line && self.wx <= 166;
+        let window_start = self.wx as i16 - 7;
+
+        for (x, pixel) in bg.iter_mut().enumerate() {
+            *pixel = if window_visible && x as i16 >= window_start {
+                self.map_pixel(window_map, (x as i16 - window_start) as u8, self.window_line)
+            } else {
+                self.map_pixel(bg_map, self.scx.wrapping_add(x as u8), self.scy.wrapping_add(line))
+            };
+        }
+
+        if window_visible {
+            self.window_line += 1;
+        }
+    }
+
+    fn render_sprites(&self, line: u8) -> [Option<SpritePixel>; SCREEN_WIDTH] {
+        let height = if bitwise::get_bit(self.lcdc, LCDC_OBJ_SIZE) { 16 } else { 8 };
+
+        let mut visible: Vec<usize> = (0..SPRITE_COUNT)
+            .filter(|&sprite| {
+                let top = self.oam[sprite * 4] as i16 - 16;
+                (top..top + height).contains(&(line as i16))
+            })
+            .take(SPRITES_PER_LINE)
+            .collect();
+        // The DMG favours the sprite with the lowest X, then the lowest OAM
+        // index; the CGB only looks at the OAM index.
+        if !self.cgb_mode {
+            visible.sort_by_key(|&sprite| self.oam[sprite * 4 + 1]);
+        }
+
+        let mut pixels = [None; SCREEN_WIDTH];
+        for sprite in visible {
+            let [y, x, tile, attributes] = [0, 1, 2, 3].map(|i| self.oam[sprite * 4 + i]);
+            let mut row = (line as i16 - (y as i16 - 16)) as u8;
+            if bitwise::get_bit(attributes, ATTRIBUTE_Y_FLIP) {
+                row = height as u8 - 1 - row;
+            }
+            let tile = if height == 16 { tile & 0xFE } else { tile };
+            let tile_address = 0x8000 + tile as u16 * 16;
+
+            let (bank, palette) = if self.cgb_mode {
+                (bitwise::get_bit(attributes, ATTRIBUTE_BANK) as usize, attributes & ATTRIBUTE_PALETTE_MASK)
+            } else {
+                (0, bitwise::get_bit(attributes, ATTRIBUTE_DMG_PALETTE) as u8)
+            };
+
+            for column in 0..8u8 {
+                let screen_x = x as i16 - 8 + column as i16;
+                if !(0..SCREEN_WIDTH as i16).contains(&screen_x) || pixels[screen_x as usize].is_some() {
+                    continue;
+                }
+                let column = if bitwise::get_bit(attributes, ATTRIBUTE_X_FLIP) { 7 - column } else { column };
+                let color = self.tile_pixel(bank, tile_address, row, column);
+                if color != 0 {
+                    pixels[screen_x as usize] = Some(SpritePixel {
+                        color,
+                        palette,
+                        behind_bg: bitwise::get_bit(attributes, ATTRIBUTE_PRIORITY),
+                    });
+                }
+            }
+        }
+
+        pixels
+    }
+}
+
+/// Expand a 15-bit color (red in the low bits) to 24-bit RGB.
+pub fn color_to_rgb(color: u16) -> [u8; 3] {
+    let expand = |channel: u16| {
+        let channel = (channel & 0x1F) as u8;
+        (channel << 3) | (channel >> 2)
+    };
+    [expand(color), expand(color >> 5), expand(color >> 10)]
+}
@@ -0,0 +1,304 @@
+use std::cell::RefCell;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::bitwise;
+use crate::png;
+use crate::serial::SerialEndpoint;
+
+const MAGIC: [u8; 2] = [0x88, 0x33];
+const DEVICE_ID: u8 = 0x81;
+
+const COMMAND_INIT: u8 = 0x01;
+const COMMAND_PRINT: u8 = 0x02;
+const COMMAND_DATA: u8 = 0x04;
+const COMMAND_STATUS: u8 = 0x0F;
+
+const STATUS_CHECKSUM_ERROR: usize = 0;
+const STATUS_PRINTING: usize = 1;
+const STATUS_IMAGE_FULL: usize = 2;
+const STATUS_UNPROCESSED: usize = 3;
+
+/// The printer holds up to 9 DATA packets: 160x144 pixels of tiles.
+const BUFFER_SIZE: usize = 0x2280;
+const IMAGE_WIDTH: usize = 160;
+const TILES_PER_ROW: usize = IMAGE_WIDTH / 8;
+const BYTES_PER_TILE: usize = 16;
+/// STATUS packets answered as busy after a PRINT, like the real printer while
+/// the paper is moving.
+const PRINTING_POLLS: u8 = 4;
+
+/// Grayscale levels for the four printable shades, from white to black.
+const SHADES: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum State {
+    Magic1,
+    Magic2,
+    Command,
+    Compression,
+    LengthLow,
+    LengthHigh,
+    Data,
+    ChecksumLow,
+    ChecksumHigh,
+    DeviceId,
+    Status,
+}
+
+/// A finished print job: 8-bit grayscale, 160 pixels wide.
+#[derive(Clone, Debug)]
+pub struct PrintedImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl PrintedImage {
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        png::write(path, self.width, self.height, png::ColorType::Grayscale, &self.pixels)
+    }
+}
+
+struct Printer {
+    state: State,
+    command: u8,
+    compressed: bool,
+    length: u16,
+    packet: Vec<u8>,
+    checksum: u16,
+    received_checksum: u16,
+    status: u8,
+    printing_polls: u8,
+    buffer: Vec<u8>,
+    images: Vec<PrintedImage>,
+    output_dir: Option<PathBuf>,
+    errors: Vec<String>,
+}
+
+/// The Game Boy Printer, plugged into the serial port.
+///
+/// The Game Boy drives the clock and sends packets of the form
+/// `88 33 | command | compression | length (LE) | data | checksum (LE) | 00 00`;
+/// the printer answers the last two bytes with its device ID and status.
+/// Each PRINT command turns the buffered 2bpp tile data into an image, which
+/// is kept in memory and, if an output directory was given, written there as
+/// `print_NNNN.png`. Clones share the same printer.
+#[derive(Clone)]
+pub struct GameBoyPrinter {
+    printer: Rc<RefCell<Printer>>,
+}
+
+impl Default for GameBoyPrinter {
+    fn default() -> Self {
+        GameBoyPrinter {
+            printer: Rc::new(RefCell::new(Printer {
+                state: State::Magic1,
+                command: 0,
+                compressed: false,
+                length: 0,
+                packet: Vec::new(),
+                checksum: 0,
+                received_checksum: 0,
+                status: 0,
+                printing_polls: 0,
+                buffer: Vec::with_capacity(BUFFER_SIZE),
+                images: Vec::new(),
+                output_dir: None,
+                errors: Vec::new(),
+            })),
+        }
+    }
+}
+
+impl GameBoyPrinter {
+    pub fn new() -> Self {
+        GameBoyPrinter::default()
+    }
+
+    /// A printer that also writes every print job as a PNG into `output_dir`.
+    pub fn with_output_dir(output_dir: &Path) -> Self {
+        let printer = GameBoyPrinter::default();
+        printer.printer.borrow_mut().output_dir = Some(output_dir.to_path_buf());
+        printer
+    }
+
+    pub fn images(&self) -> Vec<PrintedImage> {
+        self.printer.borrow().images.clone()
+    }
+
+    /// Errors hit while writing PNG files, which cannot be reported to the
+    /// Game Boy.
+    pub fn errors(&self) -> Vec<String> {
+        self.printer.borrow().errors.clone()
+    }
+}
+
+impl SerialEndpoint for GameBoyPrinter {
+    fn transfer(&mut self, outgoing: u8) -> u8 {
+        self.printer.borrow_mut().receive(outgoing)
+    }
+}
+
+impl Printer {
+    fn receive(&mut self, byte: u8) -> u8 {
+        let mut reply = 0x00;
+
+        self.state = match self.state {
+            State::Magic1 if byte == MAGIC[0] => State::Magic2,
+            State::Magic1 => State::Magic1,
+            State::Magic2 if byte == MAGIC[1] => State::Command,
+            State::Magic2 => State::Magic1,
+            State::Command => {
+                self.command = byte;
+                self.checksum = byte as u16;
+                State::Compression
+            }
+            State::Compression => {
+                self.compressed = bitwise::get_bit(byte, 0);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthLow
+            }
+            State::LengthLow => {
+                self.length = byte as u16;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                State::LengthHigh
+            }
+            State::LengthHigh => {
+                self.length |= (byte as u16) << 8;
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                self.packet.clear();
+                if self.length == 0 { State::ChecksumLow } else { State::Data }
+            }
+            State::Data => {
+                self.packet.push(byte);
+                self.checksum = self.checksum.wrapping_add(byte as u16);
+                if self.packet.len() == self.length as usize { State::ChecksumLow } else { State::Data }
+            }
+            State::ChecksumLow => {
+                self.received_checksum = byte as u16;
+                State::ChecksumHigh
+            }
+            State::ChecksumHigh => {
+                self.received_checksum |= (byte as u16) << 8;
+                self.execute_packet();
+                State::DeviceId
+            }
+            State::DeviceId => {
+                reply = DEVICE_ID;
+                State::Status
+            }
+            State::Status => {
+                reply = self.status;
+                if self.command == COMMAND_STATUS {
+                    self.advance_printing();
+                }
+                State::Magic1
+            }
+        };
+
+        reply
+    }
+
+    fn execute_packet(&mut self) {
+        let valid = self.checksum == self.received_checksum;
+        bitwise::assign_bit(&mut self.status, STATUS_CHECKSUM_ERROR, !valid);
+        if !valid {
+            return;
+        }
+
+        match self.command {
+            COMMAND_INIT => {
+                self.buffer.clear();
+                self.printing_polls = 0;
+                self.status = 0;
+            }
+            COMMAND_DATA => {
+                let data = if self.compressed { decompress(&self.packet) } else { self.packet.clone() };
+                let room = BUFFER_SIZE - self.buffer.len();
+                self.buffer.extend_from_slice(&data[..data.len().min(room)]);
+
+                bitwise::assign_bit(&mut self.status, STATUS_UNPROCESSED, !self.buffer.is_empty());
+                bitwise::assign_bit(&mut self.status, STATUS_IMAGE_FULL, self.buffer.len() == BUFFER_SIZE);
+            }
+            COMMAND_PRINT => {
+                // Sheets, margins, palette and exposure.
+                let palette = self.packet.get(2).copied().unwrap_or(0xE4);
+                self.print(palette);
+                self.printing_polls = PRINTING_POLLS;
+                bitwise::assign_bit(&mut self.status, STATUS_PRINTING, true);
+                bitwise::assign_bit(&mut self.status, STATUS_UNPROCESSED, false);
+                bitwise::assign_bit(&mut self.status, STATUS_IMAGE_FULL, false);
+            }
+            _ => {}
+        }
+    }
+
+    fn advance_printing(&mut self) {
+        self.printing_polls = self.printing_polls.saturating_sub(1);
+        if self.printing_polls == 0 {
+            bitwise::assign_bit(&mut self.status, STATUS_PRINTING, false);
+        }
+    }
+
+    fn print(&mut self, palette: u8) {
+        // A palette of 0 is sent by some games and means the default one.
+        let palette = if palette == 0 { 0xE4 } else { palette };
+        let tile_rows = self.buffer.len() / (TILES_PER_ROW * BYTES_PER_TILE);
+        // Nothing to print only feeds the paper; an image 0 pixels high
+        // can't be saved.
+        if tile_rows == 0 {
+            self.buffer.clear();
+            return;
+        }
+        let height = tile_rows * 8;
+        let mut pixels = vec![0u8; IMAGE_WIDTH * height];
+
+        for (tile_index, tile) in self.buffer.chunks_exact(BYTES_PER_TILE).enumerate().take(tile_rows * TILES_PER_ROW) {
+            let tile_x = (tile_index % TILES_PER_ROW) * 8;
+            let tile_y = (tile_index / TILES_PER_ROW) * 8;
+
+            for row in 0..8 {
+                let low = tile[row * 2];
+                let high = tile[row * 2 + 1];
+                for column in 0..8 {
+                    let bit = 7 - column;
+                    let color = (bitwise::get_bit(high, bit) as u8) << 1 | bitwise::get_bit(low, bit) as u8;
+                    let shade = (palette >> (color * 2)) & 0x03;
+                    pixels[(tile_y + row) * IMAGE_WIDTH + tile_x + column] = SHADES[shade as usize];
+                }
+            }
+        }
+        self.buffer.clear();
+
+        let image = PrintedImage { width: IMAGE_WIDTH as u32, height: height as u32, pixels };
+        if let Some(output_dir) = &self.output_dir {
+            let path = output_dir.join(format!("print_{:04}.png", self.images.len() + 1));
+            if let Err(error) = image.save_png(&path) {
+                self.errors.push(format!("{}: {error}", path.display()));
+            }
+        }
+        self.images.push(image);
+    }
+}
+
+/// Undo the printer's run-length encoding: a control byte with bit 7 set
+/// repeats the next byte `(control & 0x7F) + 2` times, otherwise the next
+/// `control + 1` bytes are copied as is.
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(BUFFER_SIZE);
+    let mut bytes = data.iter().copied();
+
+    while let Some(control) = bytes.next() {
+        if bitwise::get_bit(control, 7) {
+            let Some(value) = bytes.next() else { break };
+            let count = (control & 0x7F) as usize + 2;
+            output.extend(std::iter::repeat_n(value, count));
+        } else {
+            output.extend(bytes.by_ref().take(control as usize + 1));
+        }
+    }
+
+    output
+}
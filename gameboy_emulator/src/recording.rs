@@ -0,0 +1,169 @@
+use crate::ppu::{DOTS_PER_FRAME, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The CPU clock; a frame lasts `DOTS_PER_FRAME` of its cycles.
+const CYCLES_PER_SECOND: u64 = 4_194_304;
+const WAV_HEADER_SIZE: u32 = 44;
+const BITS_PER_SAMPLE: u16 = 16;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum VideoFormat {
+    /// YUV4MPEG2 with full resolution chroma, which ffmpeg and most encoders
+    /// read as is, at the exact frame rate.
+    Y4m,
+    /// Bare 24-bit RGB frames, as `PPU::frame_rgb` returns them.
+    Rgb,
+}
+
+impl VideoFormat {
+    /// Y4M for `.y4m` files, raw RGB for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension() {
+            Some(extension) if extension.eq_ignore_ascii_case("y4m") => VideoFormat::Y4m,
+            _ => VideoFormat::Rgb,
+        }
+    }
+}
+
+/// Writes every frame it's given to a video stream.
+pub struct VideoWriter {
+    writer: Box<dyn Write>,
+    format: VideoFormat,
+    frames: u64,
+}
+
+impl VideoWriter {
+    /// Start the stream, writing its header if the format has one.
+    pub fn new(mut writer: impl Write + 'static, format: VideoFormat) -> io::Result<Self> {
+        if format == VideoFormat::Y4m {
+            writeln!(
+                writer,
+                "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444",
+                SCREEN_WIDTH, SCREEN_HEIGHT, CYCLES_PER_SECOND, DOTS_PER_FRAME
+            )?;
+        }
+        Ok(VideoWriter { writer: Box::new(writer), format, frames: 0 })
+    }
+
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Append a frame of 24-bit RGB pixels, row by row.
+    pub fn write_frame(&mut self, rgb: &[u8]) -> io::Result<()> {
+        assert_eq!(rgb.len(), SCREEN_WIDTH * SCREEN_HEIGHT * 3, "not a frame of 24-bit RGB");
+        match self.format {
+            VideoFormat::Rgb => self.writer.write_all(rgb)?,
+            VideoFormat::Y4m => {
+                self.writer.write_all(b"FRAME\n")?;
+                self.writer.write_all(&rgb_to_yuv444(rgb))?;
+            }
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// The Y, then Cb, then Cr planes of `rgb`, in the BT.601 limited range
+/// that players assume for Y4M.
+pub fn rgb_to_yuv444(rgb: &[u8]) -> Vec<u8> {
+    let pixels = rgb.len() / 3;
+    let mut planes = vec![0; pixels * 3];
+    for (index, pixel) in rgb.chunks_exact(3).enumerate() {
+        let [r, g, b] = [pixel[0] as i32, pixel[1] as i32, pixel[2] as i32];
+        planes[index] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        planes[pixels + index] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+        planes[2 * pixels + index] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+    planes
+}
+
+/// How many audio samples per channel `frames` frames last at
+/// `sample_rate`, rounded down, so a recording's audio track can be kept
+/// exactly as long as its video.
+pub fn samples_for_frames(frames: u64, sample_rate: u32) -> u64 {
+    frames * DOTS_PER_FRAME as u64 * sample_rate as u64 / CYCLES_PER_SECOND
+}
+
+/// The sound to record, as 16-bit stereo at `sample_rate`. There's no APU
+/// yet, so it's silence, handed out frame by frame to keep the track as long
+/// as the video.
+pub struct SilentAudio {
+    sample_rate: u32,
+    frames: u64,
+    /// Samples handed out so far, per channel.
+    samples: u64,
+}
+
+impl SilentAudio {
+    pub fn new(sample_rate: u32) -> Self {
+        SilentAudio { sample_rate, frames: 0, samples: 0 }
+    }
+
+    /// The samples that one more frame lasts, interleaved by channel.
+    pub fn frame_samples(&mut self) -> Vec<i16> {
+        self.frames += 1;
+        let samples = samples_for_frames(self.frames, self.sample_rate);
+        let count = samples - std::mem::replace(&mut self.samples, samples);
+        vec![0; count as usize * 2]
+    }
+}
+
+/// Writes 16-bit PCM to a WAV file. The sizes in the header are filled in
+/// by `finish`.
+pub struct WavWriter<W: Write + Seek> {
+    writer: W,
+    channels: u16,
+    /// Samples written per channel.
+    samples: u64,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut writer: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * BITS_PER_SAMPLE / 8;
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&(WAV_HEADER_SIZE - 8).to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&channels.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        writer.write_all(&block_align.to_le_bytes())?;
+        writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { writer, channels, samples: 0 })
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Append samples, interleaved by channel.
+    pub fn write_samples(&mut self, samples: &[i16]) -> io::Result<()> {
+        let bytes: Vec<u8> = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+        self.writer.write_all(&bytes)?;
+        self.samples += (samples.len() / self.channels as usize) as u64;
+        Ok(())
+    }
+
+    /// Fill in the sizes and flush, returning the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        let data_size = u32::try_from(self.samples * self.channels as u64 * BITS_PER_SAMPLE as u64 / 8)
+            .map_err(|_| io::Error::other("WAV files are limited to 4 GiB"))?;
+        self.writer.seek(SeekFrom::Start(4))?;
+        self.writer.write_all(&(WAV_HEADER_SIZE - 8 + data_size).to_le_bytes())?;
+        self.writer.seek(SeekFrom::Start(WAV_HEADER_SIZE as u64 - 4))?;
+        self.writer.write_all(&data_size.to_le_bytes())?;
+        self.writer.seek(SeekFrom::End(0))?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
@@ -0,0 +1,106 @@
+use crate::compression;
+use std::collections::VecDeque;
+
+/// Save states taken every few frames, to step back in time. Only the
+/// newest is kept whole: each older one is stored as the compressed XOR
+/// with the snapshot after it, which is mostly zeros since little changes
+/// in a few frames. Once the buffer outgrows its budget the oldest
+/// snapshots are dropped, though the newest is always kept.
+pub struct RewindBuffer {
+    interval: u64,
+    budget: usize,
+    newest: Option<(u64, Vec<u8>)>,
+    /// Older snapshots, oldest first, as the frame they were taken on and
+    /// their compressed difference with the next one.
+    deltas: VecDeque<(u64, Vec<u8>)>,
+    delta_bytes: usize,
+}
+
+impl RewindBuffer {
+    /// A snapshot every `interval` frames, in at most `budget` bytes.
+    pub fn new(interval: u64, budget: usize) -> Self {
+        RewindBuffer { interval: interval.max(1), budget, newest: None, deltas: VecDeque::new(), delta_bytes: 0 }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Bytes taken by the snapshots.
+    pub fn memory_used(&self) -> usize {
+        self.newest.as_ref().map_or(0, |(_, state)| state.len()) + self.delta_bytes
+    }
+
+    /// How many snapshots are kept.
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// The frame of the furthest snapshot back.
+    pub fn oldest_frame(&self) -> Option<u64> {
+        self.deltas.front().or(self.newest.as_ref()).map(|&(frame, _)| frame)
+    }
+
+    /// Whether a snapshot should be taken on `frame`. Going back to an
+    /// earlier frame, by loading a state, starts the history over.
+    pub fn is_due(&self, frame: u64) -> bool {
+        match self.newest {
+            Some((last, _)) => frame < last || frame >= last + self.interval,
+            None => true,
+        }
+    }
+
+    /// Keep `state`, the save state taken on `frame`.
+    pub fn push(&mut self, frame: u64, state: Vec<u8>) {
+        match self.newest.take() {
+            Some((last, previous)) if last < frame && previous.len() == state.len() => {
+                let delta = compression::compress(&xor(&previous, &state));
+                self.delta_bytes += delta.len();
+                self.deltas.push_back((last, delta));
+            }
+            _ => self.clear(),
+        }
+        self.newest = Some((frame, state));
+
+        while self.memory_used() > self.budget {
+            let Some((_, delta)) = self.deltas.pop_front() else { break };
+            self.delta_bytes -= delta.len();
+        }
+    }
+
+    /// The last snapshot taken on or before `frame`, or the oldest one if
+    /// none goes back that far. The snapshots after it are forgotten, and it
+    /// becomes the newest.
+    pub fn pop_to(&mut self, frame: u64) -> Option<Vec<u8>> {
+        let (mut current, mut state) = self.newest.take()?;
+        while current > frame {
+            let Some((older, delta)) = self.deltas.pop_back() else { break };
+            self.delta_bytes -= delta.len();
+            let delta = compression::decompress(&delta).expect("own compressed snapshot");
+            for (byte, difference) in state.iter_mut().zip(delta) {
+                *byte ^= difference;
+            }
+            current = older;
+        }
+        self.newest = Some((current, state.clone()));
+        Some(state)
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
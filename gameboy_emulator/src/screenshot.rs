@@ -0,0 +1,116 @@
+use crate::cpu::CPU;
+use crate::png::{self, ColorType};
+use crate::ppu::{color_to_rgb, DMG_COLORS, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::sgb::{SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
+use std::io::{self, ErrorKind};
+use std::path::Path;
+
+/// Largest scale factor, which makes a 2560x2304 image.
+pub const MAX_SCALE: u32 = 16;
+
+/// The colors DMG shades are drawn in, from lightest to darkest. Games in
+/// color, on the CGB, in its DMG compatibility mode or on the SGB, keep
+/// their own colors.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DmgPalette {
+    /// The shades the emulator displays.
+    #[default]
+    Grayscale,
+    /// The yellowish green of the original DMG screen.
+    PeaSoup,
+    Custom([[u8; 3]; 4]),
+}
+
+impl DmgPalette {
+    /// Parse `grayscale`, `pea-soup` (or `green`), or four colors from
+    /// lightest to darkest as `RRGGBB` hex separated by commas.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "grayscale" | "gray" => return Ok(DmgPalette::Grayscale),
+            "pea-soup" | "green" => return Ok(DmgPalette::PeaSoup),
+            _ => {}
+        }
+        let invalid = || format!("invalid palette '{}'", text);
+        let colors = text
+            .split(',')
+            .map(|color| {
+                let color = color.trim().trim_start_matches('#');
+                let value = u32::from_str_radix(color, 16).ok().filter(|_| color.len() == 6).ok_or_else(invalid)?;
+                Ok([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(DmgPalette::Custom(colors.try_into().map_err(|_| invalid())?))
+    }
+
+    pub fn colors(self) -> [[u8; 3]; 4] {
+        match self {
+            DmgPalette::Grayscale => DMG_COLORS.map(color_to_rgb),
+            DmgPalette::PeaSoup => [[0x9B, 0xBC, 0x0F], [0x8B, 0xAC, 0x0F], [0x30, 0x62, 0x30], [0x0F, 0x38, 0x0F]],
+            DmgPalette::Custom(colors) => colors,
+        }
+    }
+}
+
+/// How the framebuffer is turned into an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Screenshot {
+    pub palette: DmgPalette,
+    /// Each pixel becomes a square this many pixels wide, 1 to `MAX_SCALE`.
+    pub scale: u32,
+}
+
+impl Default for Screenshot {
+    fn default() -> Self {
+        Screenshot { palette: DmgPalette::default(), scale: 1 }
+    }
+}
+
+impl Screenshot {
+    /// The image's width and height: the SGB's 256x224 picture, border
+    /// included, on the SGB, otherwise the 160x144 screen, times `scale`.
+    pub fn size(&self, cpu: &CPU) -> (u32, u32) {
+        let (width, height) = if cpu.sgb.is_some() {
+            (SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        };
+        (width as u32 * self.scale, height as u32 * self.scale)
+    }
+
+    /// The current frame as 24-bit RGB, row by row. Fails if `scale` isn't
+    /// 1 to `MAX_SCALE`.
+    pub fn rgb(&self, cpu: &CPU) -> io::Result<Vec<u8>> {
+        if !(1..=MAX_SCALE).contains(&self.scale) {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("scale must be 1 to {}", MAX_SCALE)));
+        }
+        let ppu = &cpu.ppu;
+        let (width, pixels): (usize, Vec<[u8; 3]>) = if let Some(sgb) = &cpu.sgb {
+            (SGB_SCREEN_WIDTH, sgb.frame().iter().map(|&color| color_to_rgb(color)).collect())
+        } else if ppu.cgb_mode || ppu.dmg_compatibility {
+            (SCREEN_WIDTH, ppu.frame().iter().map(|&color| color_to_rgb(color)).collect())
+        } else {
+            let colors = self.palette.colors();
+            (SCREEN_WIDTH, ppu.shades().iter().map(|&shade| colors[shade as usize & 3]).collect())
+        };
+
+        let scale = self.scale as usize;
+        let mut rgb = Vec::with_capacity(pixels.len() * 3 * scale * scale);
+        for row in pixels.chunks(width) {
+            let start = rgb.len();
+            for pixel in row {
+                for _ in 0..scale {
+                    rgb.extend_from_slice(pixel);
+                }
+            }
+            for _ in 1..scale {
+                rgb.extend_from_within(start..start + width * scale * 3);
+            }
+        }
+        Ok(rgb)
+    }
+
+    pub fn write(&self, path: &Path, cpu: &CPU) -> io::Result<()> {
+        let (width, height) = self.size(cpu);
+        png::write(path, width, height, ColorType::Rgb, &self.rgb(cpu)?)
+    }
+}
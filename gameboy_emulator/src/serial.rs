@@ -0,0 +1,212 @@
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use crate::bitwise;
+use crate::state::{StateReader, StateWriter};
+
+/// Bits per transfer: one byte is shifted out while one is shifted in.
+const TRANSFER_BITS: u32 = 8;
+/// The internal clock runs at 8192 Hz, i.e. one bit every 512 CPU cycles.
+const CYCLES_PER_BIT: u32 = 512;
+
+const CONTROL_START_BIT: usize = 7;
+const CONTROL_CLOCK_BIT: usize = 0;
+/// Bits 1-6 of SC are unused on the DMG and always read back as 1.
+const CONTROL_UNUSED_MASK: u8 = 0x7E;
+
+/// The other end of the link cable.
+///
+/// Whoever drives the clock decides when a byte is exchanged: when this Game
+/// Boy uses its internal clock, `transfer` is called once the eight bits have
+/// been shifted; when it waits on an external clock, `poll_external` is
+/// called until the remote side clocks a byte in.
+pub trait SerialEndpoint {
+    /// Exchange a byte while this Game Boy drives the clock. `outgoing` is the
+    /// byte shifted out of SB, and the returned byte is shifted in.
+    fn transfer(&mut self, outgoing: u8) -> u8;
+
+    /// Poll the remote side while waiting on an external clock. Returns the
+    /// byte it clocked in, if any, in exchange for `outgoing`.
+    fn poll_external(&mut self, _outgoing: u8) -> Option<u8> {
+        None
+    }
+
+    /// Called on every step in which this Game Boy is not waiting on an
+    /// external clock, so the endpoint can still service the remote side.
+    fn idle(&mut self) {}
+
+    /// Called at the start of every step with the cycles it took and the
+    /// fewest cycles from now until this Game Boy can call `transfer`, so the
+    /// endpoint can keep the remote side in step.
+    fn advance(&mut self, _cycles: u32, _until_transfer: u32) {}
+}
+
+/// No cable plugged in: the data line floats high and no external clock ever
+/// arrives.
+pub struct Disconnected;
+
+impl SerialEndpoint for Disconnected {
+    fn transfer(&mut self, _outgoing: u8) -> u8 {
+        0xFF
+    }
+}
+
+/// Records every byte sent by the Game Boy. Clones share the same buffer, so
+/// one handle can be given to the emulator and another kept to read the
+/// output (e.g. Blargg's test results).
+#[derive(Clone, Default)]
+pub struct SerialCapture {
+    bytes: Rc<RefCell<Vec<u8>>>,
+}
+
+impl SerialCapture {
+    pub fn new() -> Self {
+        SerialCapture::default()
+    }
+
+    pub fn bytes(&self) -> Vec<u8> {
+        self.bytes.borrow().clone()
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes.borrow()).into_owned()
+    }
+
+    pub fn clear(&self) {
+        self.bytes.borrow_mut().clear();
+    }
+}
+
+impl SerialEndpoint for SerialCapture {
+    fn transfer(&mut self, outgoing: u8) -> u8 {
+        self.bytes.borrow_mut().push(outgoing);
+        0xFF
+    }
+}
+
+/// Writes every byte sent by the Game Boy straight to stdout.
+pub struct StdoutSink;
+
+impl SerialEndpoint for StdoutSink {
+    fn transfer(&mut self, outgoing: u8) -> u8 {
+        let mut stdout = std::io::stdout();
+        stdout.write_all(&[outgoing]).expect("Error writing serial output");
+        stdout.flush().expect("Error writing serial output");
+        0xFF
+    }
+}
+
+/// The serial port: SB (0xFF01) and SC (0xFF02).
+pub struct Serial {
+    data: u8,
+    control: u8,
+    elapsed: u32,
+    endpoint: Box<dyn SerialEndpoint>,
+}
+
+impl Default for Serial {
+    fn default() -> Self {
+        Serial {
+            data: 0x00,
+            control: 0x00,
+            elapsed: 0,
+            endpoint: Box::new(Disconnected),
+        }
+    }
+}
+
+impl Serial {
+    /// Plug a new endpoint into the port, returning the previous one.
+    pub fn connect(&mut self, endpoint: Box<dyn SerialEndpoint>) -> Box<dyn SerialEndpoint> {
+        std::mem::replace(&mut self.endpoint, endpoint)
+    }
+
+    pub fn disconnect(&mut self) -> Box<dyn SerialEndpoint> {
+        self.connect(Box::new(Disconnected))
+    }
+
+    pub fn get_data(&self) -> u8 {
+        self.data
+    }
+
+    pub fn set_data(&mut self, value: u8) {
+        self.data = value;
+    }
+
+    pub fn get_control(&self) -> u8 {
+        self.control | CONTROL_UNUSED_MASK
+    }
+
+    pub fn set_control(&mut self, value: u8) {
+        self.control = value & !CONTROL_UNUSED_MASK;
+        if self.transfer_requested() {
+            self.elapsed = 0;
+        }
+    }
+
+    pub fn transfer_requested(&self) -> bool {
+        bitwise::get_bit(self.control, CONTROL_START_BIT)
+    }
+
+    pub fn internal_clock(&self) -> bool {
+        bitwise::get_bit(self.control, CONTROL_CLOCK_BIT)
+    }
+
+    /// Advance the port by `cycles` CPU cycles. Returns `true` when a transfer
+    /// completed and the serial interrupt should be requested.
+    pub fn step(&mut self, cycles: u32) -> bool {
+        // A transfer started later takes all eight bits from then on.
+        let until_transfer = if self.transfer_requested() && self.internal_clock() {
+            (TRANSFER_BITS * CYCLES_PER_BIT).saturating_sub(self.elapsed + cycles)
+        } else {
+            TRANSFER_BITS * CYCLES_PER_BIT
+        };
+        self.endpoint.advance(cycles, until_transfer);
+
+        if !self.transfer_requested() {
+            self.endpoint.idle();
+            return false;
+        }
+
+        if self.internal_clock() {
+            self.elapsed += cycles;
+            if self.elapsed < TRANSFER_BITS * CYCLES_PER_BIT {
+                self.endpoint.idle();
+                return false;
+            }
+            let incoming = self.endpoint.transfer(self.data);
+            self.complete_transfer(incoming);
+            true
+        } else {
+            match self.endpoint.poll_external(self.data) {
+                Some(incoming) => {
+                    self.complete_transfer(incoming);
+                    true
+                }
+                None => false,
+            }
+        }
+    }
+
+    /// The port's registers and transfer progress. The endpoint stays
+    /// plugged in as it is.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u8(self.data);
+        state.write_u8(self.control);
+        state.write_u32(self.elapsed);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.data = state.read_u8()?;
+        self.control = state.read_u8()? & !CONTROL_UNUSED_MASK;
+        self.elapsed = state.read_u32()?;
+        Ok(())
+    }
+
+    fn complete_transfer(&mut self, incoming: u8) {
+        self.data = incoming;
+        self.elapsed = 0;
+        bitwise::assign_bit(&mut self.control, CONTROL_START_BIT, false);
+    }
+}
@@ -0,0 +1,574 @@
+use crate::bitwise;
+use crate::joypad::{Button, Joypad};
+use crate::ppu::{PPU, SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::state::{self, StateReader, StateWriter};
+use std::io;
+
+pub const SGB_SCREEN_WIDTH: usize = 256;
+pub const SGB_SCREEN_HEIGHT: usize = 224;
+/// Position of the Game Boy screen inside the border.
+const GAME_X: usize = 48;
+const GAME_Y: usize = 40;
+
+const PACKET_SIZE: usize = 16;
+const PACKET_BITS: usize = PACKET_SIZE * 8;
+const P14: usize = 4;
+const P15: usize = 5;
+const SELECT_MASK: u8 = 0x30;
+
+const ATTRIBUTE_COLUMNS: usize = SCREEN_WIDTH / 8;
+const ATTRIBUTE_ROWS: usize = SCREEN_HEIGHT / 8;
+const ATTRIBUTE_CELLS: usize = ATTRIBUTE_COLUMNS * ATTRIBUTE_ROWS;
+const ATTRIBUTE_FILES: usize = 45;
+const ATTRIBUTE_FILE_SIZE: usize = ATTRIBUTE_CELLS / 4;
+
+pub const TRANSFER_SIZE: usize = 0x1000;
+const SYSTEM_PALETTES: usize = 512;
+const BORDER_TILES: usize = 256;
+const BORDER_TILE_SIZE: usize = 32;
+const BORDER_MAP_WIDTH: usize = SGB_SCREEN_WIDTH / 8;
+const BORDER_MAP_HEIGHT: usize = SGB_SCREEN_HEIGHT / 8;
+const BORDER_PALETTES_OFFSET: usize = 0x800;
+const BORDER_COLORS: usize = 16;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const ATTR_TRN: u8 = 0x15;
+const ATTR_SET: u8 = 0x16;
+const MASK_EN: u8 = 0x17;
+
+/// What MASK_EN does to the Game Boy screen while the game prepares VRAM.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mask {
+    Cancel,
+    Freeze,
+    Black,
+    Color0,
+}
+
+impl Mask {
+    pub const ALL: [Mask; 4] = [Mask::Cancel, Mask::Freeze, Mask::Black, Mask::Color0];
+}
+
+/// Data expected in VRAM on the next frame, after a *_TRN command.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Transfer {
+    Palettes,
+    BorderTiles(usize),
+    BorderMap,
+    AttributeFiles,
+}
+
+/// The Super Game Boy: the SNES side of the cartridge, talked to through
+/// packets sent over the joypad port. It colors the Game Boy screen with
+/// four palettes picked per 8x8 cell and frames it with a border.
+///
+/// A packet starts with a reset pulse (P14 and P15 both low), followed by
+/// 128 bits, least significant first: P14 low for a 0, P15 low for a 1, each
+/// followed by both high. A final 0 bit ends it. The first byte holds the
+/// command in its upper five bits and the number of packets in the lower
+/// three.
+pub struct Sgb {
+    receiving: bool,
+    waiting_release: bool,
+    bits: usize,
+    packet: [u8; PACKET_SIZE],
+    command: Vec<u8>,
+    select: u8,
+    pub palettes: [[u16; 4]; 4],
+    pub system_palettes: Vec<u16>,
+    /// Palette (0-3) of each 8x8 cell of the Game Boy screen.
+    pub attributes: [u8; ATTRIBUTE_CELLS],
+    attribute_files: Vec<u8>,
+    border_tiles: Vec<u8>,
+    border_map: Vec<u16>,
+    border_palettes: [[u16; BORDER_COLORS]; 4],
+    pub mask: Mask,
+    players: u8,
+    player: u8,
+    /// The keys held on joypads 2-4, as masks of `Button` bits. Player 1's
+    /// are the Game Boy's own joypad.
+    pressed: [u8; 3],
+    transfer: Option<Transfer>,
+    frame: Vec<u16>,
+}
+
+impl Default for Sgb {
+    fn default() -> Self {
+        Sgb {
+            receiving: false,
+            waiting_release: false,
+            bits: 0,
+            packet: [0; PACKET_SIZE],
+            command: Vec::new(),
+            select: SELECT_MASK,
+            palettes: [[0x7FFF, 0x56B5, 0x294A, 0x0000]; 4],
+            system_palettes: vec![0; SYSTEM_PALETTES * 4],
+            attributes: [0; ATTRIBUTE_CELLS],
+            attribute_files: vec![0; ATTRIBUTE_FILES * ATTRIBUTE_FILE_SIZE],
+            border_tiles: vec![0; BORDER_TILES * BORDER_TILE_SIZE],
+            border_map: vec![0; BORDER_MAP_WIDTH * BORDER_MAP_HEIGHT],
+            border_palettes: [[0; BORDER_COLORS]; 4],
+            mask: Mask::Cancel,
+            players: 1,
+            player: 0,
+            pressed: [0; 3],
+            transfer: None,
+            frame: vec![0x7FFF; SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT],
+        }
+    }
+}
+
+impl Sgb {
+    /// The 256x224 picture sent to the TV, as 15-bit colors, row by row.
+    pub fn frame(&self) -> &[u16] {
+        &self.frame
+    }
+
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+
+    /// The joypad currently read through JOYP, 0 for player 1.
+    pub fn player(&self) -> u8 {
+        self.player
+    }
+
+    /// Press a key on joypad `player`, 1 to 3 for players 2 to 4. Returns
+    /// `true` if it was not already held.
+    pub fn press(&mut self, player: u8, button: Button) -> bool {
+        let pressed = &mut self.pressed[player as usize - 1];
+        let newly_pressed = *pressed & button.mask() == 0;
+        *pressed |= button.mask();
+        newly_pressed
+    }
+
+    pub fn release(&mut self, player: u8, button: Button) {
+        self.pressed[player as usize - 1] &= !button.mask();
+    }
+
+    /// JOYP as seen through the SGB: the keys of the current joypad, or with
+    /// multiplayer enabled and both key groups deselected, its ID as
+    /// `0xF - player`.
+    pub fn read_joypad(&self, joypad: &Joypad) -> u8 {
+        let value = match self.player {
+            0 => joypad.read_register(),
+            player => joypad.read_register_with(self.pressed[player as usize - 1]),
+        };
+        if self.players > 1 && value & SELECT_MASK == SELECT_MASK {
+            (value & 0xF0) | (0x0F - self.player)
+        } else {
+            value
+        }
+    }
+
+    /// Feed a JOYP write into the packet receiver.
+    pub fn write_joypad(&mut self, value: u8) {
+        let select = value & SELECT_MASK;
+        let previous = std::mem::replace(&mut self.select, select);
+
+        match (select, self.receiving) {
+            (0x00, _) => {
+                self.receiving = true;
+                self.waiting_release = true;
+                self.bits = 0;
+                self.packet = [0; PACKET_SIZE];
+            }
+            (SELECT_MASK, _) => {
+                self.waiting_release = false;
+                // Releasing P15 moves on to the next joypad.
+                if !self.receiving && self.players > 1 && !bitwise::get_bit(previous, P15) {
+                    self.player = (self.player + 1) % self.players;
+                }
+            }
+            (_, true) if !self.waiting_release => {
+                self.waiting_release = true;
+                let bit = !bitwise::get_bit(select, P15) && bitwise::get_bit(select, P14);
+                if self.bits == PACKET_BITS {
+                    self.receiving = false;
+                    self.receive_packet();
+                } else {
+                    bitwise::assign_bit(&mut self.packet[self.bits / 8], self.bits % 8, bit);
+                    self.bits += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bool(self.receiving);
+        state.write_bool(self.waiting_release);
+        state.write_u8(self.bits as u8);
+        state.write_bytes(&self.packet);
+        state.write_vec(&self.command);
+        state.write_u8(self.select);
+        for palette in &self.palettes {
+            state.write_u16s(palette);
+        }
+        state.write_u16s(&self.system_palettes);
+        state.write_bytes(&self.attributes);
+        state.write_bytes(&self.attribute_files);
+        state.write_bytes(&self.border_tiles);
+        state.write_u16s(&self.border_map);
+        for palette in &self.border_palettes {
+            state.write_u16s(palette);
+        }
+        state.write_u8(self.mask as u8);
+        state.write_u8(self.players);
+        state.write_u8(self.player);
+        state.write_bytes(&self.pressed);
+        let (transfer, half) = match self.transfer {
+            None => (0, 0),
+            Some(Transfer::Palettes) => (1, 0),
+            Some(Transfer::BorderTiles(half)) => (2, half as u8),
+            Some(Transfer::BorderMap) => (3, 0),
+            Some(Transfer::AttributeFiles) => (4, 0),
+        };
+        state.write_u8(transfer);
+        state.write_u8(half);
+        state.write_u16s(&self.frame);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.receiving = state.read_bool()?;
+        self.waiting_release = state.read_bool()?;
+        self.bits = state.read_u8()? as usize;
+        state.read_bytes(&mut self.packet)?;
+        self.command = state.read_vec()?;
+        let invalid_command = !self.command.len().is_multiple_of(PACKET_SIZE) || self.command.len() >= 8 * PACKET_SIZE;
+        if self.bits > PACKET_BITS || invalid_command {
+            return Err(state::invalid("save state has an invalid SGB packet"));
+        }
+        self.select = state.read_u8()? & SELECT_MASK;
+        for palette in &mut self.palettes {
+            state.read_u16s(palette)?;
+        }
+        state.read_u16s(&mut self.system_palettes)?;
+        state.read_bytes(&mut self.attributes)?;
+        state.read_bytes(&mut self.attribute_files)?;
+        state.read_bytes(&mut self.border_tiles)?;
+        state.read_u16s(&mut self.border_map)?;
+        for palette in &mut self.border_palettes {
+            state.read_u16s(palette)?;
+        }
+        self.mask = Mask::ALL[state.read_index(Mask::ALL.len())?];
+        self.players = state.read_u8()?;
+        self.player = state.read_u8()?;
+        if !matches!(self.players, 1 | 2 | 4) || self.player >= self.players {
+            return Err(state::invalid("save state has invalid SGB players"));
+        }
+        state.read_bytes(&mut self.pressed)?;
+        let transfer = state.read_index(5)?;
+        let half = state.read_index(2)?;
+        self.transfer = match transfer {
+            0 => None,
+            1 => Some(Transfer::Palettes),
+            2 => Some(Transfer::BorderTiles(half)),
+            3 => Some(Transfer::BorderMap),
+            _ => Some(Transfer::AttributeFiles),
+        };
+        state.read_u16s(&mut self.frame)?;
+        Ok(())
+    }
+
+    fn receive_packet(&mut self) {
+        self.command.extend_from_slice(&self.packet);
+        let packets = (self.command[0] & 0x07).max(1) as usize;
+        if self.command.len() >= packets * PACKET_SIZE {
+            let command = std::mem::take(&mut self.command);
+            self.execute(&command);
+        }
+    }
+
+    fn execute(&mut self, data: &[u8]) {
+        match data[0] >> 3 {
+            PAL01 => self.set_palette_pair(0, 1, data),
+            PAL23 => self.set_palette_pair(2, 3, data),
+            PAL03 => self.set_palette_pair(0, 3, data),
+            PAL12 => self.set_palette_pair(1, 2, data),
+            ATTR_BLK => self.attribute_blocks(data),
+            ATTR_LIN => self.attribute_lines(data),
+            ATTR_DIV => self.attribute_divide(data),
+            ATTR_CHR => self.attribute_characters(data),
+            PAL_SET => self.set_system_palettes(data),
+            PAL_TRN => self.transfer = Some(Transfer::Palettes),
+            MLT_REQ => {
+                self.players = match data[1] & 0x03 {
+                    0x01 => 2,
+                    0x03 => 4,
+                    _ => 1,
+                };
+                self.player = 0;
+            }
+            CHR_TRN => self.transfer = Some(Transfer::BorderTiles((data[1] & 0x01) as usize)),
+            PCT_TRN => self.transfer = Some(Transfer::BorderMap),
+            ATTR_TRN => self.transfer = Some(Transfer::AttributeFiles),
+            ATTR_SET => {
+                self.apply_attribute_file(data[1] & 0x3F);
+                if bitwise::get_bit(data[1], 6) {
+                    self.mask = Mask::Cancel;
+                }
+            }
+            MASK_EN => {
+                self.mask = match data[1] & 0x03 {
+                    0x01 => Mask::Freeze,
+                    0x02 => Mask::Black,
+                    0x03 => Mask::Color0,
+                    _ => Mask::Cancel,
+                }
+            }
+            // Sound, SNES program upload and the like are not emulated.
+            _ => {}
+        }
+    }
+
+    /// Color 0 is shared by all four palettes.
+    fn set_color0(&mut self, color: u16) {
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color;
+        }
+    }
+
+    fn set_palette_pair(&mut self, first: usize, second: usize, data: &[u8]) {
+        let color = |index: usize| u16::from_le_bytes([data[1 + index * 2], data[2 + index * 2]]) & 0x7FFF;
+        self.set_color0(color(0));
+        for index in 1..4 {
+            self.palettes[first][index] = color(index);
+            self.palettes[second][index] = color(index + 3);
+        }
+    }
+
+    fn set_attribute(&mut self, x: usize, y: usize, palette: u8) {
+        if x < ATTRIBUTE_COLUMNS && y < ATTRIBUTE_ROWS {
+            self.attributes[y * ATTRIBUTE_COLUMNS + x] = palette & 0x03;
+        }
+    }
+
+    fn attribute_blocks(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for block in data[2..].chunks_exact(6).take(count) {
+            let [control, palettes, x1, y1, x2, y2] = [0, 1, 2, 3, 4, 5].map(|i| block[i]);
+            let inside = bitwise::get_bit(control, 0).then_some(palettes & 0x03);
+            let outside = bitwise::get_bit(control, 2).then_some((palettes >> 4) & 0x03);
+            // With only the inside or the outside set, the border follows it.
+            let border = match (bitwise::get_bit(control, 1), inside, outside) {
+                (true, _, _) => Some((palettes >> 2) & 0x03),
+                (false, Some(palette), None) | (false, None, Some(palette)) => Some(palette),
+                _ => None,
+            };
+            let (x1, y1, x2, y2) = (x1 & 0x1F, y1 & 0x1F, x2 & 0x1F, y2 & 0x1F);
+
+            for y in 0..ATTRIBUTE_ROWS as u8 {
+                for x in 0..ATTRIBUTE_COLUMNS as u8 {
+                    let within = (x1..=x2).contains(&x) && (y1..=y2).contains(&y);
+                    let on_edge = within && (x == x1 || x == x2 || y == y1 || y == y2);
+                    let palette = match (within, on_edge) {
+                        (true, true) => border,
+                        (true, false) => inside,
+                        (false, _) => outside,
+                    };
+                    if let Some(palette) = palette {
+                        self.set_attribute(x as usize, y as usize, palette);
+                    }
+                }
+            }
+        }
+    }
+
+    fn attribute_lines(&mut self, data: &[u8]) {
+        let count = data[1] as usize;
+        for &line in data[2..].iter().take(count) {
+            let number = (line & 0x1F) as usize;
+            let palette = (line >> 5) & 0x03;
+            if bitwise::get_bit(line, 7) {
+                (0..ATTRIBUTE_COLUMNS).for_each(|x| self.set_attribute(x, number, palette));
+            } else {
+                (0..ATTRIBUTE_ROWS).for_each(|y| self.set_attribute(number, y, palette));
+            }
+        }
+    }
+
+    fn attribute_divide(&mut self, data: &[u8]) {
+        let control = data[1];
+        let after = control & 0x03;
+        let before = (control >> 2) & 0x03;
+        let on_line = (control >> 4) & 0x03;
+        let horizontal = bitwise::get_bit(control, 6);
+        let line = (data[2] & 0x1F) as usize;
+
+        for y in 0..ATTRIBUTE_ROWS {
+            for x in 0..ATTRIBUTE_COLUMNS {
+                let position = if horizontal { y } else { x };
+                let palette = match position.cmp(&line) {
+                    std::cmp::Ordering::Less => before,
+                    std::cmp::Ordering::Equal => on_line,
+                    std::cmp::Ordering::Greater => after,
+                };
+                self.set_attribute(x, y, palette);
+            }
+        }
+    }
+
+    fn attribute_characters(&mut self, data: &[u8]) {
+        let (mut x, mut y) = ((data[1] & 0x1F) as usize, (data[2] & 0x1F) as usize);
+        let count = (u16::from_le_bytes([data[3], data[4]]) as usize).min(ATTRIBUTE_CELLS);
+        let vertical = bitwise::get_bit(data[5], 0);
+
+        for cell in 0..count {
+            let Some(&byte) = data.get(6 + cell / 4) else { break };
+            if x >= ATTRIBUTE_COLUMNS || y >= ATTRIBUTE_ROWS {
+                break;
+            }
+            self.set_attribute(x, y, byte >> (6 - 2 * (cell % 4)));
+
+            if vertical {
+                y += 1;
+                if y == ATTRIBUTE_ROWS {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == ATTRIBUTE_COLUMNS {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    fn set_system_palettes(&mut self, data: &[u8]) {
+        for palette in 0..4 {
+            let index = (u16::from_le_bytes([data[1 + palette * 2], data[2 + palette * 2]]) & 0x01FF) as usize;
+            self.palettes[palette].copy_from_slice(&self.system_palettes[index * 4..index * 4 + 4]);
+        }
+        self.set_color0(self.palettes[0][0]);
+
+        let attributes = data[9];
+        if bitwise::get_bit(attributes, 7) {
+            self.apply_attribute_file(attributes & 0x3F);
+        }
+        if bitwise::get_bit(attributes, 6) {
+            self.mask = Mask::Cancel;
+        }
+    }
+
+    /// Load one of the 45 attribute files sent with ATTR_TRN: 2 bits per
+    /// cell, the first cell in the top bits.
+    fn apply_attribute_file(&mut self, file: u8) {
+        let file = file as usize;
+        if file >= ATTRIBUTE_FILES {
+            return;
+        }
+        let start = file * ATTRIBUTE_FILE_SIZE;
+        for cell in 0..ATTRIBUTE_CELLS {
+            let byte = self.attribute_files[start + cell / 4];
+            self.attributes[cell] = (byte >> (6 - 2 * (cell % 4))) & 0x03;
+        }
+    }
+
+    /// Called at the start of each VBlank: complete any pending VRAM
+    /// transfer with the frame just drawn, then compose the TV picture.
+    pub fn vblank(&mut self, ppu: &PPU) {
+        if let Some(transfer) = self.transfer.take() {
+            self.complete_transfer(transfer, &ppu.screen_tiles());
+        }
+        self.render(ppu.shades());
+    }
+
+    fn complete_transfer(&mut self, transfer: Transfer, data: &[u8]) {
+        let words = |bytes: &[u8]| -> Vec<u16> {
+            bytes.chunks_exact(2).map(|pair| u16::from_le_bytes([pair[0], pair[1]])).collect()
+        };
+
+        match transfer {
+            Transfer::Palettes => {
+                for (palette, color) in self.system_palettes.iter_mut().zip(words(data)) {
+                    *palette = color & 0x7FFF;
+                }
+            }
+            Transfer::BorderTiles(half) => {
+                self.border_tiles[half * TRANSFER_SIZE..(half + 1) * TRANSFER_SIZE].copy_from_slice(data);
+            }
+            Transfer::BorderMap => {
+                let map_size = BORDER_MAP_WIDTH * BORDER_MAP_HEIGHT * 2;
+                self.border_map = words(&data[..map_size]);
+                let colors = words(&data[BORDER_PALETTES_OFFSET..BORDER_PALETTES_OFFSET + 4 * BORDER_COLORS * 2]);
+                for (palette, colors) in self.border_palettes.iter_mut().zip(colors.chunks_exact(BORDER_COLORS)) {
+                    for (entry, &color) in palette.iter_mut().zip(colors) {
+                        *entry = color & 0x7FFF;
+                    }
+                }
+            }
+            Transfer::AttributeFiles => {
+                let size = self.attribute_files.len();
+                self.attribute_files.copy_from_slice(&data[..size]);
+            }
+        }
+    }
+
+    /// Color index (0-15) of a border pixel; 0 is transparent.
+    fn border_pixel(&self, x: usize, y: usize) -> (u8, usize) {
+        let entry = self.border_map[(y / 8) * BORDER_MAP_WIDTH + x / 8];
+        let tile = (entry & 0xFF) as usize;
+        let palette = ((entry >> 10) & 0x03) as usize;
+        let row = if bitwise::get_bit(entry, 15) { 7 - y % 8 } else { y % 8 };
+        let column = if bitwise::get_bit(entry, 14) { x % 8 } else { 7 - x % 8 };
+
+        // SNES 4bpp tiles: planes 0 and 1 interleaved per row, then 2 and 3.
+        let offset = tile * BORDER_TILE_SIZE + row * 2;
+        let planes = [offset, offset + 1, offset + 16, offset + 17];
+        let color = planes
+            .iter()
+            .enumerate()
+            .fold(0u8, |color, (plane, &byte)| {
+                color | (bitwise::get_bit(self.border_tiles[byte], column) as u8) << plane
+            });
+        (color, palette)
+    }
+
+    fn render(&mut self, shades: &[u8]) {
+        let backdrop = self.palettes[0][0];
+
+        for y in 0..SGB_SCREEN_HEIGHT {
+            for x in 0..SGB_SCREEN_WIDTH {
+                let index = y * SGB_SCREEN_WIDTH + x;
+                let game_x = x.wrapping_sub(GAME_X);
+                let game_y = y.wrapping_sub(GAME_Y);
+
+                let mut color = if game_x < SCREEN_WIDTH && game_y < SCREEN_HEIGHT {
+                    match self.mask {
+                        Mask::Cancel => {
+                            let cell = (game_y / 8) * ATTRIBUTE_COLUMNS + game_x / 8;
+                            let shade = shades[game_y * SCREEN_WIDTH + game_x];
+                            self.palettes[self.attributes[cell] as usize][shade as usize]
+                        }
+                        Mask::Freeze => self.frame[index],
+                        Mask::Black => 0x0000,
+                        Mask::Color0 => backdrop,
+                    }
+                } else {
+                    backdrop
+                };
+
+                let (border_color, palette) = self.border_pixel(x, y);
+                if border_color != 0 {
+                    color = self.border_palettes[palette][border_color as usize];
+                }
+                self.frame[index] = color;
+            }
+        }
+    }
+}
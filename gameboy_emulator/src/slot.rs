@@ -0,0 +1,66 @@
+use crate::compression;
+use crate::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+use crate::state::{self, StateReader, StateWriter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub const MAGIC: [u8; 4] = *b"GBSL";
+pub const VERSION: u16 = 1;
+
+/// A save state kept in a numbered slot, with what a menu needs to show it
+/// without loading it.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Slot {
+    /// Seconds since the Unix epoch when the state was saved.
+    pub timestamp: u64,
+    /// CRC-32 of the ROM the state was saved with.
+    pub rom_checksum: u32,
+    /// The frame on screen when the state was saved, as 15-bit colors.
+    pub thumbnail: Vec<u16>,
+    /// A state from `Emulator::save_state`.
+    pub state: Vec<u8>,
+}
+
+impl Slot {
+    /// Slot `number` of the ROM at `rom_path` sits next to it, as
+    /// `game.ss1` for `game.gb`.
+    pub fn path(rom_path: &Path, number: u8) -> PathBuf {
+        rom_path.with_extension(format!("ss{}", number))
+    }
+
+    /// The header and timestamp, then the compressed thumbnail and state.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut writer = StateWriter::with_header(MAGIC, VERSION);
+        writer.write_u64(self.timestamp);
+        writer.write_u32(self.rom_checksum);
+        let thumbnail: Vec<u8> = self.thumbnail.iter().flat_map(|color| color.to_le_bytes()).collect();
+        writer.write_vec(&compression::compress(&thumbnail));
+        writer.write_vec(&compression::compress(&self.state));
+        writer.into_bytes()
+    }
+
+    pub fn decode(bytes: &[u8]) -> io::Result<Slot> {
+        let mut reader = StateReader::with_header(bytes, MAGIC, VERSION)?;
+        let timestamp = reader.read_u64()?;
+        let rom_checksum = reader.read_u32()?;
+        let thumbnail = compression::decompress(&reader.read_vec()?)?;
+        if thumbnail.len() != SCREEN_WIDTH * SCREEN_HEIGHT * 2 {
+            return Err(state::invalid("save slot has a thumbnail of the wrong size"));
+        }
+        let thumbnail = thumbnail.chunks_exact(2).map(|color| u16::from_le_bytes([color[0], color[1]])).collect();
+        let state = compression::decompress(&reader.read_vec()?)?;
+        if !reader.is_finished() {
+            return Err(state::invalid("save slot has trailing data"));
+        }
+        Ok(Slot { timestamp, rom_checksum, thumbnail, state })
+    }
+
+    pub fn read(path: &Path) -> io::Result<Slot> {
+        Slot::decode(&fs::read(path)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+}
@@ -0,0 +1,176 @@
+use std::io::{self, ErrorKind};
+
+/// Every save state starts with these bytes, then the format version.
+pub const MAGIC: [u8; 4] = *b"GBSS";
+/// Bumped whenever a component changes what it saves, so that states from
+/// another version are rejected instead of misread.
+pub const VERSION: u16 = 1;
+
+pub fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Builds a save state: the header, then each component's fields in the
+/// order the component writes them, little-endian.
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        StateWriter::with_header(MAGIC, VERSION)
+    }
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        StateWriter::default()
+    }
+
+    /// A writer for another file built the same way, like a save slot, with
+    /// its own magic and version.
+    pub fn with_header(magic: [u8; 4], version: u16) -> Self {
+        let mut writer = StateWriter { bytes: Vec::new() };
+        writer.write_bytes(&magic);
+        writer.write_u16(version);
+        writer
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.write_bytes(&value.to_le_bytes());
+    }
+
+    /// Bytes whose length the reader knows in advance.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn write_u16s(&mut self, values: &[u16]) {
+        for &value in values {
+            self.write_u16(value);
+        }
+    }
+
+    /// Bytes preceded by their length, for data whose size varies.
+    pub fn write_vec(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+}
+
+/// Reads back a save state written by `StateWriter`. Every read fails with
+/// `InvalidData` past the end.
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    /// Check the header. States from another format version are refused.
+    pub fn new(bytes: &'a [u8]) -> io::Result<Self> {
+        StateReader::with_header(bytes, MAGIC, VERSION)
+    }
+
+    /// Check the header of a file written with `StateWriter::with_header`,
+    /// with the same magic and version.
+    pub fn with_header(bytes: &'a [u8], magic: [u8; 4], version: u16) -> io::Result<Self> {
+        let mut reader = StateReader { bytes, position: 0 };
+        let mut found = [0; 4];
+        reader.read_bytes(&mut found).map_err(|_| invalid("not a save state"))?;
+        if found != magic {
+            return Err(invalid("not a save state"));
+        }
+        if reader.read_u16()? != version {
+            return Err(invalid("save state from an unsupported version"));
+        }
+        Ok(reader)
+    }
+
+    /// Whether every byte has been read.
+    pub fn is_finished(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> io::Result<&'a [u8]> {
+        let end = self.position.checked_add(count).filter(|&end| end <= self.bytes.len());
+        let end = end.ok_or_else(|| invalid("save state is truncated"))?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> io::Result<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("save state has an invalid flag")),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> io::Result<u16> {
+        let mut bytes = [0; 2];
+        self.read_bytes(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn read_u32(&mut self) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.read_bytes(&mut bytes)?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    pub fn read_u64(&mut self) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Fill `bytes` entirely.
+    pub fn read_bytes(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        bytes.copy_from_slice(self.take(bytes.len())?);
+        Ok(())
+    }
+
+    pub fn read_u16s(&mut self, values: &mut [u16]) -> io::Result<()> {
+        for value in values {
+            *value = self.read_u16()?;
+        }
+        Ok(())
+    }
+
+    /// Bytes written with `write_vec`.
+    pub fn read_vec(&mut self) -> io::Result<Vec<u8>> {
+        let length = self.read_u32()? as usize;
+        Ok(self.take(length)?.to_vec())
+    }
+
+    /// A value that must be below `count`, for enums written as an index.
+    pub fn read_index(&mut self, count: usize) -> io::Result<usize> {
+        let index = self.read_u8()? as usize;
+        if index < count { Ok(index) } else { Err(invalid("save state has an invalid value")) }
+    }
+}
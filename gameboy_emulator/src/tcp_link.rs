@@ -0,0 +1,193 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream, ToSocketAddrs};
+use std::thread;
+
+use crate::serial::SerialEndpoint;
+
+/// Sent by the side driving the clock, carrying its outgoing byte.
+const MESSAGE_TRANSFER: u8 = 0x01;
+/// Answer to a transfer, carrying the byte shifted out by the other side.
+const MESSAGE_REPLY: u8 = 0x02;
+/// The earliest cycle the sender can still send a transfer at.
+const MESSAGE_HORIZON: u8 = 0x03;
+/// Kind, cycle and value.
+const MESSAGE_SIZE: usize = 10;
+
+/// Link cable between two emulator processes over TCP.
+///
+/// Both sides run in lockstep on emulated time, counted in the cycles their
+/// serial ports have run. Every exchange is a `TRANSFER` message from the
+/// side driving the clock, stamped with its cycle count and answered by
+/// exactly one `REPLY`: once the other side has run up to that cycle, with
+/// its SB if it waits on an external clock then, or with 0xFF if it doesn't
+/// listen. For that, neither side runs past the earliest cycle the other can
+/// still send a transfer at, which they tell each other in `HORIZON` messages
+/// whenever one of them has to wait. What each side receives thus depends on
+/// what both run, never on how fast they run or how slow the connection is.
+/// Only a closed connection ends a wait early, with 0xFF.
+///
+/// A transfer stamped with the very cycle the other side is at is answered
+/// by the joining side on that step, and by the hosting side on its next.
+pub struct TcpLink {
+    stream: Option<TcpStream>,
+    /// Whether this side accepted the connection, which breaks ties.
+    host: bool,
+    /// Cycles run by this side.
+    cycles: u64,
+    /// The earliest cycle this side can send a transfer at, as last sent.
+    sent_horizon: u64,
+    /// The earliest cycle the other side can send a transfer at, as last
+    /// received.
+    remote_horizon: u64,
+    /// A transfer from the other side, as its cycle and byte, until this side
+    /// runs up to that cycle.
+    incoming: Option<(u64, u8)>,
+}
+
+impl TcpLink {
+    /// Wait on 127.0.0.1:`port` for the other emulator to join.
+    pub fn host(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+        TcpLink::accept(&listener)
+    }
+
+    /// Join an emulator hosting on 127.0.0.1:`port`.
+    pub fn join(port: u16) -> io::Result<Self> {
+        TcpLink::connect((Ipv4Addr::LOCALHOST, port))
+    }
+
+    pub fn accept(listener: &TcpListener) -> io::Result<Self> {
+        let (stream, _) = listener.accept()?;
+        TcpLink::from_stream(stream, true)
+    }
+
+    pub fn connect<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        TcpLink::from_stream(TcpStream::connect(address)?, false)
+    }
+
+    /// `host` tells the side that accepted the connection from the one that
+    /// made it; each side of a link must be given a different one.
+    pub fn from_stream(stream: TcpStream, host: bool) -> io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_nonblocking(false)?;
+        Ok(TcpLink { stream: Some(stream), host, cycles: 0, sent_horizon: 0, remote_horizon: 0, incoming: None })
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// Whether this side has run up to `cycle` of the other side.
+    fn reached(&self, cycle: u64) -> bool {
+        if self.host { self.cycles > cycle } else { self.cycles >= cycle }
+    }
+
+    /// The other side's transfer, as its cycle and byte, once it is due.
+    fn take_due(&mut self) -> Option<(u64, u8)> {
+        match self.incoming {
+            Some((cycle, _)) if self.reached(cycle) => self.incoming.take(),
+            _ => None,
+        }
+    }
+
+    fn send(&mut self, kind: u8, cycle: u64, value: u8) {
+        let Some(stream) = self.stream.as_mut() else {
+            return;
+        };
+        let mut message = [0u8; MESSAGE_SIZE];
+        message[0] = kind;
+        message[1..9].copy_from_slice(&cycle.to_le_bytes());
+        message[9] = value;
+
+        let mut written = 0;
+        while written < MESSAGE_SIZE {
+            match stream.write(&message[written..]) {
+                Ok(0) => {
+                    self.stream = None;
+                    return;
+                }
+                Ok(count) => written += count,
+                // The send buffer is full, until the other side reads.
+                Err(error) if error.kind() == ErrorKind::WouldBlock => thread::yield_now(),
+                Err(error) if error.kind() == ErrorKind::Interrupted => {}
+                Err(_) => {
+                    self.stream = None;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Wait for the next message. `None` means the connection was closed.
+    fn receive(&mut self) -> Option<(u8, u64, u8)> {
+        let mut message = [0u8; MESSAGE_SIZE];
+        if self.stream.as_mut()?.read_exact(&mut message).is_err() {
+            self.stream = None;
+            return None;
+        }
+        let cycle = u64::from_le_bytes(message[1..9].try_into().expect("8 bytes"));
+        Some((message[0], cycle, message[9]))
+    }
+
+    /// Take in a message that isn't a reply.
+    fn handle(&mut self, kind: u8, cycle: u64, value: u8) {
+        match kind {
+            MESSAGE_HORIZON => self.remote_horizon = cycle,
+            // The other side waits at that cycle for the reply.
+            MESSAGE_TRANSFER => {
+                self.remote_horizon = cycle;
+                self.incoming = Some((cycle, value));
+            }
+            _ => {}
+        }
+    }
+}
+
+impl SerialEndpoint for TcpLink {
+    fn transfer(&mut self, outgoing: u8) -> u8 {
+        // Both sides drove the clock at once: neither was listening.
+        if let Some((remote, _)) = self.take_due() {
+            self.send(MESSAGE_REPLY, remote, 0xFF);
+        }
+        let cycle = self.cycles;
+        self.send(MESSAGE_TRANSFER, cycle, outgoing);
+
+        while let Some((kind, replied, value)) = self.receive() {
+            if kind == MESSAGE_REPLY && replied == cycle {
+                return value;
+            }
+            self.handle(kind, replied, value);
+        }
+        0xFF
+    }
+
+    fn poll_external(&mut self, outgoing: u8) -> Option<u8> {
+        let (remote, incoming) = self.take_due()?;
+        self.send(MESSAGE_REPLY, remote, outgoing);
+        Some(incoming)
+    }
+
+    /// Answer the other side's transfer with 0xFF, as this side isn't
+    /// listening.
+    fn idle(&mut self) {
+        if let Some((remote, _)) = self.take_due() {
+            self.send(MESSAGE_REPLY, remote, 0xFF);
+        }
+    }
+
+    /// Wait for the other side while it may still send a transfer due by
+    /// now, telling it how far this side can go meanwhile.
+    fn advance(&mut self, cycles: u32, until_transfer: u32) {
+        self.cycles += cycles as u64;
+        let horizon = self.cycles + until_transfer as u64;
+        while self.incoming.is_none() && self.reached(self.remote_horizon) && self.is_connected() {
+            if self.sent_horizon != horizon {
+                self.send(MESSAGE_HORIZON, horizon, 0x00);
+                self.sent_horizon = horizon;
+            }
+            if let Some((kind, cycle, value)) = self.receive() {
+                self.handle(kind, cycle, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,200 @@
+use crate::emulator::Emulator;
+use crate::joypad::Button;
+use crate::ppu::{color_to_rgb, FRAME_RATE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Upper half block: the top pixel is drawn in the foreground color, the
+/// bottom one in the background color.
+const HALF_BLOCK: char = '\u{2580}';
+const ENTER_SCREEN: &str = "\x1b[?1049h\x1b[?25l\x1b[2J";
+const LEAVE_SCREEN: &str = "\x1b[0m\x1b[?25h\x1b[?1049l";
+/// Terminals only report key presses, repeated while the key is held, so a
+/// key counts as held until this many frames without a repeat. About 670 ms,
+/// longer than the usual 250-600 ms before the first repeat, so a held key
+/// isn't let go of in between.
+const HOLD_FRAMES: u64 = 40;
+const CTRL_C: u8 = 0x03;
+const ESCAPE: u8 = 0x1B;
+
+pub const KEYS: &str = "arrows: D-pad, x: A, z: B, enter: Start, space: Select, q: quit";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Key {
+    Button(Button),
+    Quit,
+}
+
+/// Turn the bytes typed into keys, ignoring those that mean nothing here.
+pub fn decode_keys(bytes: &[u8]) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut index = 0;
+    while index < bytes.len() {
+        let key = match bytes[index] {
+            // Arrows are sent as ESC [ A-D, or ESC O A-D in application mode.
+            ESCAPE if matches!(bytes.get(index + 1), Some(b'[' | b'O')) => {
+                index += 2;
+                match bytes.get(index) {
+                    Some(b'A') => Some(Key::Button(Button::Up)),
+                    Some(b'B') => Some(Key::Button(Button::Down)),
+                    Some(b'C') => Some(Key::Button(Button::Right)),
+                    Some(b'D') => Some(Key::Button(Button::Left)),
+                    _ => None,
+                }
+            }
+            b'x' | b'X' => Some(Key::Button(Button::A)),
+            b'z' | b'Z' => Some(Key::Button(Button::B)),
+            b'\r' | b'\n' => Some(Key::Button(Button::Start)),
+            b' ' => Some(Key::Button(Button::Select)),
+            b'q' | b'Q' | CTRL_C => Some(Key::Quit),
+            _ => None,
+        };
+        keys.extend(key);
+        index += 1;
+    }
+    keys
+}
+
+/// Draw `frame` with one half block per two pixels, in 24-bit color. Only the
+/// rows of blocks that differ from `previous` are drawn again.
+pub fn render(frame: &[u16], previous: Option<&[u16]>) -> String {
+    let mut output = String::new();
+    for row in 0..SCREEN_HEIGHT / 2 {
+        let pixels = 2 * row * SCREEN_WIDTH..(2 * row + 2) * SCREEN_WIDTH;
+        if previous.is_some_and(|previous| previous[pixels.clone()] == frame[pixels.clone()]) {
+            continue;
+        }
+
+        let _ = write!(output, "\x1b[{};1H", row + 1);
+        let mut colors = None;
+        for x in 0..SCREEN_WIDTH {
+            let top = frame[pixels.start + x];
+            let bottom = frame[pixels.start + SCREEN_WIDTH + x];
+            if colors != Some((top, bottom)) {
+                let [top_red, top_green, top_blue] = color_to_rgb(top);
+                let [bottom_red, bottom_green, bottom_blue] = color_to_rgb(bottom);
+                let _ = write!(
+                    output,
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    top_red, top_green, top_blue, bottom_red, bottom_green, bottom_blue
+                );
+                colors = Some((top, bottom));
+            }
+            output.push(HALF_BLOCK);
+        }
+        output.push_str("\x1b[0m");
+    }
+    output
+}
+
+/// Run `emulator` in the terminal in real time, times `speed`, until quit
+/// or until `frames` frames if given. `after_frame` sees every frame. The
+/// terminal needs 160 columns and 73 rows; without one, on stdin and stdout,
+/// nothing is run.
+pub fn run(
+    emulator: &mut Emulator,
+    speed: f64,
+    frames: Option<u64>,
+    mut after_frame: impl FnMut(&Emulator) -> io::Result<()>,
+) -> io::Result<()> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return Err(io::Error::other("the screen is drawn in a terminal, so run in one or use --headless"));
+    }
+
+    let _raw_mode = RawMode::enable()?;
+    let keys = spawn_key_reader();
+    let mut stdout = io::stdout().lock();
+    write!(stdout, "{}\x1b[{};1H{}", ENTER_SCREEN, SCREEN_HEIGHT / 2 + 1, KEYS)?;
+
+    let frame_time = Duration::from_secs_f64(1.0 / (FRAME_RATE * speed));
+    let start = Instant::now();
+    // The frame each button stops being held on.
+    let mut held: [Option<u64>; 8] = [None; 8];
+    let mut previous: Option<Vec<u16>> = None;
+    let mut frame = 0;
+    while frames.is_none_or(|limit| frame < limit) {
+        for key in keys.try_iter().flat_map(|bytes| decode_keys(&bytes)) {
+            match key {
+                Key::Quit => return Ok(()),
+                Key::Button(button) => {
+                    if held[button as usize].is_none() {
+                        emulator.cpu.press(button);
+                    }
+                    held[button as usize] = Some(frame + HOLD_FRAMES);
+                }
+            }
+        }
+        for button in Button::ALL {
+            if held[button as usize].is_some_and(|until| until <= frame) {
+                held[button as usize] = None;
+                emulator.cpu.release(button);
+            }
+        }
+
+        emulator.run_frame();
+        frame += 1;
+        after_frame(emulator)?;
+
+        let current = emulator.cpu.ppu.frame();
+        stdout.write_all(render(current, previous.as_deref()).as_bytes())?;
+        stdout.flush()?;
+        previous = Some(current.to_vec());
+
+        let deadline = start + frame_time.mul_f64(frame as f64);
+        thread::sleep(deadline.saturating_duration_since(Instant::now()));
+    }
+    Ok(())
+}
+
+/// Bytes typed, read on their own thread so the emulator never waits for
+/// them. The thread ends with the program.
+fn spawn_key_reader() -> Receiver<Vec<u8>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut stdin = io::stdin();
+        let mut buffer = [0; 64];
+        while let Ok(count @ 1..) = stdin.read(&mut buffer) {
+            if sender.send(buffer[..count].to_vec()).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+/// Keys are delivered as typed, without echo or line editing, and Ctrl-C
+/// arrives as a key, until dropped. Done with `stty`, which saves depending
+/// on a terminal library.
+struct RawMode {
+    saved: String,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let saved = stty(&["-g"])?;
+        stty(&["-icanon", "-echo", "-isig", "min", "1", "time", "0"])?;
+        Ok(RawMode { saved: saved.trim().to_string() })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = stty(&[&self.saved]);
+        let mut stdout = io::stdout();
+        let _ = write!(stdout, "{}", LEAVE_SCREEN);
+        let _ = stdout.flush();
+    }
+}
+
+fn stty(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("stty").args(args).stdin(File::open("/dev/tty")?).stderr(Stdio::inherit()).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other("stty failed; the terminal frontend needs a terminal"));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,124 @@
+use crate::bitwise;
+use crate::state::{StateReader, StateWriter};
+use std::io;
+
+pub const DIV_ADDRESS: u16 = 0xFF04;
+pub const TIMA_ADDRESS: u16 = 0xFF05;
+pub const TMA_ADDRESS: u16 = 0xFF06;
+pub const TAC_ADDRESS: u16 = 0xFF07;
+
+const TAC_ENABLE: usize = 2;
+const TAC_CLOCK_MASK: u8 = 0x03;
+const TAC_UNUSED_MASK: u8 = 0xF8;
+
+/// Bit of the internal counter whose falling edge increments TIMA, for each
+/// clock select value: 4096, 262144, 65536 and 16384 Hz.
+const CLOCK_BITS: [usize; 4] = [9, 3, 5, 7];
+
+/// The timer is clocked at 4 cycles per step, the shortest TIMA period being
+/// 16 cycles.
+const CYCLES_PER_STEP: u32 = 4;
+
+/// DIV, TIMA, TMA and TAC. DIV is the upper byte of a 16-bit counter that
+/// runs at the CPU clock, so it ticks twice as fast in CGB double speed mode.
+#[derive(Default)]
+pub struct Timer {
+    counter: u16,
+    pub tima: u8,
+    pub tma: u8,
+    pub tac: u8,
+}
+
+impl Timer {
+    pub fn read_register(&self, address: u16) -> u8 {
+        match address {
+            DIV_ADDRESS => bitwise::get_high(self.counter),
+            TIMA_ADDRESS => self.tima,
+            TMA_ADDRESS => self.tma,
+            TAC_ADDRESS => TAC_UNUSED_MASK | self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    /// Write a timer register. Returns `true` if the write made TIMA
+    /// overflow.
+    pub fn write_register(&mut self, address: u16, value: u8) -> bool {
+        match address {
+            DIV_ADDRESS => self.reset_divider(),
+            TIMA_ADDRESS => {
+                self.tima = value;
+                false
+            }
+            TMA_ADDRESS => {
+                self.tma = value;
+                false
+            }
+            TAC_ADDRESS => {
+                let before = self.timer_bit();
+                self.tac = value & !TAC_UNUSED_MASK;
+                before && !self.timer_bit() && self.increment()
+            }
+            _ => false,
+        }
+    }
+
+    /// The full internal counter, DIV being its upper byte.
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    pub fn set_counter(&mut self, counter: u16) {
+        self.counter = counter;
+    }
+
+    /// Clear the internal counter, as writing DIV or executing STOP does. If
+    /// the bit selected by TAC was set this is a falling edge and TIMA
+    /// increments. Returns `true` if TIMA overflowed.
+    pub fn reset_divider(&mut self) -> bool {
+        let before = self.timer_bit();
+        self.counter = 0;
+        before && self.increment()
+    }
+
+    /// Advance by `cycles` CPU cycles. Returns `true` if TIMA overflowed and
+    /// the timer interrupt should be requested.
+    pub fn step(&mut self, cycles: u32) -> bool {
+        let mut overflow = false;
+        for _ in 0..cycles / CYCLES_PER_STEP {
+            let before = self.timer_bit();
+            self.counter = self.counter.wrapping_add(CYCLES_PER_STEP as u16);
+            if before && !self.timer_bit() {
+                overflow |= self.increment();
+            }
+        }
+        overflow
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_u16(self.counter);
+        state.write_u8(self.tima);
+        state.write_u8(self.tma);
+        state.write_u8(self.tac);
+    }
+
+    pub fn load_state(&mut self, state: &mut StateReader) -> io::Result<()> {
+        self.counter = state.read_u16()?;
+        self.tima = state.read_u8()?;
+        self.tma = state.read_u8()?;
+        self.tac = state.read_u8()? & !TAC_UNUSED_MASK;
+        Ok(())
+    }
+
+    /// The input of the falling edge detector: the selected counter bit,
+    /// gated by the enable bit.
+    fn timer_bit(&self) -> bool {
+        let bit = CLOCK_BITS[(self.tac & TAC_CLOCK_MASK) as usize];
+        bitwise::get_bit(self.tac, TAC_ENABLE) && bitwise::get_bit(self.counter, bit)
+    }
+
+    fn increment(&mut self) -> bool {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = if overflow { self.tma } else { tima };
+        overflow
+    }
+}
@@ -0,0 +1,95 @@
+mod common;
+
+mod batch_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::batch::{ram_dump, Batch, Condition, Inputs};
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::joypad::Button;
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::screenshot::Screenshot;
+    use gameboy_emulator::serial::SerialCapture;
+    use std::fs;
+
+    fn init_emulator(program: &[u8]) -> (Emulator, SerialCapture) {
+        let rom = rom_with_program(program);
+        let mut emulator = Emulator::builder().model(Model::Dmg).rom(rom).build().unwrap();
+        let serial = SerialCapture::new();
+        emulator.cpu.serial.connect(Box::new(serial.clone()));
+        (emulator, serial)
+    }
+
+    /// Copy the buttons half of JOYP to 0xC000 forever.
+    const READ_BUTTONS: [u8; 11] = [0x3E, 0x10, 0xE0, 0x00, 0xF0, 0x00, 0xEA, 0x00, 0xC0, 0x18, 0xF5];
+
+    #[test]
+    fn parses_inputs() {
+        let inputs = Inputs::parse("# title screen\n10 start\n\n12 -\n20 A+right # jump\n").unwrap();
+        assert_eq!(inputs.held(0), 0);
+        assert_eq!(inputs.held(10), Button::Start.mask());
+        assert_eq!(inputs.held(11), Button::Start.mask());
+        assert_eq!(inputs.held(12), 0);
+        assert_eq!(inputs.held(1000), Button::A.mask() | Button::Right.mask());
+
+        for invalid in ["10", "x start", "10 start\n5 a", "10 turbo", "10 a+"] {
+            assert!(Inputs::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_conditions() {
+        assert_eq!(Condition::parse("serial:Passed"), Ok(Condition::Serial("Passed".to_string())));
+        assert_eq!(Condition::parse("memory:0xC000=d7"), Ok(Condition::Memory(0xC000, 0xD7)));
+        assert_eq!(Condition::parse("memory:FF80=1").unwrap().to_string(), "memory:FF80=01");
+        for invalid in ["serial:", "memory:C000", "memory:10000=0", "pc:0150", "Passed"] {
+            assert!(Condition::parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn replays_inputs_until_the_condition_holds() {
+        let (mut emulator, serial) = init_emulator(&READ_BUTTONS);
+        let batch = Batch {
+            frames: 100,
+            inputs: Some(Inputs::parse("5 start").unwrap()),
+            until: Some(Condition::Memory(0xC000, 0xD7)),
+        };
+        let mut seen = 0;
+        let report = batch
+            .run(&mut emulator, &serial, |_| {
+                seen += 1;
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!((report.frames, report.condition_met, seen), (6, true, 6));
+        assert!(emulator.cpu.joypad.is_pressed(Button::Start));
+
+        // Without the input it runs out of frames, with the same frame each
+        // time.
+        let (mut emulator, serial) = init_emulator(&READ_BUTTONS);
+        let unmet = Batch { inputs: None, ..batch }.run(&mut emulator, &serial, |_| Ok(())).unwrap();
+        assert_eq!((unmet.frames, unmet.condition_met), (100, false));
+        assert_eq!(unmet.frame_hash, report.frame_hash);
+    }
+
+    #[test]
+    fn stops_on_serial_output_and_writes_the_outputs() {
+        // LD A, 'P'; LDH (SB), A; LD A, 0x81; LDH (SC), A; JR -2
+        let program = [0x3E, b'P', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE];
+        let (mut emulator, serial) = init_emulator(&program);
+        let batch = Batch { until: Some(Condition::Serial("P".to_string())), ..Batch::default() };
+        let report = batch.run(&mut emulator, &serial, |_| Ok(())).unwrap();
+        assert_eq!((report.frames, report.condition_met), (1, true));
+
+        let directory = std::env::temp_dir().join(format!("batch_tests_{}", std::process::id()));
+        report.write(&directory, &emulator, &serial, &Screenshot::default()).unwrap();
+        assert_eq!(&fs::read(directory.join("screenshot.png")).unwrap()[1..4], b"PNG");
+        assert_eq!(fs::read(directory.join("serial.bin")).unwrap(), b"P");
+        assert_eq!(fs::read(directory.join("ram.bin")).unwrap(), ram_dump(&emulator.cpu));
+        assert_eq!(ram_dump(&emulator.cpu).len(), 0x8000 + 0x7F);
+        assert_eq!(
+            fs::read_to_string(directory.join("report.txt")).unwrap(),
+            format!("frames: 1\ncondition met: yes\nframe hash: {:08X}\n", report.frame_hash)
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+mod common;
+
+mod bess_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::cartridge::{Mbc, RtcMode};
+    use gameboy_emulator::cpu::Register16bit;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+    use std::io::ErrorKind;
+
+    fn rom(title: &str) -> Vec<u8> {
+        // JR -2
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+        // MBC1 with 8 KiB of RAM.
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x02;
+        rom
+    }
+
+    fn init_emulator(model: Model, title: &str) -> Emulator {
+        let mut emulator = Emulator::new(model);
+        emulator.cpu.load_rom(&rom(title));
+        emulator.cpu.skip_boot();
+        emulator
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> usize {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as usize
+    }
+
+    fn block_ids(file: &[u8]) -> Vec<String> {
+        let mut offset = u32_at(file, file.len() - 8);
+        let mut ids = Vec::new();
+        while offset < file.len() - 8 {
+            ids.push(String::from_utf8_lossy(&file[offset..offset + 4]).into_owned());
+            offset += 8 + u32_at(file, offset + 4);
+        }
+        ids
+    }
+
+    fn block<'a>(file: &'a [u8], id: &[u8; 4]) -> Option<&'a [u8]> {
+        let mut offset = u32_at(file, file.len() - 8);
+        while offset < file.len() - 8 {
+            let size = u32_at(file, offset + 4);
+            if &file[offset..offset + 4] == id {
+                return Some(&file[offset + 8..offset + 8 + size]);
+            }
+            offset += 8 + size;
+        }
+        None
+    }
+
+    #[test]
+    fn export_lays_out_blocks_after_the_memory() {
+        let file = init_emulator(Model::Dmg, "GAME").export_bess();
+        assert_eq!(&file[file.len() - 4..], b"BESS");
+        assert_eq!(block_ids(&file), ["NAME", "CORE", "INFO", "MBC ", "END "]);
+
+        let core = u32_at(&file, file.len() - 8) + 8 + u32_at(&file, u32_at(&file, file.len() - 8) + 4);
+        assert_eq!(&file[core..core + 4], b"CORE");
+        assert_eq!(u32_at(&file, core + 4), 0xD0);
+        assert_eq!(&file[core + 12..core + 16], b"GDB ");
+        let dmg0 = init_emulator(Model::Dmg0, "GAME").export_bess();
+        assert_eq!(&block(&dmg0, b"CORE").unwrap()[4..8], b"GD0 ");
+    }
+
+    #[test]
+    fn exported_state_imports_back() {
+        for model in [Model::Dmg0, Model::Dmg, Model::Cgb] {
+            let mut source = init_emulator(model, "GAME");
+            source.cpu.set_register_16bit(Register16bit::BC, 0x1234);
+            source.cpu.set_pc(0x0150);
+            source.cpu.set_memory_8bit(0xC123, 0x56);
+            source.cpu.set_memory_8bit(0x8010, 0x78);
+            source.cpu.set_memory_8bit(0xFF90, 0x9A);
+            source.cpu.set_memory_8bit(0xFF06, 0xBC);
+            source.cpu.set_memory_8bit(0x0000, 0x0A);
+            source.cpu.set_memory_8bit(0x2000, 0x03);
+            source.cpu.set_memory_8bit(0xA000, 0xDE);
+            let file = source.export_bess();
+
+            let mut target = Emulator::new(Model::Cgb);
+            target.cpu.load_rom(&rom("GAME"));
+            target.import_bess(&file).unwrap();
+
+            assert_eq!(target.cpu.model, model);
+            assert_eq!(target.cpu.cgb_mode, source.cpu.cgb_mode);
+            assert_eq!(target.cpu.ppu.dmg_compatibility, source.cpu.ppu.dmg_compatibility);
+            assert_eq!(target.cpu.get_pc(), 0x0150);
+            for register in [Register16bit::AF, Register16bit::BC, Register16bit::DE, Register16bit::HL] {
+                assert_eq!(target.cpu.get_register_16bit(register), source.cpu.get_register_16bit(register));
+            }
+            for address in [0xC123, 0x8010, 0xFF90, 0xFF06, 0xFF40, 0xFF47, 0x4000, 0xA000] {
+                assert_eq!(target.cpu.get_memory_8bit(address), source.cpu.get_memory_8bit(address), "{:04X}", address);
+            }
+            assert_eq!(target.cpu.cartridge.mbc, source.cpu.cartridge.mbc);
+        }
+    }
+
+    #[test]
+    fn clock_is_exported_running_and_latched() {
+        // MBC3 with a clock.
+        let mut rom = rom("GAME");
+        rom[0x147] = 0x0F;
+        let mut emulator = Emulator::builder().rom(rom).host_clock(|| 1_000_000).build().unwrap();
+        emulator.cpu.set_memory_8bit(0x0000, 0x0A);
+        emulator.run_cycles(2 * 4_194_304);
+        emulator.cpu.set_memory_8bit(0x6000, 0x00);
+        emulator.cpu.set_memory_8bit(0x6000, 0x01);
+        emulator.run_cycles(4_194_304);
+
+        let file = emulator.export_bess();
+        let rtc = block(&file, b"RTC ").unwrap();
+        assert_eq!(rtc.len(), 0x30);
+        assert_eq!(u32_at(rtc, 0), 3);
+        assert_eq!(u32_at(rtc, 20), 2);
+        assert_eq!(u64::from_le_bytes(rtc[40..48].try_into().unwrap()), 1_000_000);
+
+        let mut imported = Emulator::builder().rom(emulator.cpu.cartridge.rom.clone()).build().unwrap();
+        imported.import_bess(&file).unwrap();
+        assert_eq!(imported.cpu.cartridge.mbc, emulator.cpu.cartridge.mbc);
+
+        // In real time, the clock counts the host time since the export.
+        let mut imported = Emulator::builder()
+            .rom(emulator.cpu.cartridge.rom.clone())
+            .rtc_mode(RtcMode::RealTime)
+            .host_clock(|| 1_000_000 + 2 * 3600 + 5)
+            .build()
+            .unwrap();
+        imported.import_bess(&file).unwrap();
+        let Mbc::Mbc3 { rtc, latched, .. } = imported.cpu.cartridge.mbc else { panic!("not an MBC3") };
+        assert_eq!(rtc, [8, 0, 2, 0, 0]);
+        assert_eq!(latched, [2, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn states_for_another_game_are_rejected() {
+        let file = init_emulator(Model::Dmg, "GAME").export_bess();
+        let mut emulator = init_emulator(Model::Dmg, "OTHER GAME");
+        emulator.cpu.set_memory_8bit(0xC000, 0x11);
+        let before = emulator.save_state();
+
+        let error = emulator.import_bess(&file).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(emulator.save_state(), before);
+        assert_eq!(emulator.import_bess(b"not a state").unwrap_err().kind(), ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn minimal_states_from_other_emulators_import() {
+        // A WRAM buffer, then only a CORE block, an unknown block and END.
+        let mut file = vec![0x00; 0x8000];
+        file[0x0010] = 0x42;
+        let first_block = file.len();
+
+        let mut core = Vec::new();
+        core.extend_from_slice(&1u16.to_le_bytes());
+        core.extend_from_slice(&1u16.to_le_bytes());
+        core.extend_from_slice(b"CCA ");
+        for value in [0x0200u16, 0x1180, 0x0000, 0xFF56, 0x000D, 0xFFFE] {
+            core.extend_from_slice(&value.to_le_bytes());
+        }
+        core.extend_from_slice(&[0x01, 0x05, 0x01, 0x00]);
+        let mut io = [0x00; 0x80];
+        io[0x40] = 0x91;
+        io[0x44] = 0x90;
+        core.extend_from_slice(&io);
+        core.extend_from_slice(&0x8000u32.to_le_bytes());
+        core.extend_from_slice(&0u32.to_le_bytes());
+        core.resize(0xD0, 0x00);
+
+        for (id, data) in [(b"CORE", &core[..]), (b"XYZW", &[1, 2, 3][..]), (b"END ", &[][..])] {
+            file.extend_from_slice(id);
+            file.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            file.extend_from_slice(data);
+        }
+        file.extend_from_slice(&(first_block as u32).to_le_bytes());
+        file.extend_from_slice(b"BESS");
+
+        let mut emulator = Emulator::default();
+        emulator.cpu.load_rom(&rom("GAME"));
+        emulator.import_bess(&file).unwrap();
+        assert_eq!(emulator.cpu.model, Model::Cgb);
+        assert!(emulator.cpu.cgb_mode);
+        assert!(emulator.cpu.halted);
+        assert!(emulator.cpu.ime_flag);
+        assert_eq!(emulator.cpu.get_pc(), 0x0200);
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::AF), 0x1180);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xC010), 0x42);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF44), 0x90);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFFFF), 0x05);
+    }
+}
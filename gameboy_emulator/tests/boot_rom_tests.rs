@@ -0,0 +1,173 @@
+mod common;
+
+mod boot_rom_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::boot_rom::{BootRom, CGB_SIZE, DMG_SIZE};
+    use gameboy_emulator::cartridge::Header;
+    use gameboy_emulator::cpu::Register16bit;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+    use std::fs;
+    use std::io::ErrorKind;
+
+    fn rom(cgb_flag: u8) -> Vec<u8> {
+        // JR -2
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x143] = cgb_flag;
+        rom
+    }
+
+    /// NOPs ending with LD A, 1 and LDH (0x50), A just before 0x0100, the way
+    /// real boot ROMs hand over. `code` goes at 0x0000.
+    fn boot_rom(size: usize, code: &[u8]) -> BootRom {
+        let mut data = vec![0x00; size];
+        data[..code.len()].copy_from_slice(code);
+        data[0xFC..0x100].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        BootRom::new(&data).unwrap()
+    }
+
+    fn run_boot(emulator: &mut Emulator) {
+        emulator.run_until(|emulator| emulator.cpu.get_pc() == 0x0100);
+    }
+
+    #[test]
+    fn boot_rom_is_mapped_until_ff50_is_written() {
+        let mut emulator = Emulator::default();
+        emulator.cpu.load_rom(&rom(0x00));
+        emulator.cpu.load_boot_rom(boot_rom(DMG_SIZE, &[0x31, 0xFE, 0xFF]));
+        assert_eq!(emulator.cpu.get_pc(), 0x0000);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x0000), 0x31);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x0100), 0x18);
+
+        run_boot(&mut emulator);
+        assert!(emulator.cpu.boot_rom.is_none());
+        assert_eq!(emulator.cpu.get_sp(), 0xFFFE);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x0000), 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF50), 0xFF);
+    }
+
+    #[test]
+    fn cgb_boot_rom_leaves_the_header_visible_and_sets_key0() {
+        let mut emulator = Emulator::default();
+        emulator.cpu.load_boot_rom(boot_rom(CGB_SIZE, &[0x3E, 0x04, 0xE0, 0x4C]));
+        emulator.cpu.load_rom(&rom(0x00));
+        assert_eq!(emulator.cpu.model, Model::Cgb);
+        assert!(emulator.cpu.cgb_mode);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x0143), 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x0200), 0x00);
+
+        run_boot(&mut emulator);
+        assert!(!emulator.cpu.cgb_mode);
+        assert!(emulator.cpu.ppu.dmg_compatibility);
+
+        // KEY0 is locked once the boot ROM is gone.
+        emulator.cpu.set_memory_8bit(0xFF4C, 0x80);
+        assert!(!emulator.cpu.cgb_mode);
+    }
+
+    #[test]
+    fn boot_rom_files_must_have_a_boot_rom_size() {
+        let path = std::env::temp_dir().join(format!("boot_rom_tests_{}.bin", std::process::id()));
+        fs::write(&path, [0x00; 0x200]).unwrap();
+
+        let mut emulator = Emulator::default();
+        let error = emulator.init_boot_rom(&path).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(emulator.cpu.boot_rom.is_none());
+
+        fs::write(&path, [0x00; DMG_SIZE]).unwrap();
+        emulator.init_boot_rom(&path).unwrap();
+        assert!(emulator.cpu.boot_rom.is_some());
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn rom_with_logo(cgb_flag: u8) -> Vec<u8> {
+        let mut rom = rom(cgb_flag);
+        for (index, byte) in rom[0x104..0x134].iter_mut().enumerate() {
+            *byte = index as u8;
+        }
+        rom[0x134..0x13A].copy_from_slice(b"TETRIS");
+        rom[0x14B] = 0x01;
+        rom[0x14D] = 0x0A;
+        rom
+    }
+
+    #[test]
+    fn replacement_boot_rom_ends_in_the_skip_boot_state() {
+        for model in Model::ALL {
+            for cgb_flag in [0x00, 0x80] {
+                let mut booted = Emulator::new(model);
+                booted.cpu.load_rom(&rom_with_logo(cgb_flag));
+                booted.cpu.load_replacement_boot_rom();
+                run_boot(&mut booted);
+
+                let mut skipped = Emulator::new(model);
+                skipped.cpu.load_rom(&rom_with_logo(cgb_flag));
+                skipped.cpu.skip_boot();
+
+                for register in [Register16bit::AF, Register16bit::BC, Register16bit::DE, Register16bit::HL] {
+                    assert_eq!(
+                        booted.cpu.get_register_16bit(register),
+                        skipped.cpu.get_register_16bit(register),
+                        "{:?} {:02X}",
+                        model,
+                        cgb_flag
+                    );
+                }
+                assert_eq!(booted.cpu.get_sp(), 0xFFFE);
+                for (address, _) in model.post_boot_io() {
+                    assert_eq!(
+                        booted.cpu.get_memory_8bit(address),
+                        skipped.cpu.get_memory_8bit(address),
+                        "{:?} {:02X} {:04X}",
+                        model,
+                        cgb_flag,
+                        address
+                    );
+                }
+                assert_eq!(booted.cpu.cgb_mode, skipped.cpu.cgb_mode);
+                assert_eq!(booted.cpu.ppu.dmg_compatibility, skipped.cpu.ppu.dmg_compatibility);
+            }
+        }
+    }
+
+    #[test]
+    fn replacement_boot_program_fits_every_model() {
+        let mut sgb = rom_with_logo(0x00);
+        sgb[0x146] = 0x03;
+        sgb[0x14B] = 0x33;
+        for model in Model::ALL {
+            for rom in [rom_with_logo(0x00), rom_with_logo(0x80), sgb.clone()] {
+                let boot_rom = BootRom::replacement(model, &Header::parse(&rom));
+                // The program ends with JP 0x00FE, followed by nothing until
+                // the handoff, or the end of the file past the CGB's hole.
+                let code = if model.is_cgb() { &boot_rom.data()[0x200..] } else { &boot_rom.data()[..0xFE] };
+                let last = code.iter().rposition(|&byte| byte != 0x00).unwrap();
+                assert_eq!(code.get(last - 1..last + 2), Some(&[0xC3, 0xFE, 0x00][..]), "{:?}", model);
+            }
+        }
+    }
+
+    #[test]
+    fn replacement_boot_rom_shows_the_cartridge_logo() {
+        let mut emulator = Emulator::new(Model::Dmg);
+        emulator.cpu.load_rom(&rom_with_logo(0x00));
+        emulator.cpu.load_replacement_boot_rom();
+        run_boot(&mut emulator);
+
+        // Logo byte 0x01 is the bottom half of tile 1: its top nibble is
+        // blank, its bottom one has the last bit set, doubled to the two
+        // rightmost pixels of rows 6 and 7.
+        let tile = 0x8010;
+        assert_eq!(emulator.cpu.get_memory_8bit(tile + 8), 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(tile + 12), 0x03);
+        assert_eq!(emulator.cpu.get_memory_8bit(tile + 13), 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(tile + 14), 0x03);
+
+        assert_eq!(emulator.cpu.get_memory_8bit(0x9904), 1);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x990F), 12);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x9924), 13);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x992F), 24);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x9910), 0);
+    }
+}
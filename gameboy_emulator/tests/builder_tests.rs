@@ -0,0 +1,161 @@
+mod common;
+
+mod builder_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::builder::{EmulatorBuilder, EmulatorError};
+    use gameboy_emulator::cartridge::RtcMode;
+    use gameboy_emulator::cpu::CPU;
+    use gameboy_emulator::debug::DebugHook;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::slot::Slot;
+    use std::cell::Cell;
+    use std::fs;
+    use std::rc::Rc;
+
+    fn rom(cartridge_type: u8) -> Vec<u8> {
+        // JR -2
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x134..0x138].copy_from_slice(b"GAME");
+        rom[0x147] = cartridge_type;
+        rom
+    }
+
+    #[test]
+    fn builds_a_booted_emulator_from_rom_bytes() {
+        let emulator = Emulator::builder().model(Model::Mgb).rom(rom(0x00)).sample_rate(44_100).build().unwrap();
+        assert_eq!(emulator.cpu.model, Model::Mgb);
+        assert_eq!(emulator.cpu.get_pc(), 0x0100);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x0134), b'G');
+        assert_eq!(emulator.sample_rate, 44_100);
+        assert!(emulator.rom_path.is_none());
+
+        let booting = EmulatorBuilder::new().rom(rom(0x00)).replacement_boot_rom().build().unwrap();
+        assert_eq!(booting.cpu.get_pc(), 0x0000);
+        assert!(booting.cpu.boot_rom.is_some());
+    }
+
+    #[test]
+    fn reads_the_rom_and_boot_rom_from_files() {
+        let directory = std::env::temp_dir().join(format!("builder_tests_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let rom_path = directory.join("game.gb");
+        let boot_rom_path = directory.join("dmg_boot.bin");
+        fs::write(&rom_path, rom(0x00)).unwrap();
+        fs::write(&boot_rom_path, [0x00; 0x100]).unwrap();
+
+        let emulator = Emulator::builder().rom_path(&rom_path).boot_rom_path(&boot_rom_path).build().unwrap();
+        assert_eq!(emulator.rom_path.as_deref(), Some(rom_path.as_path()));
+        assert_eq!(emulator.cpu.model, Model::Dmg);
+        assert_eq!(emulator.cpu.get_pc(), 0x0000);
+
+        let saves = directory.join("saves");
+        fs::create_dir_all(&saves).unwrap();
+        let emulator = Emulator::builder().rom_path(&rom_path).save_directory(&saves).build().unwrap();
+        emulator.save_slot(2).unwrap();
+        assert!(Slot::path(&saves.join("game.gb"), 2).exists());
+        assert!(!Slot::path(&rom_path, 2).exists());
+
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn reports_what_went_wrong() {
+        let missing = std::env::temp_dir().join("builder_tests_missing.gb");
+        match Emulator::builder().rom_path(&missing).build() {
+            Err(EmulatorError::Io { path, .. }) => assert_eq!(path, missing),
+            other => panic!("unexpected {:?}", other.err()),
+        }
+        assert!(matches!(Emulator::builder().build(), Err(EmulatorError::MissingRom)));
+        assert!(matches!(Emulator::builder().rom(Vec::new()).build(), Err(EmulatorError::EmptyRom)));
+
+        let error = Emulator::builder().rom(rom(0x00)).boot_rom([0x00; 0x200]).build().err().unwrap();
+        assert!(matches!(error, EmulatorError::InvalidBootRom { size: 0x200 }));
+        assert!(error.to_string().contains("512"));
+    }
+
+    #[test]
+    fn ram_seed_fills_memory_with_repeatable_noise() {
+        let build = |seed| Emulator::builder().rom(rom(0x00)).ram_seed(seed).build().unwrap();
+        let ram = |emulator: &Emulator| (0xC000..0xC100).map(|address| emulator.cpu.get_memory_8bit(address)).collect::<Vec<_>>();
+
+        let first = ram(&build(1));
+        assert!(first.iter().any(|&byte| byte != 0x00));
+        assert_eq!(ram(&build(1)), first);
+        assert_ne!(ram(&build(2)), first);
+        assert!(ram(&Emulator::builder().rom(rom(0x00)).build().unwrap()).iter().all(|&byte| byte == 0x00));
+    }
+
+    struct Counter {
+        instructions: Rc<Cell<u32>>,
+        frames: Rc<Cell<u32>>,
+    }
+
+    impl DebugHook for Counter {
+        fn before_instruction(&mut self, cpu: &CPU) {
+            assert_eq!(cpu.get_pc() & 0xFF00, 0x0100);
+            self.instructions.set(self.instructions.get() + 1);
+        }
+
+        fn frame_done(&mut self, _cpu: &CPU) {
+            self.frames.set(self.frames.get() + 1);
+        }
+    }
+
+    #[test]
+    fn debug_hooks_see_every_instruction_and_frame() {
+        let instructions = Rc::new(Cell::new(0));
+        let frames = Rc::new(Cell::new(0));
+        let hook = Counter { instructions: instructions.clone(), frames: frames.clone() };
+        let mut emulator = Emulator::builder().rom(rom(0x00)).debug_hook(hook).build().unwrap();
+
+        emulator.run_frame();
+        emulator.run_frame();
+        assert_eq!(frames.get(), 2);
+        assert!(instructions.get() > 1000);
+    }
+
+    /// Latch the clock and read its seconds and minutes.
+    fn read_clock(emulator: &mut Emulator) -> (u8, u8) {
+        emulator.cpu.set_memory_8bit(0x6000, 0x00);
+        emulator.cpu.set_memory_8bit(0x6000, 0x01);
+        emulator.cpu.set_memory_8bit(0x4000, 0x08);
+        let seconds = emulator.cpu.get_memory_8bit(0xA000);
+        emulator.cpu.set_memory_8bit(0x4000, 0x09);
+        (seconds, emulator.cpu.get_memory_8bit(0xA000))
+    }
+
+    #[test]
+    fn emulated_clock_counts_cycles() {
+        // MBC3 with a clock and RAM.
+        let mut emulator = Emulator::builder().rom(rom(0x10)).build().unwrap();
+        emulator.cpu.set_memory_8bit(0x0000, 0x0A);
+        emulator.cpu.set_memory_8bit(0x4000, 0x08);
+        emulator.cpu.set_memory_8bit(0xA000, 58);
+
+        emulator.run_cycles(3 * 4_194_304);
+        assert_eq!(read_clock(&mut emulator), (1, 1));
+
+        // Setting the halt flag stops it.
+        emulator.cpu.set_memory_8bit(0x4000, 0x0C);
+        emulator.cpu.set_memory_8bit(0xA000, 0x40);
+        emulator.run_cycles(2 * 4_194_304);
+        assert_eq!(read_clock(&mut emulator), (1, 1));
+
+        let now = Rc::new(Cell::new(1_000_000));
+        let host_clock = now.clone();
+        let mut real_time = Emulator::builder()
+            .rom(rom(0x10))
+            .rtc_mode(RtcMode::RealTime)
+            .host_clock(move || host_clock.get())
+            .build()
+            .unwrap();
+        assert_eq!(real_time.cpu.cartridge.rtc_mode(), RtcMode::RealTime);
+        real_time.cpu.set_memory_8bit(0x0000, 0x0A);
+        real_time.run_cycles(2 * 4_194_304);
+        assert_eq!(read_clock(&mut real_time), (0, 0));
+
+        now.set(now.get() + 61);
+        assert_eq!(read_clock(&mut real_time), (1, 1));
+    }
+}
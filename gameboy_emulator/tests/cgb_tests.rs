@@ -0,0 +1,175 @@
+mod common;
+
+mod cgb_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::ppu::{DMG_COLORS, SCREEN_WIDTH};
+
+    const RED: u16 = 0x001F;
+    const GREEN: u16 = 0x03E0;
+    const BLUE: u16 = 0x7C00;
+
+    fn init_emulator(cgb_flag: u8) -> Emulator {
+        // JR -2, with the CGB flag in the header.
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x143] = cgb_flag;
+        Emulator::builder().rom(rom).build().unwrap()
+    }
+
+    fn write_palette(emulator: &mut Emulator, spec_address: u16, palette: u8, colors: [u16; 4]) {
+        emulator.cpu.set_memory_8bit(spec_address, 0x80 | (palette * 8));
+        for color in colors {
+            for byte in color.to_le_bytes() {
+                emulator.cpu.set_memory_8bit(spec_address + 1, byte);
+            }
+        }
+    }
+
+    /// A solid tile of color index `color` at tile `index` of the given bank.
+    fn write_solid_tile(emulator: &mut Emulator, bank: u8, index: u16, color: u8) {
+        emulator.cpu.set_memory_8bit(0xFF4F, bank);
+        let low = if color & 1 != 0 { 0xFF } else { 0x00 };
+        let high = if color & 2 != 0 { 0xFF } else { 0x00 };
+        for row in 0..8 {
+            emulator.cpu.set_memory_8bit(0x8000 + index * 16 + row * 2, low);
+            emulator.cpu.set_memory_8bit(0x8000 + index * 16 + row * 2 + 1, high);
+        }
+        emulator.cpu.set_memory_8bit(0xFF4F, 0);
+    }
+
+    fn place_sprite(emulator: &mut Emulator, sprite: u16, x: u8, tile: u8, attributes: u8) {
+        let address = 0xFE00 + sprite * 4;
+        for (offset, value) in [16, x, tile, attributes].into_iter().enumerate() {
+            emulator.cpu.set_memory_8bit(address + offset as u16, value);
+        }
+    }
+
+    #[test]
+    fn cgb_mode_is_selected_from_the_header() {
+        assert!(init_emulator(0x80).cpu.cgb_mode);
+        assert!(init_emulator(0xC0).cpu.cgb_mode);
+        assert!(!init_emulator(0x00).cpu.cgb_mode);
+    }
+
+    #[test]
+    fn vram_banks_are_switched_by_vbk() {
+        let mut emulator = init_emulator(0x80);
+        emulator.cpu.set_memory_8bit(0x8000, 0x11);
+        emulator.cpu.set_memory_8bit(0xFF4F, 0x01);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF4F), 0xFF);
+        emulator.cpu.set_memory_8bit(0x8000, 0x22);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x8000), 0x22);
+        emulator.cpu.set_memory_8bit(0xFF4F, 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x8000), 0x11);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF4F), 0xFE);
+
+        let mut emulator = init_emulator(0x00);
+        emulator.cpu.set_memory_8bit(0x8000, 0x11);
+        emulator.cpu.set_memory_8bit(0xFF4F, 0x01);
+        assert_eq!(emulator.cpu.get_memory_8bit(0x8000), 0x11);
+    }
+
+    #[test]
+    fn wram_banks_are_switched_by_svbk() {
+        let mut emulator = init_emulator(0x80);
+        for bank in 1..8u8 {
+            emulator.cpu.set_memory_8bit(0xFF70, bank);
+            emulator.cpu.set_memory_8bit(0xD000, bank * 0x10);
+        }
+        for bank in 1..8u8 {
+            emulator.cpu.set_memory_8bit(0xFF70, bank);
+            assert_eq!(emulator.cpu.get_memory_8bit(0xD000), bank * 0x10);
+            assert_eq!(emulator.cpu.get_memory_8bit(0xF000), bank * 0x10);
+        }
+
+        // Bank 0 can't be mapped at 0xD000 and selects bank 1 instead.
+        emulator.cpu.set_memory_8bit(0xFF70, 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF70), 0xF9);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xD000), 0x10);
+    }
+
+    #[test]
+    fn palette_ram_auto_increments() {
+        let mut emulator = init_emulator(0x80);
+        write_palette(&mut emulator, 0xFF68, 1, [RED, GREEN, BLUE, 0x7FFF]);
+
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF68), 0xC0 | 16);
+        assert_eq!(emulator.cpu.ppu.bg_palettes.color(1, 2), BLUE);
+
+        emulator.cpu.set_memory_8bit(0xFF68, 8 + 2);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF69), GREEN.to_le_bytes()[0]);
+    }
+
+    #[test]
+    fn background_uses_attribute_map_palette_and_bank() {
+        let mut emulator = init_emulator(0x80);
+        write_palette(&mut emulator, 0xFF68, 0, [RED; 4]);
+        write_palette(&mut emulator, 0xFF68, 3, [0x0000, GREEN, BLUE, 0x7FFF]);
+        write_solid_tile(&mut emulator, 1, 0, 2);
+
+        // Tile (0, 0) of the map: palette 3, tile data from bank 1.
+        emulator.cpu.set_memory_8bit(0xFF4F, 1);
+        emulator.cpu.set_memory_8bit(0x9800, 0x08 | 0x03);
+        emulator.cpu.set_memory_8bit(0xFF4F, 0);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x91);
+
+        emulator.run_frame();
+        let frame = emulator.cpu.ppu.frame();
+        assert_eq!(frame[0], BLUE);
+        assert_eq!(frame[7 * SCREEN_WIDTH + 7], BLUE);
+        assert_eq!(frame[8], RED);
+    }
+
+    #[test]
+    fn sprite_priority_follows_oam_order_on_cgb_and_x_on_dmg() {
+        let mut emulator = init_emulator(0x80);
+        write_palette(&mut emulator, 0xFF6A, 0, [0x0000, RED, RED, RED]);
+        write_palette(&mut emulator, 0xFF6A, 1, [0x0000, GREEN, GREEN, GREEN]);
+        write_solid_tile(&mut emulator, 0, 1, 3);
+        place_sprite(&mut emulator, 0, 20, 1, 0x00);
+        place_sprite(&mut emulator, 1, 16, 1, 0x01);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x93);
+
+        emulator.run_frame();
+        assert_eq!(emulator.cpu.ppu.frame()[13], RED);
+
+        let mut emulator = init_emulator(0x00);
+        emulator.cpu.set_memory_8bit(0xFF47, 0xE4);
+        emulator.cpu.set_memory_8bit(0xFF48, 0xE4);
+        emulator.cpu.set_memory_8bit(0xFF49, 0x1B);
+        write_solid_tile(&mut emulator, 0, 1, 3);
+        place_sprite(&mut emulator, 0, 20, 1, 0x00);
+        place_sprite(&mut emulator, 1, 16, 1, 0x10);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x93);
+
+        emulator.run_frame();
+        assert_eq!(emulator.cpu.ppu.shades()[13], 0);
+        assert_eq!(emulator.cpu.ppu.frame()[13], DMG_COLORS[0]);
+        assert_eq!(emulator.cpu.ppu.shades()[16], 3);
+    }
+
+    #[test]
+    fn bg_attribute_priority_hides_sprites() {
+        let mut emulator = init_emulator(0x80);
+        write_palette(&mut emulator, 0xFF68, 0, [0x0000, BLUE, BLUE, BLUE]);
+        write_palette(&mut emulator, 0xFF6A, 0, [0x0000, RED, RED, RED]);
+        write_solid_tile(&mut emulator, 0, 0, 1);
+        write_solid_tile(&mut emulator, 0, 1, 3);
+        emulator.cpu.set_memory_8bit(0xFF4F, 1);
+        emulator.cpu.set_memory_8bit(0x9800, 0x80);
+        emulator.cpu.set_memory_8bit(0xFF4F, 0);
+        place_sprite(&mut emulator, 0, 8, 1, 0x00);
+        place_sprite(&mut emulator, 1, 16, 1, 0x00);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x93);
+
+        emulator.run_frame();
+        assert_eq!(emulator.cpu.ppu.frame()[0], BLUE);
+        assert_eq!(emulator.cpu.ppu.frame()[8], RED);
+
+        // With LCDC bit 0 cleared, sprites always win.
+        emulator.cpu.set_memory_8bit(0xFF40, 0x92);
+        emulator.run_frame();
+        emulator.run_frame();
+        assert_eq!(emulator.cpu.ppu.frame()[0], RED);
+    }
+}
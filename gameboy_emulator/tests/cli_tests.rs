@@ -0,0 +1,173 @@
+mod common;
+
+mod cli_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::batch::Condition;
+    use gameboy_emulator::cli::{self, BootOption, Command, Options};
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+    use gameboy_emulator::screenshot::{DmgPalette, Screenshot};
+    use std::fs;
+    use std::process;
+    use std::path::PathBuf;
+
+    fn parse(args: &[&str]) -> Result<Command, String> {
+        cli::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    fn options(args: &[&str]) -> Options {
+        match parse(args) {
+            Ok(Command::Run(options)) => *options,
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parses_options_in_both_forms() {
+        let parsed = options(&["--model", "CGB", "game.gb", "-b=builtin", "--headless", "-f", "600", "--speed=2.5"]);
+        assert_eq!(parsed.rom, PathBuf::from("game.gb"));
+        assert_eq!(parsed.model, Some(Model::Cgb));
+        assert_eq!(parsed.boot, BootOption::Builtin);
+        assert!(parsed.headless);
+        assert_eq!(parsed.frames, Some(600));
+        assert_eq!(parsed.speed, 2.5);
+
+        let defaults = options(&["-t", "trace.txt", "game.gb", "--boot-rom", "dmg_boot.bin"]);
+        assert_eq!(defaults.boot, BootOption::Path("dmg_boot.bin".into()));
+        assert_eq!(defaults.trace, Some("trace.txt".into()));
+        assert_eq!((defaults.model, defaults.headless, defaults.frames, defaults.speed), (None, false, None, 1.0));
+
+        let batch = options(&["game.gb", "--headless", "-u", "serial:Passed", "-i", "keys.txt", "-o=out"]);
+        assert_eq!(batch.until, Some(Condition::Serial("Passed".to_string())));
+        assert_eq!((batch.frames, batch.input, batch.output), (None, Some("keys.txt".into()), Some("out".into())));
+
+        let screenshot = options(&["game.gb", "-s", "shot.png", "--palette", "pea-soup", "--scale=4"]);
+        assert_eq!(screenshot.screenshot_format, Screenshot { palette: DmgPalette::PeaSoup, scale: 4 });
+        let gif = options(&["game.gb", "-g", "run.gif", "--gif-skip", "3"]);
+        assert_eq!((gif.gif, gif.gif_skip), (Some("run.gif".into()), 3));
+        assert_eq!(defaults.gif_skip, 1);
+
+        assert_eq!(parse(&["game.gb", "--help"]), Ok(Command::Help));
+        assert_eq!(parse(&["-h"]), Ok(Command::Help));
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        for args in [
+            &[][..],
+            &["a.gb", "b.gb"],
+            &["game.gb", "--model", "gba"],
+            &["game.gb", "--frames"],
+            &["game.gb", "--frames", "-1"],
+            &["game.gb", "--speed", "0"],
+            &["game.gb", "--headless"],
+            &["game.gb", "--until", "serial:Passed"],
+            &["game.gb", "--headless", "--until", "pc:0150"],
+            &["game.gb", "--volume", "11"],
+            &["game.gb", "--scale", "0"],
+            &["game.gb", "--palette", "sepia"],
+            &["game.gb", "--record", "-"],
+            &["game.gb", "--gif-skip", "-1"],
+        ] {
+            assert!(parse(args).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn headless_run_writes_the_outputs() {
+        let directory = std::env::temp_dir().join(format!("cli_tests_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let rom_path = directory.join("game.gb");
+        // LDH A, (LY); JR -4
+        let rom = rom_with_program(&[0xF0, 0x44, 0x18, 0xFC]);
+        fs::write(&rom_path, rom).unwrap();
+
+        let run = Options {
+            headless: true,
+            frames: Some(3),
+            screenshot: Some(directory.join("last.png")),
+            record: Some(directory.join("frames.rgb")),
+            trace: Some(directory.join("trace.txt")),
+            ..options(&[rom_path.to_str().unwrap()])
+        };
+        cli::run(&run).unwrap();
+
+        assert_eq!(&fs::read(directory.join("last.png")).unwrap()[1..4], b"PNG");
+        let frame_size = SCREEN_WIDTH * SCREEN_HEIGHT * 3;
+        assert_eq!(fs::read(directory.join("frames.rgb")).unwrap().len(), 3 * frame_size);
+        let trace = fs::read_to_string(directory.join("trace.txt")).unwrap();
+        assert_eq!(
+            trace.lines().take(2).collect::<Vec<_>>(),
+            [
+                "A:01 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:F0,44,18,FC",
+                // LY reads as Gameboy Doctor expects while tracing.
+                "A:90 F:80 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:18,FC,00,00",
+            ]
+        );
+
+        let unmet = Options {
+            until: Some(Condition::Memory(0xC000, 0x01)),
+            output: Some(directory.join("out")),
+            screenshot: None,
+            record: None,
+            trace: None,
+            ..run.clone()
+        };
+        assert!(cli::run(&unmet).is_err());
+        let report = fs::read_to_string(directory.join("out").join("report.txt")).unwrap();
+        assert!(report.starts_with("frames: 3\ncondition met: no\n"));
+
+        let video = Options {
+            frames: Some(3),
+            screenshot: None,
+            record: Some(directory.join("video.y4m")),
+            audio: Some(directory.join("audio.wav")),
+            gif: Some(directory.join("run.gif")),
+            trace: None,
+            ..run.clone()
+        };
+        cli::run(&video).unwrap();
+        let y4m = fs::read(directory.join("video.y4m")).unwrap();
+        assert!(y4m.starts_with(b"YUV4MPEG2 "));
+        let header = y4m.iter().position(|&byte| byte == b'\n').unwrap() + 1;
+        assert_eq!(y4m.len(), header + 3 * (b"FRAME\n".len() + frame_size));
+        // 2410 stereo samples of 16 bits for 3 frames at 48 kHz.
+        assert_eq!(fs::read(directory.join("audio.wav")).unwrap().len(), 44 + 2410 * 4);
+        let gif = fs::read(directory.join("run.gif")).unwrap();
+        assert!(gif.starts_with(b"GIF89a") && gif.ends_with(&[0x3B]));
+
+        let missing = Options { rom: directory.join("missing.gb"), ..run };
+        assert!(cli::run(&missing).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn video_on_stdout_is_only_frames() {
+        let directory = std::env::temp_dir().join(format!("cli_tests_stdout_{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let rom_path = directory.join("game.gb");
+        // DI, EI, JR -4, sending a byte over the link cable first:
+        // LD A, 'X'; LDH (SB), A; LD A, 0x81; LDH (SC), A
+        let rom = rom_with_program(&[0x3E, b'X', 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0xF3, 0xFB, 0x18, 0xFC]);
+        fs::write(&rom_path, rom).unwrap();
+
+        let output = process::Command::new(env!("CARGO_BIN_EXE_gameboy_emulator"))
+            .args([rom_path.to_str().unwrap(), "--headless", "--frames", "2", "--record", "-"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        assert_eq!(output.stdout.len(), 2 * SCREEN_WIDTH * SCREEN_HEIGHT * 3);
+        assert_eq!(output.stderr, b"X");
+
+        // Without --headless it needs a terminal to draw in.
+        let output = process::Command::new(env!("CARGO_BIN_EXE_gameboy_emulator"))
+            .args([rom_path.to_str().unwrap(), "--frames", "2"])
+            .stdin(process::Stdio::null())
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(cli::EXIT_FAILURE));
+        assert!(output.stdout.is_empty());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--headless"));
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
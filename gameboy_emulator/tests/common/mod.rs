@@ -0,0 +1,7 @@
+/// A 32 KiB ROM without a mapper, holding `program` at 0x0100 where the
+/// cartridge starts once the boot ROM is done.
+pub fn rom_with_program(program: &[u8]) -> Vec<u8> {
+    let mut rom = vec![0x00; 0x8000];
+    rom[0x100..0x100 + program.len()].copy_from_slice(program);
+    rom
+}
@@ -0,0 +1,107 @@
+mod common;
+
+mod compatibility_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::cartridge::Header;
+    use gameboy_emulator::compatibility::{self, rgb_to_color, DARK_GREEN, GRAYSCALE};
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::joypad::Button;
+
+    fn rom_with_title(title: &str, old_licensee: u8, new_licensee: &[u8; 2]) -> Vec<u8> {
+        // JR -2
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+        rom[0x144..0x146].copy_from_slice(new_licensee);
+        rom[0x14B] = old_licensee;
+        rom
+    }
+
+    #[test]
+    fn only_nintendo_titles_are_looked_up() {
+        let red = compatibility::for_header(&Header::parse(&rom_with_title("POKEMON RED", 0x01, b"00")));
+        assert_eq!(red.bg, [0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]);
+
+        let new_licensee = compatibility::for_header(&Header::parse(&rom_with_title("POKEMON RED", 0x33, b"01")));
+        assert_eq!(new_licensee, red);
+
+        let other = compatibility::for_header(&Header::parse(&rom_with_title("POKEMON RED", 0x08, b"00")));
+        assert_eq!(other, DARK_GREEN);
+    }
+
+    #[test]
+    fn lookup_goes_by_title_checksum() {
+        // Same bytes in a different order, so the same sum.
+        let red = compatibility::for_header(&Header::parse(&rom_with_title("POKEMON RED", 0x01, b"00")));
+        let anagram = compatibility::for_header(&Header::parse(&rom_with_title("POKEMON DER", 0x01, b"00")));
+        assert_eq!(anagram, red);
+
+        let unknown = compatibility::for_header(&Header::parse(&rom_with_title("HOMEBREW GAME", 0x01, b"00")));
+        assert_eq!(unknown, DARK_GREEN);
+    }
+
+    #[test]
+    fn shared_checksums_go_by_the_fourth_letter() {
+        // Both sum to 0x61.
+        let blue = compatibility::for_header(&Header::parse(&rom_with_title("POKEMON BLUE", 0x01, b"00")));
+        let vegas = compatibility::for_header(&Header::parse(&rom_with_title("VEGAS STAKES", 0x01, b"00")));
+        assert_eq!(blue.bg, [0xFFFFFF, 0x63A5FF, 0x0000FF, 0x000000]);
+        assert_eq!(vegas.bg, [0xFFFFFF, 0x7BFF31, 0x008400, 0x000000]);
+        assert_eq!(vegas.obj1, blue.obj1);
+
+        // Same sum, but a fourth letter the table doesn't list.
+        let neither = compatibility::for_header(&Header::parse(&rom_with_title("VEGSA STAKES", 0x01, b"00")));
+        assert_eq!(neither, DARK_GREEN);
+    }
+
+    #[test]
+    fn titles_get_all_three_palettes_from_the_table() {
+        let mario = compatibility::for_header(&Header::parse(&rom_with_title("SUPER MARIOLAND", 0x01, b"00")));
+        assert_eq!(mario.bg, [0xFFFFFF, 0xADAD84, 0x42737B, 0x000000]);
+        assert_eq!(mario.obj0, [0xFFFFFF, 0xFF7300, 0x944200, 0x000000]);
+        assert_eq!(mario.obj1, [0xFFFFFF, 0x5ABDFF, 0xFF0000, 0x0000FF]);
+
+        let zelda = compatibility::for_header(&Header::parse(&rom_with_title("ZELDA", 0x01, b"00")));
+        assert_eq!(zelda.bg, [0xFFFFFF, 0xFF8484, 0x943A3A, 0x000000]);
+        assert_eq!(zelda.obj0, [0xFFFFFF, 0x00FF00, 0x318400, 0x004A00]);
+    }
+
+    #[test]
+    fn held_buttons_override_the_title() {
+        assert_eq!(compatibility::for_buttons(Button::Left.mask() | Button::B.mask()), Some(GRAYSCALE));
+        assert_eq!(compatibility::for_buttons(Button::Right.mask() | Button::A.mask()), Some(DARK_GREEN));
+        assert_eq!(compatibility::for_buttons(Button::Up.mask() | Button::A.mask() | Button::B.mask()), None);
+        assert_eq!(compatibility::for_buttons(Button::Up.mask() | Button::Down.mask()), None);
+        assert_eq!(compatibility::for_buttons(0), None);
+
+        let header = Header::parse(&rom_with_title("POKEMON RED", 0x01, b"00"));
+        assert_eq!(compatibility::select(&header, Button::Left.mask() | Button::B.mask()), GRAYSCALE);
+    }
+
+    #[test]
+    fn colorized_dmg_game_renders_with_cgb_palettes() {
+        let mut emulator = Emulator::default();
+        emulator.cpu.load_rom(&rom_with_title("POKEMON BLUE", 0x01, b"00"));
+        emulator.cpu.set_sp(0xFFFE);
+        emulator.cpu.colorize();
+        assert!(!emulator.cpu.cgb_mode);
+        assert!(emulator.cpu.ppu.dmg_compatibility);
+
+        // Tile 0 is solid color 1, shown through BGP as shade 2.
+        for row in 0..8 {
+            emulator.cpu.set_memory_8bit(0x8000 + row * 2, 0xFF);
+        }
+        emulator.cpu.set_memory_8bit(0xFF47, 0x08);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x91);
+
+        emulator.run_frame();
+        assert_eq!(emulator.cpu.ppu.shades()[0], 2);
+        assert_eq!(emulator.cpu.ppu.frame()[0], rgb_to_color(0x0000FF));
+    }
+
+    #[test]
+    fn rgb_is_truncated_to_15_bits() {
+        assert_eq!(rgb_to_color(0xFFFFFF), 0x7FFF);
+        assert_eq!(rgb_to_color(0xFF0000), 0x001F);
+        assert_eq!(rgb_to_color(0x0000FF), 0x7C00);
+    }
+}
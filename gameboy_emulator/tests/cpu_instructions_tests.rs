@@ -8,8 +8,9 @@ mod cpu_instructions_tests {
     use std::{path::Path, process::Command};
     use std::fs::File;
     use std::io::Write;
-    use gameboy_emulator::cpu::Register8bit;
     use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::serial::SerialCapture;
 
     const ROMS_FOLDER_PATH: &str = "resources/blargg-test-roms/cpu_instrs/individual";
 
@@ -34,23 +35,7 @@ mod cpu_instructions_tests {
     }
 
     fn init_emulator(rom_path: &Path) -> Emulator {
-        let mut emulator = Emulator::default();
-
-        emulator.cpu.set_register_8bit(Register8bit::A, 0x01);
-        emulator.cpu.set_register_8bit(Register8bit::F, 0xB0);
-        emulator.cpu.set_register_8bit(Register8bit::B, 0x00);
-        emulator.cpu.set_register_8bit(Register8bit::C, 0x13);
-        emulator.cpu.set_register_8bit(Register8bit::D, 0x00);
-        emulator.cpu.set_register_8bit(Register8bit::E, 0xD8);
-        emulator.cpu.set_register_8bit(Register8bit::H, 0x01);
-        emulator.cpu.set_register_8bit(Register8bit::L, 0x4D);
-        emulator.cpu.set_sp(0xFFFE);
-        emulator.cpu.set_pc(0x0100);
-        emulator.cpu.set_memory_8bit(0xFF44, 0x90);
-
-        emulator.init_rom(rom_path);
-
-        emulator
+        Emulator::builder().model(Model::Dmg).rom_path(rom_path).gameboy_doctor().build().unwrap()
     }
 
     fn get_current_log(emulator: &mut Emulator) -> String {
@@ -82,33 +67,28 @@ mod cpu_instructions_tests {
 
 
     pub fn start_game_boy_doctor(emulator: &mut Emulator, rom_number: u8) {
-        let mut serial_output = String::new();
+        let serial_capture = SerialCapture::new();
+        emulator.cpu.serial.connect(Box::new(serial_capture.clone()));
         let mut log_file = File::create(format!("resources/gameboy-doctor/logs/log{rom_number}.txt"))
             .expect("Error opening file");
         let mut log_string = String::new();
 
         loop {
+            let serial_output = serial_capture.text();
             if serial_output.contains("Passed") || serial_output.contains("Failed") {
                 break;
             }
             let max_runs = 1000;
             for _ in 0..max_runs {
                 log_string.push_str(&get_current_log(emulator));
-                emulator.cpu.execute_instruction();
-
-                // blarggs test - serial output
-                if emulator.cpu.memory[0xFF02] == 0x81 {
-                    let c = emulator.cpu.memory[0xFF01] as char;
-                    serial_output.push(c);
-                    emulator.cpu.memory[0xFF02] = 0;
-                }
+                emulator.step();
             }
             log_file.write_all(log_string.as_bytes())
                 .expect("Error writing to file");
             log_string.clear();
         }
 
-        println!("Serial output: {serial_output}");
+        println!("Serial output: {}", serial_capture.text());
     }
     
     fn  print_test_output(test_case: String, gameboy_doctor_output: String) {
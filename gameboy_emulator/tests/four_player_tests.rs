@@ -0,0 +1,89 @@
+mod four_player_tests {
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::four_player::{FourPlayerAdapter, Phase};
+
+    const RECEIVED: u16 = 0xC000;
+    const BYTE_CYCLES: u64 = 16384;
+
+    /// Answers every byte clocked by the adapter with the next byte of
+    /// `replies`, recording what it received at 0xC000.
+    fn init_player(replies: &[u8]) -> Emulator {
+        let mut program = vec![
+            0x21, 0x00, 0xC0, // LD HL,$C000
+            0x11, 0x00, 0x01, // LD DE,$0100
+            0x1A,             // LD A,(DE)
+            0x13,             // INC DE
+            0xE0, 0x01,       // LDH (SB),A
+            0x3E, 0x80,       // LD A,$80
+            0xE0, 0x02,       // LDH (SC),A
+            0xF0, 0x02,       // LDH A,(SC)
+            0xCB, 0x7F,       // BIT 7,A
+            0x20, 0xFA,       // JR NZ,-6
+            0xF0, 0x01,       // LDH A,(SB)
+            0x22,             // LD (HL+),A
+            0x18, 0xED,       // JR -19
+        ];
+        program.resize(0x100, 0x00);
+        program.extend_from_slice(replies);
+
+        let mut emulator = Emulator::default();
+        emulator.cpu.load_rom(&program);
+        emulator.cpu.set_sp(0xFFFE);
+        emulator
+    }
+
+    fn received(emulator: &Emulator, count: u16) -> Vec<u8> {
+        (0..count).map(|i| emulator.cpu.get_memory_8bit(RECEIVED + i)).collect()
+    }
+
+    #[test]
+    fn ping_phase_reports_connected_players() {
+        let players = (0..4).map(|_| init_player(&[0x88; 16])).collect();
+        let mut adapter = FourPlayerAdapter::new(players);
+        adapter.run_cycles(BYTE_CYCLES * 8 + 100);
+
+        for (player, emulator) in adapter.players.iter().enumerate() {
+            let id = player as u8 + 1;
+            assert_eq!(received(emulator, 8), vec![0xFE, id, id, id, 0xFE, 0xF0 | id, 0xF0 | id, 0xF0 | id]);
+        }
+        assert_eq!(adapter.phase(), Phase::Ping);
+    }
+
+    #[test]
+    fn transmission_phase_broadcasts_every_players_packet() {
+        let mut players = vec![init_player(&[
+            0x88, 0x88, 0x00, 0x01, // ping: rate 0, one byte per player
+            0xAA, 0xAA, 0xAA, 0xAA, // start transmission
+            0x00, 0x00, 0x00, 0x00, // transition
+            0x11, 0x00, 0x00, 0x00, // round 1
+            0x12, 0x00, 0x00, 0x00, // round 2
+        ])];
+        for player in 2..=4u8 {
+            players.push(init_player(&[
+                0x88, 0x88, 0x00, 0x00,
+                0x88, 0x88, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+                player << 4 | 0x01, 0x00, 0x00, 0x00,
+                player << 4 | 0x02, 0x00, 0x00, 0x00,
+            ]));
+        }
+        let mut adapter = FourPlayerAdapter::new(players);
+        adapter.run_cycles(BYTE_CYCLES * 12 + 4096 * 8 + 100);
+        assert_eq!(adapter.phase(), Phase::Transmission);
+
+        for emulator in adapter.players.iter() {
+            let bytes = received(emulator, 20);
+            assert_eq!(bytes[8..12], [0xCC; 4]);
+            assert_eq!(bytes[12..16], [0x00; 4]);
+            assert_eq!(bytes[16..20], [0x11, 0x21, 0x31, 0x41]);
+        }
+    }
+
+    #[test]
+    fn missing_players_are_not_reported_connected() {
+        let mut adapter = FourPlayerAdapter::new(vec![init_player(&[0x88; 16]), init_player(&[0x88; 16])]);
+        adapter.run_cycles(BYTE_CYCLES * 8 + 100);
+
+        assert_eq!(received(&adapter.players[1], 8)[5], 0x32);
+    }
+}
@@ -0,0 +1,187 @@
+mod common;
+
+mod gif_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::gif::{lzw_encode, GifRecorder};
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::ppu::{color_to_rgb, SCREEN_HEIGHT, SCREEN_WIDTH};
+    use gameboy_emulator::screenshot::DmgPalette;
+    use gameboy_emulator::sgb::{SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
+    use std::fs;
+
+    /// A plain GIF LZW decoder, to check the encoder against.
+    fn lzw_decode(data: &[u8], min_code_size: u8) -> Vec<u8> {
+        let clear = 1usize << min_code_size;
+        let end = clear + 1;
+        let mut table: Vec<Vec<u8>> = Vec::new();
+        let mut code_size = min_code_size + 1;
+        let mut previous: Option<usize> = None;
+        let mut output = Vec::new();
+        let (mut buffer, mut bits, mut position) = (0u32, 0u8, 0);
+        loop {
+            while bits < code_size {
+                buffer |= (data[position] as u32) << bits;
+                position += 1;
+                bits += 8;
+            }
+            let code = (buffer & ((1 << code_size) - 1)) as usize;
+            buffer >>= code_size;
+            bits -= code_size;
+
+            if code == clear {
+                table = (0..clear).map(|index| vec![index as u8]).chain([vec![], vec![]]).collect();
+                code_size = min_code_size + 1;
+                previous = None;
+                continue;
+            }
+            if code == end {
+                return output;
+            }
+            let entry = match previous {
+                None => table[code].clone(),
+                Some(previous) => {
+                    let entry = if code < table.len() {
+                        table[code].clone()
+                    } else {
+                        let mut entry = table[previous].clone();
+                        entry.push(table[previous][0]);
+                        entry
+                    };
+                    let mut added = table[previous].clone();
+                    added.push(entry[0]);
+                    table.push(added);
+                    if table.len() == 1 << code_size && code_size < 12 {
+                        code_size += 1;
+                    }
+                    entry
+                }
+            };
+            output.extend_from_slice(&entry);
+            previous = Some(code);
+        }
+    }
+
+    struct Frame {
+        delay: u16,
+        colors: Vec<[u8; 3]>,
+        indices: Vec<u8>,
+    }
+
+    /// The frames of a GIF as `GifRecorder` writes them, all `width` by
+    /// `height`.
+    fn parse(bytes: &[u8], width: u16, height: u16) -> Vec<Frame> {
+        assert!(bytes.starts_with(b"GIF89a"));
+        assert_eq!(bytes[6..10], [width.to_le_bytes(), height.to_le_bytes()].concat());
+        assert_eq!(&bytes[16..27], b"NETSCAPE2.0");
+        let mut position = 32;
+        let mut frames = Vec::new();
+        while bytes[position] != 0x3B {
+            assert_eq!(bytes[position..position + 4], [0x21, 0xF9, 0x04, 0x00]);
+            let delay = u16::from_le_bytes([bytes[position + 4], bytes[position + 5]]);
+            position += 8;
+            assert_eq!(bytes[position], 0x2C);
+            assert_eq!(bytes[position + 5..position + 9], bytes[6..10]);
+            let bits = (bytes[position + 9] & 0x07) + 1;
+            position += 10;
+            let colors = bytes[position..position + 3 * (1 << bits)].chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+            position += 3 * (1 << bits);
+            let min_code_size = bytes[position];
+            position += 1;
+            let mut data = Vec::new();
+            while bytes[position] != 0 {
+                let size = bytes[position] as usize;
+                data.extend_from_slice(&bytes[position + 1..position + 1 + size]);
+                position += 1 + size;
+            }
+            position += 1;
+            frames.push(Frame { delay, colors, indices: lzw_decode(&data, min_code_size) });
+        }
+        assert_eq!(position, bytes.len() - 1);
+        frames
+    }
+
+    #[test]
+    fn lzw_round_trips() {
+        let noise: Vec<u8> = (0..40_000u32).map(|index| (index.wrapping_mul(2_654_435_761) >> 24) as u8).collect();
+        let cases: Vec<(Vec<u8>, u8)> = vec![
+            (vec![], 2),
+            (vec![3], 2),
+            (vec![0; 23_040], 2),
+            ((0..23_040).map(|index| (index % 7 % 4) as u8).collect(), 2),
+            (noise.iter().map(|byte| byte & 0x0F).collect(), 4),
+            (noise, 8),
+        ];
+        for (indices, min_code_size) in cases {
+            assert_eq!(lzw_decode(&lzw_encode(&indices, min_code_size), min_code_size), indices);
+        }
+        // Long runs compress well.
+        assert!(lzw_encode(&[0; 23_040], 2).len() < 400);
+    }
+
+    fn init_emulator(model: Model, bgp: u8) -> Emulator {
+        // LD A, bgp; LDH (BGP), A; JR -2
+        let rom = rom_with_program(&[0x3E, bgp, 0xE0, 0x47, 0x18, 0xFE]);
+        Emulator::builder().model(model).rom(rom).build().unwrap()
+    }
+
+    fn record(emulator: &mut Emulator, palette: DmgPalette, frame_skip: u32, frames: u32) -> Vec<Frame> {
+        let path = std::env::temp_dir().join(format!("gif_tests_{}_{}.gif", std::process::id(), frame_skip));
+        let mut gif = GifRecorder::new(fs::File::create(&path).unwrap(), palette, frame_skip).unwrap();
+        for _ in 0..frames {
+            emulator.run_frame();
+            gif.add_frame(&emulator.cpu).unwrap();
+        }
+        assert_eq!(gif.captured(), (frames as u64).div_ceil(frame_skip as u64 + 1));
+        gif.finish().unwrap();
+        let (width, height) = if emulator.cpu.sgb.is_some() {
+            (SGB_SCREEN_WIDTH, SGB_SCREEN_HEIGHT)
+        } else {
+            (SCREEN_WIDTH, SCREEN_HEIGHT)
+        };
+        let frames = parse(&fs::read(&path).unwrap(), width as u16, height as u16);
+        fs::remove_file(path).unwrap();
+        frames
+    }
+
+    #[test]
+    fn records_dmg_frames_in_the_palette_with_frame_skip() {
+        let mut emulator = init_emulator(Model::Dmg, 0xFF);
+        let frames = record(&mut emulator, DmgPalette::PeaSoup, 2, 7);
+        assert_eq!(frames.len(), 3);
+        for frame in &frames {
+            assert_eq!(frame.colors, DmgPalette::PeaSoup.colors());
+            assert_eq!(frame.indices.len(), SCREEN_WIDTH * SCREEN_HEIGHT);
+        }
+        assert!(frames[2].indices.iter().all(|&index| index == 3));
+        // Three frames are 5.02/100 s, the delays add up without drifting.
+        assert_eq!(frames.iter().map(|frame| frame.delay).collect::<Vec<_>>(), [5, 5, 5]);
+
+        let every = record(&mut init_emulator(Model::Dmg, 0xFF), DmgPalette::Grayscale, 0, 6);
+        assert_eq!(every.iter().map(|frame| frame.delay).collect::<Vec<_>>(), [2, 1, 2, 2, 1, 2]);
+    }
+
+    #[test]
+    fn records_color_frames_with_their_own_colors() {
+        let mut emulator = init_emulator(Model::Cgb, 0xFF);
+        let frames = record(&mut emulator, DmgPalette::PeaSoup, 1, 2);
+        let frame = &frames[0];
+        let rgb = emulator.cpu.ppu.frame_rgb();
+        for (pixel, &index) in rgb.chunks(3).zip(&frame.indices) {
+            assert_eq!(pixel, frame.colors[index as usize]);
+        }
+    }
+
+    #[test]
+    fn records_the_whole_sgb_picture() {
+        let mut emulator = init_emulator(Model::Sgb, 0xFF);
+        emulator.cpu.set_sgb_mode(true);
+        let frames = record(&mut emulator, DmgPalette::PeaSoup, 0, 2);
+        let sgb = emulator.cpu.sgb.as_ref().unwrap();
+        let frame = &frames[1];
+        assert_eq!(frame.indices.len(), SGB_SCREEN_WIDTH * SGB_SCREEN_HEIGHT);
+        for (&color, &index) in sgb.frame().iter().zip(&frame.indices) {
+            assert_eq!(color_to_rgb(color), frame.colors[index as usize]);
+        }
+    }
+}
@@ -0,0 +1,138 @@
+mod common;
+
+mod hdma_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::ppu::Mode;
+
+    // LD A,$01 / LDH (HDMA5),A / JR -2
+    const GENERAL_DMA: [u8; 6] = [0x3E, 0x01, 0xE0, 0x55, 0x18, 0xFE];
+
+    fn init_emulator(program: &[u8], cgb_flag: u8) -> Emulator {
+        let mut rom = rom_with_program(program);
+        rom[0x143] = cgb_flag;
+        Emulator::builder().rom(rom).build().unwrap()
+    }
+
+    /// Fill 0xC000 onwards with a counting pattern and point the DMA from
+    /// there to 0x8800.
+    fn prepare_transfer(emulator: &mut Emulator) {
+        for offset in 0..0x100u16 {
+            emulator.cpu.set_memory_8bit(0xC000 + offset, offset as u8);
+        }
+        for (address, value) in [(0xFF51, 0xC0), (0xFF52, 0x0F), (0xFF53, 0x88), (0xFF54, 0x0F)] {
+            emulator.cpu.set_memory_8bit(address, value);
+        }
+    }
+
+    fn copied_blocks(emulator: &Emulator) -> usize {
+        (0..0x100u16)
+            .take_while(|&offset| emulator.cpu.get_memory_8bit(0x8800 + offset) == offset as u8)
+            .count()
+            / 16
+    }
+
+    fn run_lines(emulator: &mut Emulator, lines: u32) {
+        let mut elapsed = 0;
+        while elapsed < lines * 456 {
+            elapsed += emulator.cpu.execute_instruction();
+        }
+    }
+
+    #[test]
+    fn general_dma_copies_everything_and_halts_the_cpu() {
+        let mut emulator = init_emulator(&GENERAL_DMA, 0x80);
+        prepare_transfer(&mut emulator);
+
+        emulator.cpu.execute_instruction();
+        let cycles = emulator.cpu.execute_instruction();
+        assert_eq!(cycles, 12 + 2 * 32);
+        assert_eq!(copied_blocks(&emulator), 2);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn general_dma_takes_as_long_in_double_speed() {
+        let mut emulator = init_emulator(&GENERAL_DMA, 0x80);
+        prepare_transfer(&mut emulator);
+        emulator.cpu.double_speed = true;
+
+        emulator.cpu.execute_instruction();
+        let cycles = emulator.cpu.execute_instruction();
+        assert_eq!(cycles, (12 + 2 * 64) / 2);
+        assert_eq!(copied_blocks(&emulator), 2);
+    }
+
+    #[test]
+    fn hblank_dma_copies_one_block_per_line() {
+        let mut emulator = init_emulator(&[0x18, 0xFE], 0x80);
+        prepare_transfer(&mut emulator);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x80);
+        emulator.cpu.set_memory_8bit(0xFF55, 0x83);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF55), 0x03);
+        assert_eq!(copied_blocks(&emulator), 0);
+
+        run_lines(&mut emulator, 1);
+        assert_eq!(copied_blocks(&emulator), 1);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF55), 0x02);
+
+        run_lines(&mut emulator, 3);
+        assert_eq!(copied_blocks(&emulator), 4);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF55), 0xFF);
+    }
+
+    #[test]
+    fn hblank_dma_can_be_cancelled() {
+        let mut emulator = init_emulator(&[0x18, 0xFE], 0x80);
+        prepare_transfer(&mut emulator);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x80);
+        emulator.cpu.set_memory_8bit(0xFF55, 0x83);
+
+        run_lines(&mut emulator, 1);
+        emulator.cpu.set_memory_8bit(0xFF55, 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF55), 0x82);
+
+        run_lines(&mut emulator, 3);
+        assert_eq!(copied_blocks(&emulator), 1);
+    }
+
+    #[test]
+    fn hblank_dma_copies_a_block_at_once_outside_drawing() {
+        let mut emulator = init_emulator(&[0x18, 0xFE], 0x80);
+        prepare_transfer(&mut emulator);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x00);
+        emulator.cpu.set_memory_8bit(0xFF55, 0x83);
+        assert_eq!(copied_blocks(&emulator), 1);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF55), 0x02);
+
+        let mut emulator = init_emulator(&[0x18, 0xFE], 0x80);
+        prepare_transfer(&mut emulator);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x80);
+        while emulator.cpu.ppu.mode() != Mode::HBlank {
+            emulator.cpu.execute_instruction();
+        }
+        emulator.cpu.set_memory_8bit(0xFF55, 0x83);
+        assert_eq!(copied_blocks(&emulator), 1);
+
+        // The next block waits for the next HBlank.
+        while emulator.cpu.ppu.mode() == Mode::HBlank {
+            emulator.cpu.execute_instruction();
+        }
+        assert_eq!(copied_blocks(&emulator), 1);
+        while emulator.cpu.ppu.mode() != Mode::HBlank {
+            emulator.cpu.execute_instruction();
+        }
+        emulator.cpu.execute_instruction();
+        assert_eq!(copied_blocks(&emulator), 2);
+    }
+
+    #[test]
+    fn hdma_registers_are_unmapped_on_dmg() {
+        let mut emulator = init_emulator(&GENERAL_DMA, 0x00);
+        prepare_transfer(&mut emulator);
+        emulator.cpu.execute_instruction();
+        emulator.cpu.execute_instruction();
+        assert_eq!(copied_blocks(&emulator), 0);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF55), 0xFF);
+    }
+}
@@ -0,0 +1,53 @@
+mod common;
+
+mod joypad_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::joypad::Button;
+
+    fn init_emulator(program: &[u8]) -> Emulator {
+        Emulator::builder().rom(rom_with_program(program)).build().unwrap()
+    }
+
+    #[test]
+    fn selected_keys_read_as_zero_when_pressed() {
+        let mut emulator = init_emulator(&[0x18, 0xFE]);
+        emulator.cpu.press(Button::Down);
+        emulator.cpu.press(Button::A);
+
+        emulator.cpu.set_memory_8bit(0xFF00, 0x20);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00), 0xE7);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x10);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00), 0xDE);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x30);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00), 0xFF);
+
+        emulator.cpu.release(Button::Down);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x20);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00), 0xEF);
+    }
+
+    #[test]
+    fn pressing_a_key_requests_the_joypad_interrupt_once() {
+        let mut emulator = init_emulator(&[0x18, 0xFE]);
+        emulator.cpu.press(Button::Start);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF0F) & 0x10, 0x10);
+
+        emulator.cpu.set_memory_8bit(0xFF0F, 0x00);
+        emulator.cpu.press(Button::Start);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF0F) & 0x10, 0x00);
+    }
+
+    #[test]
+    fn pressing_a_key_wakes_the_cpu_from_stop() {
+        // STOP / JR -2
+        let mut emulator = init_emulator(&[0x10, 0x00, 0x18, 0xFE]);
+        emulator.cpu.execute_instruction();
+        emulator.cpu.execute_instruction();
+        assert!(emulator.cpu.stopped);
+
+        emulator.cpu.press(Button::B);
+        emulator.cpu.execute_instruction();
+        assert!(!emulator.cpu.stopped);
+    }
+}
@@ -0,0 +1,59 @@
+mod common;
+
+mod link_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::link::{LinkedPair, Side};
+
+    const TRANSFER_CYCLES: u64 = 8 * 512;
+
+    // LD A,byte / LDH (SB),A / LD A,control / LDH (SC),A / JR -2
+    fn send_program(byte: u8, control: u8) -> [u8; 10] {
+        [0x3E, byte, 0xE0, 0x01, 0x3E, control, 0xE0, 0x02, 0x18, 0xFE]
+    }
+
+    fn init_emulator(program: &[u8]) -> Emulator {
+        Emulator::builder().rom(rom_with_program(program)).build().unwrap()
+    }
+
+    #[test]
+    fn bytes_are_exchanged_between_both_sides() {
+        let master = init_emulator(&send_program(0x41, 0x81));
+        let slave = init_emulator(&send_program(0x42, 0x80));
+        let mut pair = LinkedPair::new(master, slave);
+
+        let done = pair.run_until(TRANSFER_CYCLES * 2, |pair| {
+            pair.left.cpu.get_memory_8bit(0xFF0F) & 0x08 != 0
+                && pair.right.cpu.get_memory_8bit(0xFF0F) & 0x08 != 0
+        });
+        assert!(done);
+
+        assert_eq!(pair.left.cpu.get_memory_8bit(0xFF01), 0x42);
+        assert_eq!(pair.right.cpu.get_memory_8bit(0xFF01), 0x41);
+        assert_eq!(pair.left.cpu.get_memory_8bit(0xFF02) & 0x80, 0x00);
+        assert_eq!(pair.right.cpu.get_memory_8bit(0xFF02) & 0x80, 0x00);
+    }
+
+    #[test]
+    fn sides_stay_in_lockstep() {
+        let mut pair = LinkedPair::new(init_emulator(&[0x18, 0xFE]), init_emulator(&[0x00, 0x18, 0xFD]));
+        pair.run_cycles(10_000);
+
+        let left = pair.cycles(Side::Left);
+        let right = pair.cycles(Side::Right);
+        assert!(left >= 10_000 && right >= 10_000);
+        assert!(left.abs_diff(right) <= 12);
+    }
+
+    #[test]
+    fn master_reads_ff_when_other_side_is_not_listening() {
+        let master = init_emulator(&send_program(0x41, 0x81));
+        let idle = init_emulator(&[0x18, 0xFE]);
+        let mut pair = LinkedPair::new(master, idle);
+        pair.run_cycles(TRANSFER_CYCLES * 2);
+
+        let (left, right) = pair.disconnect();
+        assert_eq!(left.cpu.get_memory_8bit(0xFF01), 0xFF);
+        assert_eq!(right.cpu.get_memory_8bit(0xFF0F) & 0x08, 0x00);
+    }
+}
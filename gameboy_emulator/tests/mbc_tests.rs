@@ -0,0 +1,134 @@
+mod mbc_tests {
+    use gameboy_emulator::cartridge::Cartridge;
+
+    /// A ROM of `2 << rom_size` banks, each starting with its bank number as
+    /// a little-endian 16-bit value.
+    fn rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+        let banks = 2usize << rom_size;
+        let mut rom = vec![0x00; banks * 0x4000];
+        for bank in 0..banks {
+            rom[bank * 0x4000..bank * 0x4000 + 2].copy_from_slice(&(bank as u16).to_le_bytes());
+        }
+        rom[0x147] = cartridge_type;
+        rom[0x148] = rom_size;
+        rom[0x149] = ram_size;
+        rom
+    }
+
+    fn bank_at(cartridge: &Cartridge, address: u16) -> u16 {
+        u16::from_le_bytes([cartridge.read(address), cartridge.read(address + 1)])
+    }
+
+    #[test]
+    fn mbc1_maps_bank_0_to_bank_1() {
+        let mut cartridge = Cartridge::new(rom(0x01, 0x02, 0x00));
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+        cartridge.write(0x2000, 0x03);
+        assert_eq!(bank_at(&cartridge, 0x4000), 3);
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+        // Only the low 5 bits count, so 0x20 is bank 0 as well.
+        cartridge.write(0x2000, 0x20);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+    }
+
+    #[test]
+    fn mbc1_upper_bits_and_advanced_mode() {
+        let mut cartridge = Cartridge::new(rom(0x03, 0x05, 0x03));
+        cartridge.write(0x4000, 0x01);
+        cartridge.write(0x2000, 0x02);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x22);
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x21);
+        assert_eq!(bank_at(&cartridge, 0x0000), 0x00);
+
+        // Advanced mode applies the upper bits to 0x0000-0x3FFF and to the
+        // RAM bank.
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(bank_at(&cartridge, 0x0000), 0x20);
+        cartridge.write(0xA000, 0x42);
+        cartridge.write(0x6000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x0000), 0x00);
+        assert_eq!(cartridge.read(0xA000), 0x00);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+    }
+
+    #[test]
+    fn mbc2_has_512_half_bytes_of_ram_and_selects_registers_with_a8() {
+        let mut cartridge = Cartridge::new(rom(0x06, 0x02, 0x00));
+        // A8 set selects the ROM bank, clear the RAM enable.
+        cartridge.write(0x0100, 0x05);
+        assert_eq!(bank_at(&cartridge, 0x4000), 5);
+        cartridge.write(0x0000, 0x03);
+        assert_eq!(bank_at(&cartridge, 0x4000), 5);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0xAB);
+        assert_eq!(cartridge.read(0xA000), 0xFB);
+        assert_eq!(cartridge.read(0xA200), 0xFB);
+        assert_eq!(cartridge.read(0xBE00), 0xFB);
+        cartridge.write(0xA1FF, 0x07);
+        assert_eq!(cartridge.read(0xA3FF), 0xF7);
+
+        cartridge.write(0x3100, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 1);
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn mbc5_rom_bank_has_9_bits() {
+        let mut cartridge = Cartridge::new(rom(0x19, 0x08, 0x00));
+        cartridge.write(0x2000, 0x05);
+        cartridge.write(0x3000, 0x01);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x105);
+        cartridge.write(0x2000, 0xFF);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x1FF);
+        // Unlike the others, MBC5 can map bank 0 at 0x4000.
+        cartridge.write(0x2000, 0x00);
+        cartridge.write(0x3000, 0x00);
+        assert_eq!(bank_at(&cartridge, 0x4000), 0x000);
+    }
+
+    #[test]
+    fn mbc3_selects_a_ram_bank_or_a_clock_register() {
+        let mut cartridge = Cartridge::new(rom(0x10, 0x02, 0x03));
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x01);
+        cartridge.write(0xA000, 0x11);
+
+        // 0x08 is the seconds register.
+        cartridge.write(0x4000, 0x08);
+        cartridge.write(0xA000, 30);
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 30);
+
+        cartridge.write(0x4000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 0x11);
+        cartridge.write(0x4000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0x00);
+    }
+
+    #[test]
+    fn mbc3_clock_reads_what_was_latched() {
+        let mut cartridge = Cartridge::new(rom(0x10, 0x02, 0x03));
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x08);
+        assert_eq!(cartridge.read(0xA000), 0);
+
+        // The registers keep the latched time while the clock runs on, and
+        // writing 1 again without a 0 first doesn't latch.
+        cartridge.step(2 * 4_194_304);
+        assert_eq!(cartridge.read(0xA000), 0);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 0);
+
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 2);
+    }
+}
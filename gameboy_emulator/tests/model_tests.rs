@@ -0,0 +1,95 @@
+mod common;
+
+mod model_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::cpu::{Register16bit, Register8bit};
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+
+    fn rom(cgb_flag: u8, sgb_flag: u8, title: &str) -> Vec<u8> {
+        // JR -2
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+        rom[0x143] = cgb_flag;
+        rom[0x146] = sgb_flag;
+        rom[0x14B] = if sgb_flag == 0x03 { 0x33 } else { 0x01 };
+        rom[0x14D] = 0x01;
+        rom
+    }
+
+    fn boot(model: Option<Model>, rom: &[u8]) -> Emulator {
+        let mut emulator = match model {
+            Some(model) => Emulator::new(model),
+            None => Emulator::default(),
+        };
+        emulator.cpu.load_rom(rom);
+        emulator.cpu.skip_boot();
+        emulator
+    }
+
+    #[test]
+    fn dmg_starts_with_the_boot_rom_state() {
+        let emulator = boot(Some(Model::Dmg), &rom(0x00, 0x00, "TEST"));
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::AF), 0x01B0);
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::BC), 0x0013);
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::DE), 0x00D8);
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::HL), 0x014D);
+        assert_eq!(emulator.cpu.get_sp(), 0xFFFE);
+        assert_eq!(emulator.cpu.get_pc(), 0x0100);
+
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF04), 0xAB);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF07), 0xF8);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF40), 0x91);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF47), 0xFC);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF0F), 0xE1);
+    }
+
+    #[test]
+    fn model_follows_the_header_unless_chosen() {
+        let emulator = boot(None, &rom(0x80, 0x00, "TEST"));
+        assert_eq!(emulator.cpu.model, Model::Cgb);
+        assert!(emulator.cpu.cgb_mode);
+        assert_eq!(emulator.cpu.get_register_8bit(Register8bit::A), 0x11);
+
+        let emulator = boot(Some(Model::Mgb), &rom(0x80, 0x00, "TEST"));
+        assert_eq!(emulator.cpu.model, Model::Mgb);
+        assert!(!emulator.cpu.cgb_mode);
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::AF), 0xFFB0);
+    }
+
+    #[test]
+    fn cgb_colorizes_dmg_games() {
+        let title = "TETRIS";
+        let checksum = title.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        let emulator = boot(Some(Model::Cgb), &rom(0x00, 0x00, title));
+        assert!(!emulator.cpu.cgb_mode);
+        assert!(emulator.cpu.ppu.dmg_compatibility);
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::BC), (checksum as u16) << 8);
+
+        // The AGB boot ROM increments B last.
+        let emulator = boot(Some(Model::Agb), &rom(0x00, 0x00, title));
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::BC), (checksum.wrapping_add(1) as u16) << 8);
+    }
+
+    #[test]
+    fn sgb_only_for_cartridges_supporting_it() {
+        let emulator = boot(Some(Model::Sgb), &rom(0x00, 0x03, "TEST"));
+        assert!(emulator.cpu.sgb.is_some());
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00), 0xFF);
+        assert_eq!(emulator.cpu.get_register_16bit(Register16bit::AF), 0x0100);
+
+        let emulator = boot(Some(Model::Sgb2), &rom(0x00, 0x00, "TEST"));
+        assert!(emulator.cpu.sgb.is_none());
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF04), 0xD8);
+    }
+
+    #[test]
+    fn models_are_named() {
+        assert_eq!(Model::from_name("CGB"), Some(Model::Cgb));
+        assert_eq!(Model::from_name("dmg0"), Some(Model::Dmg0));
+        assert_eq!(Model::from_name("gba"), None);
+        for model in Model::ALL {
+            assert_eq!(Model::from_name(model.name()), Some(model));
+        }
+    }
+}
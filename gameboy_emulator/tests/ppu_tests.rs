@@ -0,0 +1,45 @@
+mod common;
+
+mod ppu_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::ppu::{DMG_COLORS, GAMEBOY_DOCTOR_LY};
+
+    fn init_emulator() -> Emulator {
+        // JR -2
+        let rom = rom_with_program(&[0x18, 0xFE]);
+        Emulator::builder().rom(rom).build().unwrap()
+    }
+
+    #[test]
+    fn gameboy_doctor_only_changes_what_ly_reads_as() {
+        let rom = rom_with_program(&[0x18, 0xFE]);
+        let mut emulator = Emulator::builder().rom(rom).gameboy_doctor().build().unwrap();
+        emulator.run_cycles(456 * 3);
+
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF44), GAMEBOY_DOCTOR_LY);
+        assert_eq!(emulator.cpu.ppu.ly, 3);
+
+        emulator.cpu.ppu.set_ly_stub(None);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF44), 3);
+
+        let mut emulator = init_emulator();
+        emulator.run_cycles(456 * 3);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF44), 3);
+    }
+
+    #[test]
+    fn dmg_background_off_is_shade_0_whatever_bgp_says() {
+        let mut emulator = init_emulator();
+        emulator.cpu.set_memory_8bit(0xFF47, 0xFF);
+        emulator.run_frame();
+        emulator.run_frame();
+        assert!(emulator.cpu.ppu.shades().iter().all(|&shade| shade == 3));
+
+        emulator.cpu.set_memory_8bit(0xFF40, 0x90);
+        emulator.run_frame();
+        emulator.run_frame();
+        assert!(emulator.cpu.ppu.shades().iter().all(|&shade| shade == 0));
+        assert!(emulator.cpu.ppu.frame().iter().all(|&color| color == DMG_COLORS[0]));
+    }
+}
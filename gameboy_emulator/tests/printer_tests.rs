@@ -0,0 +1,103 @@
+mod printer_tests {
+    use std::fs;
+    use gameboy_emulator::printer::GameBoyPrinter;
+    use gameboy_emulator::serial::SerialEndpoint;
+
+    const INIT: u8 = 0x01;
+    const PRINT: u8 = 0x02;
+    const DATA: u8 = 0x04;
+    const STATUS: u8 = 0x0F;
+
+    fn packet(command: u8, compressed: bool, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x88, 0x33, command, compressed as u8];
+        packet.extend_from_slice(&(data.len() as u16).to_le_bytes());
+        packet.extend_from_slice(data);
+        let checksum = packet[2..].iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16));
+        packet.extend_from_slice(&checksum.to_le_bytes());
+        packet.extend_from_slice(&[0x00, 0x00]);
+        packet
+    }
+
+    /// Send a packet and return the printer's device ID and status replies.
+    fn send(printer: &mut GameBoyPrinter, packet: &[u8]) -> (u8, u8) {
+        let replies: Vec<u8> = packet.iter().map(|&byte| printer.transfer(byte)).collect();
+        (replies[replies.len() - 2], replies[replies.len() - 1])
+    }
+
+    #[test]
+    fn prints_buffered_tiles_with_palette() {
+        let mut printer = GameBoyPrinter::new();
+        assert_eq!(send(&mut printer, &packet(INIT, false, &[])), (0x81, 0x00));
+
+        // Two rows of black tiles.
+        let (_, status) = send(&mut printer, &packet(DATA, false, &[0xFF; 640]));
+        assert_eq!(status, 0x08);
+        send(&mut printer, &packet(DATA, false, &[]));
+
+        let (_, status) = send(&mut printer, &packet(PRINT, false, &[0x01, 0x13, 0xE4, 0x40]));
+        assert_eq!(status & 0x02, 0x02);
+
+        let images = printer.images();
+        assert_eq!(images.len(), 1);
+        assert_eq!((images[0].width, images[0].height), (160, 16));
+        assert!(images[0].pixels.iter().all(|&pixel| pixel == 0x00));
+
+        let mut status = 0x02;
+        for _ in 0..10 {
+            status = send(&mut printer, &packet(STATUS, false, &[])).1;
+        }
+        assert_eq!(status, 0x00);
+    }
+
+    #[test]
+    fn decompresses_run_length_encoded_data() {
+        let mut printer = GameBoyPrinter::new();
+        send(&mut printer, &packet(INIT, false, &[]));
+
+        // One literal row of color 1, then runs of white for the other 638 bytes.
+        let mut compressed = vec![0x01, 0xFF, 0x00];
+        compressed.extend_from_slice(&[0xFF, 0x00].repeat(4));
+        compressed.extend_from_slice(&[0x80 | 120, 0x00]);
+        send(&mut printer, &packet(DATA, true, &compressed));
+        send(&mut printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+
+        let image = &printer.images()[0];
+        assert_eq!(image.height, 16);
+        assert!(image.pixels[..8].iter().all(|&pixel| pixel == 0xAA));
+        assert!(image.pixels[8..].iter().all(|&pixel| pixel == 0xFF));
+    }
+
+    #[test]
+    fn bad_checksum_is_reported_and_ignored() {
+        let mut printer = GameBoyPrinter::new();
+        let mut bad = packet(DATA, false, &[0xFF; 640]);
+        let checksum = bad.len() - 4;
+        bad[checksum] ^= 0x01;
+
+        let (_, status) = send(&mut printer, &bad);
+        assert_eq!(status, 0x01);
+        send(&mut printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+        assert!(printer.images().is_empty());
+    }
+
+    #[test]
+    fn print_jobs_are_written_as_png() {
+        let output_dir = std::env::temp_dir().join(format!("gb_printer_{}", std::process::id()));
+        fs::create_dir_all(&output_dir).unwrap();
+
+        let mut printer = GameBoyPrinter::with_output_dir(&output_dir);
+        send(&mut printer, &packet(INIT, false, &[]));
+        send(&mut printer, &packet(DATA, false, &[0x00; 640]));
+        send(&mut printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+
+        let png = fs::read(output_dir.join("print_0001.png")).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert!(printer.errors().is_empty());
+
+        // Printing again with nothing buffered writes no image.
+        send(&mut printer, &packet(PRINT, false, &[0x01, 0x00, 0xE4, 0x40]));
+        assert_eq!(printer.images().len(), 1);
+        assert!(!output_dir.join("print_0002.png").exists());
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+}
@@ -0,0 +1,90 @@
+mod recording_tests {
+    use gameboy_emulator::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+    use gameboy_emulator::recording::{
+        rgb_to_yuv444, samples_for_frames, SilentAudio, VideoFormat, VideoWriter, WavWriter,
+    };
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::Path;
+
+    const FRAME_SIZE: usize = SCREEN_WIDTH * SCREEN_HEIGHT * 3;
+
+    #[test]
+    fn picks_the_format_from_the_extension() {
+        assert_eq!(VideoFormat::from_path(Path::new("run.y4m")), VideoFormat::Y4m);
+        assert_eq!(VideoFormat::from_path(Path::new("RUN.Y4M")), VideoFormat::Y4m);
+        assert_eq!(VideoFormat::from_path(Path::new("run.rgb")), VideoFormat::Rgb);
+        assert_eq!(VideoFormat::from_path(Path::new("run")), VideoFormat::Rgb);
+    }
+
+    #[test]
+    fn converts_to_limited_range_yuv() {
+        let planes = rgb_to_yuv444(&[0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0xFF, 0x00, 0x00]);
+        // Y, then Cb, then Cr, for white, black and red.
+        assert_eq!(planes, [235, 16, 82, 128, 128, 90, 128, 128, 240]);
+    }
+
+    #[test]
+    fn writes_y4m_and_raw_frames() {
+        let path = std::env::temp_dir().join(format!("recording_tests_{}.y4m", std::process::id()));
+        let frame: Vec<u8> = (0..FRAME_SIZE).map(|index| (index % 251) as u8).collect();
+        for format in [VideoFormat::Y4m, VideoFormat::Rgb] {
+            let mut video = VideoWriter::new(fs::File::create(&path).unwrap(), format).unwrap();
+            video.write_frame(&frame).unwrap();
+            video.write_frame(&frame).unwrap();
+            assert_eq!(video.frames(), 2);
+            video.finish().unwrap();
+
+            let bytes = fs::read(&path).unwrap();
+            if format == VideoFormat::Rgb {
+                assert_eq!(bytes, [frame.clone(), frame.clone()].concat());
+                continue;
+            }
+            let header = b"YUV4MPEG2 W160 H144 F4194304:70224 Ip A1:1 C444\n";
+            assert!(bytes.starts_with(header));
+            let frames = &bytes[header.len()..];
+            assert_eq!(frames.len(), 2 * (6 + FRAME_SIZE));
+            assert!(frames.starts_with(b"FRAME\n"));
+            assert_eq!(&frames[6..6 + FRAME_SIZE], rgb_to_yuv444(&frame));
+        }
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn writes_wav_as_long_as_the_video() {
+        assert_eq!(samples_for_frames(0, 48_000), 0);
+        assert_eq!(samples_for_frames(1, 48_000), 803);
+        assert_eq!(samples_for_frames(60, 48_000), 48_218);
+
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 48_000, 2).unwrap();
+        wav.write_samples(&[1, -1, 2, -2]).unwrap();
+        wav.write_samples(&[0; 6]).unwrap();
+        assert_eq!(wav.samples(), 5);
+        let bytes = wav.finish().unwrap().into_inner();
+
+        assert_eq!(bytes.len(), 44 + 20);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(bytes[4..8], 56u32.to_le_bytes());
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        // PCM, two channels, 48 kHz, 192000 bytes per second, 4 per block,
+        // 16 bits.
+        assert_eq!(bytes[20..24], [1, 0, 2, 0]);
+        assert_eq!(bytes[24..28], 48_000u32.to_le_bytes());
+        assert_eq!(bytes[28..32], 192_000u32.to_le_bytes());
+        assert_eq!(bytes[32..36], [4, 0, 16, 0]);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(bytes[40..44], 20u32.to_le_bytes());
+        assert_eq!(bytes[44..52], [1, 0, 0xFF, 0xFF, 2, 0, 0xFE, 0xFF]);
+        assert!(bytes[52..].iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn silent_audio_keeps_pace_with_the_frames() {
+        let mut audio = SilentAudio::new(48_000);
+        let frames: Vec<Vec<i16>> = (0..60).map(|_| audio.frame_samples()).collect();
+        assert_eq!(frames[0].len(), 2 * 803);
+        assert_eq!(frames[1].len(), 2 * 804);
+        assert_eq!(frames.iter().map(Vec::len).sum::<usize>(), 2 * samples_for_frames(60, 48_000) as usize);
+        assert!(frames.iter().flatten().all(|&sample| sample == 0));
+    }
+}
@@ -0,0 +1,86 @@
+mod common;
+
+mod rewind_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+
+    /// Enable the timer, then keep writing a counter through WRAM:
+    /// LD A, 0x05, LDH (0x07), A, LD HL, 0xC000,
+    /// loop: INC A, LD (HL+), A, RES 4, H, JR loop
+    const PROGRAM: [u8; 13] = [0x3E, 0x05, 0xE0, 0x07, 0x21, 0x00, 0xC0, 0x3C, 0x22, 0xCB, 0xA4, 0x18, 0xFA];
+
+    fn init_emulator() -> Emulator {
+        let rom = rom_with_program(&PROGRAM);
+        Emulator::builder().model(Model::Dmg).rom(rom).build().unwrap()
+    }
+
+    /// Run `frames` frames and return the state at the end of each.
+    fn run_frames(emulator: &mut Emulator, frames: u64) -> Vec<Vec<u8>> {
+        let mut states = Vec::new();
+        for _ in 0..frames {
+            emulator.run_frame();
+            states.push(emulator.save_state());
+        }
+        states
+    }
+
+    #[test]
+    fn rewinding_restores_earlier_frames() {
+        let mut emulator = init_emulator();
+        emulator.enable_rewind(1, 1 << 20);
+        let states = run_frames(&mut emulator, 10);
+
+        assert_eq!(emulator.rewind(3), 3);
+        assert_eq!(emulator.save_state(), states[6]);
+        assert_eq!(emulator.rewind(2), 2);
+        assert_eq!(emulator.save_state(), states[4]);
+
+        // Playing on from there records a new history.
+        let replayed = run_frames(&mut emulator, 2);
+        assert_eq!(replayed, states[5..7]);
+        assert_eq!(emulator.rewind(1), 1);
+        assert_eq!(emulator.save_state(), states[5]);
+    }
+
+    #[test]
+    fn snapshots_are_taken_every_interval() {
+        let mut emulator = init_emulator();
+        let start = emulator.cpu.ppu.frame_count();
+        emulator.enable_rewind(4, 1 << 20);
+        let states = run_frames(&mut emulator, 10);
+        assert_eq!(emulator.rewind_buffer.as_ref().unwrap().len(), 3);
+
+        // From frame 10 back 3 frames is frame 7; the last snapshot by then
+        // was taken on frame 4.
+        assert_eq!(emulator.rewind(3), 6);
+        assert_eq!(emulator.cpu.ppu.frame_count(), start + 4);
+        assert_eq!(emulator.save_state(), states[3]);
+    }
+
+    #[test]
+    fn oldest_snapshots_are_dropped_to_stay_within_budget() {
+        let mut emulator = init_emulator();
+        let state_size = emulator.save_state().len();
+        emulator.enable_rewind(1, state_size + 2000);
+        run_frames(&mut emulator, 60);
+
+        let buffer = emulator.rewind_buffer.as_ref().unwrap();
+        assert!(buffer.memory_used() <= buffer.budget());
+        assert!(buffer.len() > 2 && buffer.len() < 61);
+        let oldest = buffer.oldest_frame().unwrap();
+
+        let current = emulator.cpu.ppu.frame_count();
+        assert_eq!(emulator.rewind(1000), current - oldest);
+        assert_eq!(emulator.rewind_buffer.as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn rewinding_without_a_buffer_does_nothing() {
+        let mut emulator = init_emulator();
+        run_frames(&mut emulator, 2);
+        let before = emulator.save_state();
+        assert_eq!(emulator.rewind(1), 0);
+        assert_eq!(emulator.save_state(), before);
+    }
+}
@@ -0,0 +1,66 @@
+mod common;
+
+mod run_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::{Emulator, StopReason};
+    use gameboy_emulator::model::Model;
+
+    fn init_emulator(program: &[u8]) -> Emulator {
+        let rom = rom_with_program(program);
+        Emulator::builder().model(Model::Dmg).rom(rom).build().unwrap()
+    }
+
+    /// Count up in 0xC000: loop: LD HL, 0xC000, INC (HL), JR loop
+    const COUNTER: [u8; 6] = [0x21, 0x00, 0xC0, 0x34, 0x18, 0xFB];
+
+    #[test]
+    fn run_frame_stops_at_each_vblank() {
+        let mut emulator = init_emulator(&COUNTER);
+        let frame = emulator.cpu.ppu.frame_count();
+        for count in 1..=3 {
+            assert_eq!(emulator.run_frame(), StopReason::FrameDone);
+            assert_eq!(emulator.cpu.ppu.frame_count(), frame + count);
+            assert_eq!(emulator.cpu.get_memory_8bit(0xFF44), 144);
+        }
+    }
+
+    #[test]
+    fn run_frame_takes_a_frame_worth_of_cycles_with_the_lcd_off() {
+        let mut emulator = init_emulator(&COUNTER);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x00);
+        let frame = emulator.cpu.ppu.frame_count();
+        let divider = emulator.cpu.get_memory_8bit(0xFF04);
+
+        assert_eq!(emulator.run_frame(), StopReason::LcdOff);
+        assert_eq!(emulator.cpu.ppu.frame_count(), frame);
+        // 70224 cycles are 274 and a bit DIV increments, which wraps at 256.
+        let ticks = emulator.cpu.get_memory_8bit(0xFF04).wrapping_sub(divider);
+        assert!((18..=19).contains(&ticks), "{}", ticks);
+    }
+
+    #[test]
+    fn run_cycles_runs_at_least_that_long() {
+        let mut emulator = init_emulator(&COUNTER);
+        let divider = emulator.cpu.get_memory_8bit(0xFF04);
+        assert_eq!(emulator.run_cycles(256 * 10), StopReason::CyclesDone);
+        let ticks = emulator.cpu.get_memory_8bit(0xFF04).wrapping_sub(divider);
+        assert!((10..=11).contains(&ticks), "{}", ticks);
+    }
+
+    #[test]
+    fn run_until_stops_when_the_condition_holds() {
+        let mut emulator = init_emulator(&COUNTER);
+        let reason = emulator.run_until(|emulator| emulator.cpu.get_memory_8bit(0xC000) == 5);
+        assert_eq!(reason, StopReason::ConditionMet);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xC000), 5);
+        assert_eq!(emulator.run_until(|_| true), StopReason::ConditionMet);
+    }
+
+    #[test]
+    fn run_until_gives_up_once_the_cpu_is_locked() {
+        // XOR A, LDH (0xFF), A, DI, HALT
+        let mut emulator = init_emulator(&[0xAF, 0xE0, 0xFF, 0xF3, 0x76]);
+        assert_eq!(emulator.run_until(|_| false), StopReason::Locked);
+        assert!(emulator.cpu.halted);
+    }
+}
@@ -0,0 +1,110 @@
+mod common;
+
+mod screenshot_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::ppu::{color_to_rgb, SCREEN_HEIGHT, SCREEN_WIDTH};
+    use gameboy_emulator::screenshot::{DmgPalette, Screenshot};
+    use gameboy_emulator::sgb::{SGB_SCREEN_HEIGHT, SGB_SCREEN_WIDTH};
+    use std::fs;
+    use std::io::ErrorKind;
+
+    /// Run a frame of a program that sets BGP to `bgp` and loops, so the
+    /// blank background is drawn in shade `bgp & 3`.
+    fn init_emulator(model: Model, bgp: u8) -> Emulator {
+        // LD A, bgp; LDH (BGP), A; JR -2
+        let rom = rom_with_program(&[0x3E, bgp, 0xE0, 0x47, 0x18, 0xFE]);
+        let mut emulator = Emulator::builder().model(model).rom(rom).build().unwrap();
+        emulator.run_frame();
+        emulator.run_frame();
+        emulator
+    }
+
+    #[test]
+    fn parses_palettes() {
+        assert_eq!(DmgPalette::parse("grayscale"), Ok(DmgPalette::Grayscale));
+        assert_eq!(DmgPalette::parse("Pea-Soup"), Ok(DmgPalette::PeaSoup));
+        assert_eq!(DmgPalette::parse("green"), Ok(DmgPalette::PeaSoup));
+        assert_eq!(
+            DmgPalette::parse("e0f8d0, 88C070,#346856,081820"),
+            Ok(DmgPalette::Custom([[0xE0, 0xF8, 0xD0], [0x88, 0xC0, 0x70], [0x34, 0x68, 0x56], [0x08, 0x18, 0x20]]))
+        );
+        for invalid in ["sepia", "e0f8d0,88c070,346856", "e0f8d0,88c070,346856,08182", "e0f8d0,88c070,346856,0818zz"] {
+            assert!(DmgPalette::parse(invalid).is_err(), "{:?}", invalid);
+        }
+        assert_eq!(DmgPalette::Grayscale.colors()[0], [0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn draws_dmg_shades_in_the_palette() {
+        let emulator = init_emulator(Model::Dmg, 0xFF);
+        let default = Screenshot::default().rgb(&emulator.cpu).unwrap();
+        assert_eq!(default, emulator.cpu.ppu.frame_rgb());
+        assert_eq!(&default[..3], &[0x00, 0x00, 0x00]);
+
+        let green = Screenshot { palette: DmgPalette::PeaSoup, scale: 1 }.rgb(&emulator.cpu).unwrap();
+        assert!(green.chunks(3).all(|pixel| pixel == [0x0F, 0x38, 0x0F]));
+
+        let light = init_emulator(Model::Dmg, 0xFE);
+        let custom = DmgPalette::Custom([[1, 2, 3], [4, 5, 6], [7, 8, 9], [10, 11, 12]]);
+        let rgb = Screenshot { palette: custom, scale: 1 }.rgb(&light.cpu).unwrap();
+        assert!(rgb.chunks(3).all(|pixel| pixel == [7, 8, 9]));
+    }
+
+    #[test]
+    fn keeps_the_colors_of_color_games() {
+        let emulator = init_emulator(Model::Cgb, 0xFF);
+        let pea_soup = Screenshot { palette: DmgPalette::PeaSoup, scale: 1 };
+        assert_eq!(pea_soup.rgb(&emulator.cpu).unwrap(), emulator.cpu.ppu.frame_rgb());
+    }
+
+    #[test]
+    fn takes_the_whole_sgb_picture() {
+        let mut emulator = init_emulator(Model::Sgb, 0xFF);
+        emulator.cpu.set_sgb_mode(true);
+        emulator.run_frame();
+        let screenshot = Screenshot { palette: DmgPalette::PeaSoup, scale: 2 };
+        assert_eq!(screenshot.size(&emulator.cpu), (SGB_SCREEN_WIDTH as u32 * 2, SGB_SCREEN_HEIGHT as u32 * 2));
+
+        let rgb = Screenshot::default().rgb(&emulator.cpu).unwrap();
+        let sgb = emulator.cpu.sgb.as_ref().unwrap();
+        assert_eq!(rgb, sgb.frame().iter().flat_map(|&color| color_to_rgb(color)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn refuses_scales_out_of_range() {
+        let emulator = init_emulator(Model::Dmg, 0xFF);
+        for scale in [0, 17] {
+            let error = Screenshot { palette: DmgPalette::Grayscale, scale }.rgb(&emulator.cpu).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        }
+    }
+
+    #[test]
+    fn scales_up_and_writes_png() {
+        let emulator = init_emulator(Model::Dmg, 0xE4);
+        let screenshot = Screenshot { palette: DmgPalette::Grayscale, scale: 3 };
+        let frame = Screenshot::default().rgb(&emulator.cpu).unwrap();
+        let scaled = screenshot.rgb(&emulator.cpu).unwrap();
+        assert_eq!(scaled.len(), frame.len() * 9);
+        let width = SCREEN_WIDTH * 3;
+        for (x, y) in [(0, 0), (5, 7), (159, 143), (80, 1)] {
+            for (dx, dy) in [(0, 0), (2, 0), (0, 2), (2, 2)] {
+                let scaled_offset = ((y * 3 + dy) * width + x * 3 + dx) * 3;
+                let offset = (y * SCREEN_WIDTH + x) * 3;
+                assert_eq!(scaled[scaled_offset..scaled_offset + 3], frame[offset..offset + 3], "{:?}", (x, y));
+            }
+        }
+
+        let path = std::env::temp_dir().join(format!("screenshot_tests_{}.png", std::process::id()));
+        emulator.screenshot_with(&path, &screenshot).unwrap();
+        let png = fs::read(&path).unwrap();
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..20], (SCREEN_WIDTH as u32 * 3).to_be_bytes());
+        assert_eq!(png[20..24], (SCREEN_HEIGHT as u32 * 3).to_be_bytes());
+        emulator.screenshot(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap()[16..20], (SCREEN_WIDTH as u32).to_be_bytes());
+        fs::remove_file(path).unwrap();
+    }
+}
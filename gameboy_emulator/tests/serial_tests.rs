@@ -0,0 +1,97 @@
+mod common;
+
+mod serial_tests {
+    use crate::common::rom_with_program;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::serial::{SerialCapture, SerialEndpoint};
+
+    const TRANSFER_CYCLES: u32 = 8 * 512;
+
+    // LD A,'A' / LDH (SB),A / LD A,$81 / LDH (SC),A / JR -2
+    const SEND_BYTE: [u8; 10] = [0x3E, 0x41, 0xE0, 0x01, 0x3E, 0x81, 0xE0, 0x02, 0x18, 0xFE];
+
+    fn init_emulator(program: &[u8]) -> Emulator {
+        Emulator::builder().rom(rom_with_program(program)).build().unwrap()
+    }
+
+    fn run_cycles(emulator: &mut Emulator, cycles: u32) {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            elapsed += emulator.cpu.execute_instruction();
+        }
+    }
+
+    struct ExternalClock {
+        incoming: u8,
+        received: Rc<RefCell<Vec<u8>>>,
+    }
+
+    impl SerialEndpoint for ExternalClock {
+        fn transfer(&mut self, _outgoing: u8) -> u8 {
+            panic!("the remote side drives the clock");
+        }
+
+        fn poll_external(&mut self, outgoing: u8) -> Option<u8> {
+            self.received.borrow_mut().push(outgoing);
+            Some(self.incoming)
+        }
+    }
+
+    #[test]
+    fn internal_clock_transfer_takes_4096_cycles() {
+        let mut emulator = init_emulator(&SEND_BYTE);
+        let capture = SerialCapture::new();
+        emulator.cpu.serial.connect(Box::new(capture.clone()));
+
+        run_cycles(&mut emulator, TRANSFER_CYCLES / 2);
+        assert!(capture.bytes().is_empty());
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF02), 0xFF);
+
+        run_cycles(&mut emulator, TRANSFER_CYCLES);
+        assert_eq!(capture.text(), "A");
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF01), 0xFF);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF02), 0x7F);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF0F) & 0x08, 0x08);
+    }
+
+    #[test]
+    fn transfer_complete_wakes_halt_and_jumps_to_vector() {
+        // LD A,$08 / LDH (IE),A / EI / <SEND_BYTE without the loop> / HALT / JR -2
+        let mut program = vec![0x3E, 0x08, 0xE0, 0xFF, 0xFB];
+        program.extend_from_slice(&SEND_BYTE[..8]);
+        program.extend_from_slice(&[0x76, 0x18, 0xFE]);
+        let mut rom = rom_with_program(&program);
+        // Serial handler: JR -2
+        rom[0x58..0x5A].copy_from_slice(&[0x18, 0xFE]);
+
+        let mut emulator = Emulator::builder().rom(rom).build().unwrap();
+        run_cycles(&mut emulator, TRANSFER_CYCLES / 2);
+        assert!(emulator.cpu.halted);
+
+        run_cycles(&mut emulator, TRANSFER_CYCLES);
+        assert!(!emulator.cpu.halted);
+        assert_eq!(emulator.cpu.get_pc(), 0x58);
+        assert!(!emulator.cpu.ime_flag);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF0F) & 0x08, 0x00);
+    }
+
+    #[test]
+    fn external_clock_waits_for_remote() {
+        // LD A,'A' / LDH (SB),A / LD A,$80 / LDH (SC),A / JR -2
+        let program = [0x3E, 0x41, 0xE0, 0x01, 0x3E, 0x80, 0xE0, 0x02, 0x18, 0xFE];
+
+        let mut emulator = init_emulator(&program);
+        run_cycles(&mut emulator, TRANSFER_CYCLES * 2);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF02), 0xFE);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF01), 0x41);
+
+        let received = Rc::new(RefCell::new(Vec::new()));
+        emulator.cpu.serial.connect(Box::new(ExternalClock { incoming: 0x55, received: received.clone() }));
+        run_cycles(&mut emulator, 4);
+        assert_eq!(*received.borrow(), vec![0x41]);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF01), 0x55);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF02), 0x7E);
+    }
+}
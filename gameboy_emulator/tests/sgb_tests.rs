@@ -0,0 +1,235 @@
+mod common;
+
+mod sgb_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::joypad::Button;
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::sgb::{Mask, SGB_SCREEN_WIDTH};
+
+    const RED: u16 = 0x001F;
+    const GREEN: u16 = 0x03E0;
+    const BLUE: u16 = 0x7C00;
+    const WHITE: u16 = 0x7FFF;
+
+    fn init_emulator() -> Emulator {
+        // JR -2
+        let mut rom = rom_with_program(&[0x18, 0xFE]);
+        rom[0x146] = 0x03;
+        rom[0x14B] = 0x33;
+        Emulator::builder().model(Model::Sgb).rom(rom).build().unwrap()
+    }
+
+    /// Send a packet over the joypad port, one pulse per bit.
+    fn send_packet(emulator: &mut Emulator, packet: &[u8]) {
+        let mut bytes = [0u8; 16];
+        bytes[..packet.len()].copy_from_slice(packet);
+
+        emulator.cpu.set_memory_8bit(0xFF00, 0x00);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x30);
+        for byte in bytes {
+            for bit in 0..8 {
+                let pulse = if byte & (1 << bit) != 0 { 0x10 } else { 0x20 };
+                emulator.cpu.set_memory_8bit(0xFF00, pulse);
+                emulator.cpu.set_memory_8bit(0xFF00, 0x30);
+            }
+        }
+        emulator.cpu.set_memory_8bit(0xFF00, 0x20);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x30);
+    }
+
+    /// Show tiles 0-255 in order on the background, 20 per row, so that the
+    /// 4 KiB at 0x8000 get sent by the next VRAM transfer.
+    fn show_transfer_data(emulator: &mut Emulator, data: &[u8]) {
+        for (offset, &byte) in data.iter().enumerate() {
+            emulator.cpu.set_memory_8bit(0x8000 + offset as u16, byte);
+        }
+        for tile in 0..256u16 {
+            emulator.cpu.set_memory_8bit(0x9800 + (tile / 20) * 32 + tile % 20, tile as u8);
+        }
+        emulator.cpu.set_memory_8bit(0xFF40, 0x91);
+    }
+
+    fn attribute(emulator: &Emulator, x: usize, y: usize) -> u8 {
+        emulator.cpu.sgb.as_ref().unwrap().attributes[y * 20 + x]
+    }
+
+    fn sgb_pixel(emulator: &Emulator, x: usize, y: usize) -> u16 {
+        emulator.cpu.sgb.as_ref().unwrap().frame()[y * SGB_SCREEN_WIDTH + x]
+    }
+
+    fn pal01(color0: u16, first: [u16; 3], second: [u16; 3]) -> Vec<u8> {
+        // PAL01, one packet.
+        let mut packet = vec![0x01];
+        for color in [color0].iter().chain(first.iter()).chain(second.iter()) {
+            packet.extend_from_slice(&color.to_le_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn palettes_and_attribute_blocks_color_the_screen() {
+        let mut emulator = init_emulator();
+        send_packet(&mut emulator, &pal01(WHITE, [RED, RED, RED], [GREEN, GREEN, GREEN]));
+        // ATTR_BLK: inside only, palette 1, cells (1, 1) to (3, 3).
+        send_packet(&mut emulator, &[0x04 << 3 | 1, 1, 0x01, 0x01, 1, 1, 3, 3]);
+        assert_eq!(attribute(&emulator, 1, 1), 1);
+        assert_eq!(attribute(&emulator, 2, 2), 1);
+        assert_eq!(attribute(&emulator, 4, 2), 0);
+
+        // Tile 0 solid color 1, which BGP keeps as shade 1.
+        for row in 0..8 {
+            emulator.cpu.set_memory_8bit(0x8000 + row * 2, 0xFF);
+        }
+        emulator.cpu.set_memory_8bit(0xFF47, 0xE4);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x91);
+        emulator.run_frame();
+
+        assert_eq!(sgb_pixel(&emulator, 48, 40), RED);
+        assert_eq!(sgb_pixel(&emulator, 48 + 16, 40 + 16), GREEN);
+        assert_eq!(sgb_pixel(&emulator, 0, 0), WHITE);
+    }
+
+    #[test]
+    fn attribute_lines_divisions_and_characters() {
+        let mut emulator = init_emulator();
+        // ATTR_LIN: horizontal line 2 with palette 3, vertical line 5 with palette 2.
+        send_packet(&mut emulator, &[0x05 << 3 | 1, 2, 0x80 | 0x60 | 2, 0x40 | 5]);
+        assert_eq!(attribute(&emulator, 0, 2), 3);
+        assert_eq!(attribute(&emulator, 5, 10), 2);
+        assert_eq!(attribute(&emulator, 0, 0), 0);
+
+        // ATTR_DIV: split at row 9, palette 1 above, 2 on the line, 3 below.
+        send_packet(&mut emulator, &[0x06 << 3 | 1, 0x40 | 0x20 | 0x04 | 0x03, 9]);
+        assert_eq!(attribute(&emulator, 7, 8), 1);
+        assert_eq!(attribute(&emulator, 7, 9), 2);
+        assert_eq!(attribute(&emulator, 7, 10), 3);
+
+        // ATTR_CHR: from (19, 0), left to right, palettes 0 1 2 3.
+        send_packet(&mut emulator, &[0x07 << 3 | 1, 19, 0, 4, 0, 0, 0b00_01_10_11]);
+        assert_eq!(attribute(&emulator, 19, 0), 0);
+        assert_eq!(attribute(&emulator, 0, 1), 1);
+        assert_eq!(attribute(&emulator, 1, 1), 2);
+        assert_eq!(attribute(&emulator, 2, 1), 3);
+    }
+
+    #[test]
+    fn pal_trn_and_pal_set_load_system_palettes() {
+        let mut emulator = init_emulator();
+        let mut data = vec![0u8; 0x1000];
+        for (index, color) in [WHITE, RED, GREEN, BLUE].into_iter().enumerate() {
+            data[5 * 8 + index * 2..5 * 8 + index * 2 + 2].copy_from_slice(&color.to_le_bytes());
+        }
+        show_transfer_data(&mut emulator, &data);
+
+        send_packet(&mut emulator, &[0x0B << 3 | 1]);
+        emulator.run_frame();
+        send_packet(&mut emulator, &[0x0A << 3 | 1, 5, 0, 5, 0, 5, 0, 5, 0, 0x40]);
+
+        let sgb = emulator.cpu.sgb.as_ref().unwrap();
+        assert_eq!(sgb.palettes[0], [WHITE, RED, GREEN, BLUE]);
+        assert_eq!(sgb.palettes[3], [WHITE, RED, GREEN, BLUE]);
+        assert_eq!(sgb.mask, Mask::Cancel);
+    }
+
+    #[test]
+    fn vram_transfers_send_the_scrolled_screen() {
+        let mut emulator = init_emulator();
+        let mut data = vec![0u8; 0x1000];
+        data[5 * 8..5 * 8 + 2].copy_from_slice(&RED.to_le_bytes());
+        show_transfer_data(&mut emulator, &data);
+        // Move the tiles one column right and two rows down, and scroll
+        // them back into place.
+        for offset in 0..0x400 {
+            emulator.cpu.set_memory_8bit(0x9800 + offset, 0x00);
+        }
+        for tile in 0..256u16 {
+            emulator.cpu.set_memory_8bit(0x9800 + (tile / 20 + 2) * 32 + tile % 20 + 1, tile as u8);
+        }
+        emulator.cpu.set_memory_8bit(0xFF42, 16);
+        emulator.cpu.set_memory_8bit(0xFF43, 8);
+
+        send_packet(&mut emulator, &[0x0B << 3 | 1]);
+        emulator.run_frame();
+        send_packet(&mut emulator, &[0x0A << 3 | 1, 5, 0, 5, 0, 5, 0, 5, 0]);
+        assert_eq!(emulator.cpu.sgb.as_ref().unwrap().palettes[0][0], RED);
+    }
+
+    #[test]
+    fn chr_trn_and_pct_trn_draw_the_border() {
+        let mut emulator = init_emulator();
+
+        // Border tile 1: plane 0 set everywhere, so color 1.
+        let mut tiles = vec![0u8; 0x1000];
+        for row in 0..8 {
+            tiles[32 + row * 2] = 0xFF;
+        }
+        show_transfer_data(&mut emulator, &tiles);
+        send_packet(&mut emulator, &[0x13 << 3 | 1, 0]);
+        emulator.run_frame();
+
+        // Map entry (0, 0): tile 1 with palette 4, whose color 1 is blue.
+        let mut map = vec![0u8; 0x1000];
+        map[0..2].copy_from_slice(&(1u16 | 4 << 10).to_le_bytes());
+        map[0x802..0x804].copy_from_slice(&BLUE.to_le_bytes());
+        show_transfer_data(&mut emulator, &map);
+        send_packet(&mut emulator, &[0x14 << 3 | 1]);
+        emulator.run_frame();
+        emulator.run_frame();
+
+        assert_eq!(sgb_pixel(&emulator, 0, 0), BLUE);
+        assert_eq!(sgb_pixel(&emulator, 7, 7), BLUE);
+        assert_ne!(sgb_pixel(&emulator, 8, 0), BLUE);
+    }
+
+    #[test]
+    fn mask_en_blanks_the_game_screen() {
+        let mut emulator = init_emulator();
+        send_packet(&mut emulator, &pal01(GREEN, [RED, RED, RED], [RED, RED, RED]));
+        send_packet(&mut emulator, &[0x17 << 3 | 1, 2]);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x91);
+        emulator.run_frame();
+        assert_eq!(sgb_pixel(&emulator, 100, 100), 0x0000);
+        assert_eq!(sgb_pixel(&emulator, 0, 0), GREEN);
+
+        send_packet(&mut emulator, &[0x17 << 3 | 1, 3]);
+        emulator.run_frame();
+        assert_eq!(sgb_pixel(&emulator, 100, 100), GREEN);
+    }
+
+    #[test]
+    fn mlt_req_cycles_through_joypad_ids() {
+        let mut emulator = init_emulator();
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00) & 0x0F, 0x0F);
+
+        send_packet(&mut emulator, &[0x11 << 3 | 1, 0x01]);
+        assert_eq!(emulator.cpu.sgb.as_ref().unwrap().players(), 2);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00) & 0x0F, 0x0F);
+
+        emulator.cpu.set_memory_8bit(0xFF00, 0x10);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x30);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00) & 0x0F, 0x0E);
+
+        emulator.cpu.set_memory_8bit(0xFF00, 0x10);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x30);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00) & 0x0F, 0x0F);
+    }
+
+    #[test]
+    fn mlt_req_reads_each_players_keys() {
+        let mut emulator = init_emulator();
+        emulator.cpu.press(Button::Start);
+        emulator.cpu.press_player(1, Button::A);
+        send_packet(&mut emulator, &[0x11 << 3 | 1, 0x01]);
+
+        // Select the buttons, then deselect them to move on to player 2.
+        emulator.cpu.set_memory_8bit(0xFF00, 0x10);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00) & 0x0F, 0x07);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x30);
+        emulator.cpu.set_memory_8bit(0xFF00, 0x10);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00) & 0x0F, 0x0E);
+
+        emulator.cpu.release_player(1, Button::A);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF00) & 0x0F, 0x0F);
+    }
+}
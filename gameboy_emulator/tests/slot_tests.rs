@@ -0,0 +1,118 @@
+mod common;
+
+mod slot_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::compression;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::slot::Slot;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::{Path, PathBuf};
+
+    /// Write a counter through WRAM forever:
+    /// LD HL, 0xC000, loop: INC A, LD (HL+), A, RES 4, H, JR loop
+    const PROGRAM: [u8; 9] = [0x21, 0x00, 0xC0, 0x3C, 0x22, 0xCB, 0xA4, 0x18, 0xFA];
+
+    fn rom_file(name: &str, title: &str) -> PathBuf {
+        let mut rom = rom_with_program(&PROGRAM);
+        rom[0x134..0x134 + title.len()].copy_from_slice(title.as_bytes());
+        let path = std::env::temp_dir().join(format!("slot_tests_{}_{}.gb", name, std::process::id()));
+        fs::write(&path, rom).unwrap();
+        path
+    }
+
+    fn init_emulator(rom_path: &Path) -> Emulator {
+        let mut emulator = Emulator::new(Model::Dmg);
+        emulator.init_rom(rom_path);
+        emulator.cpu.skip_boot();
+        emulator
+    }
+
+    fn run_frames(emulator: &mut Emulator, frames: u64) {
+        for _ in 0..frames {
+            emulator.run_frame();
+        }
+    }
+
+    #[test]
+    fn compression_round_trips() {
+        let mut noise = Vec::new();
+        let mut seed = 0x1234_5678u32;
+        for _ in 0..5000 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            noise.push((seed >> 16) as u8);
+        }
+        let mut mixed = vec![0x00; 70000];
+        mixed.extend_from_slice(&noise);
+        mixed.extend(std::iter::repeat_n([1, 2, 3], 300).flatten());
+
+        for data in [&[][..], &[7][..], &noise[..], &mixed[..]] {
+            let compressed = compression::compress(data);
+            assert_eq!(compression::decompress(&compressed).unwrap(), data);
+        }
+        assert!(compression::compress(&mixed).len() < noise.len() + 1000);
+    }
+
+    #[test]
+    fn damaged_compressed_data_is_rejected() {
+        let compressed = compression::compress(&[0x42; 100]);
+        let mut longer = compressed.clone();
+        longer[0] += 1;
+        let mut bad_offset = compressed.clone();
+        let last = bad_offset.len() - 4;
+        bad_offset[last] = 0xFF;
+
+        for bytes in [&compressed[..3], &compressed[..compressed.len() - 2], &longer[..], &bad_offset[..]] {
+            assert_eq!(compression::decompress(bytes).unwrap_err().kind(), ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn slots_are_saved_next_to_the_rom_and_load_back() {
+        let rom_path = rom_file("round_trip", "GAME");
+        let mut emulator = init_emulator(&rom_path);
+        run_frames(&mut emulator, 2);
+        let saved = emulator.save_state();
+        emulator.save_slot(3).unwrap();
+
+        let slot_path = rom_path.with_extension("ss3");
+        assert!(slot_path.exists());
+        assert!(fs::metadata(&slot_path).unwrap().len() < saved.len() as u64 / 4);
+
+        let slot = emulator.read_slot(3).unwrap();
+        assert_eq!(slot.thumbnail, emulator.cpu.ppu.frame());
+        assert_eq!(slot.rom_checksum, emulator.rom_checksum());
+        assert!(slot.timestamp > 0);
+
+        run_frames(&mut emulator, 1);
+        emulator.load_slot(3).unwrap();
+        assert_eq!(emulator.save_state(), saved);
+        assert_eq!(emulator.load_slot(4).unwrap_err().kind(), ErrorKind::NotFound);
+
+        fs::remove_file(slot_path).unwrap();
+        fs::remove_file(rom_path).unwrap();
+    }
+
+    #[test]
+    fn slots_from_another_game_are_rejected() {
+        let rom_path = rom_file("saved", "GAME");
+        let other_path = rom_file("other", "OTHER GAME");
+        init_emulator(&rom_path).save_slot(1).unwrap();
+        fs::copy(Slot::path(&rom_path, 1), Slot::path(&other_path, 1)).unwrap();
+
+        let mut emulator = init_emulator(&other_path);
+        run_frames(&mut emulator, 1);
+        let before = emulator.save_state();
+        assert_eq!(emulator.load_slot(1).unwrap_err().kind(), ErrorKind::InvalidData);
+        assert_eq!(emulator.save_state(), before);
+
+        let mut damaged = fs::read(Slot::path(&other_path, 1)).unwrap();
+        damaged.truncate(damaged.len() - 1);
+        assert_eq!(Slot::decode(&damaged).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        for path in [Slot::path(&rom_path, 1), Slot::path(&other_path, 1), rom_path, other_path] {
+            fs::remove_file(path).unwrap();
+        }
+    }
+}
@@ -0,0 +1,107 @@
+mod common;
+
+mod state_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::model::Model;
+    use gameboy_emulator::state::{MAGIC, VERSION};
+    use std::io::ErrorKind;
+
+    /// Enable the timer, then keep writing a counter through WRAM:
+    /// LD A, 0x05, LDH (0x07), A, LD HL, 0xC000,
+    /// loop: INC A, LD (HL+), A, RES 4, H, JR loop
+    const PROGRAM: [u8; 14] = [0x3E, 0x05, 0xE0, 0x07, 0x21, 0x00, 0xC0, 0x3C, 0x22, 0xCB, 0xA4, 0x18, 0xFA, 0x00];
+
+    fn rom(cartridge_type: u8, ram_size: u8) -> Vec<u8> {
+        let mut rom = rom_with_program(&PROGRAM);
+        rom[0x147] = cartridge_type;
+        rom[0x149] = ram_size;
+        rom
+    }
+
+    fn init_emulator(model: Model, rom: &[u8]) -> Emulator {
+        let mut emulator = Emulator::new(model);
+        emulator.cpu.load_rom(rom);
+        emulator.cpu.skip_boot();
+        emulator
+    }
+
+    fn run_frames(emulator: &mut Emulator, frames: u64) {
+        for _ in 0..frames {
+            emulator.run_frame();
+        }
+    }
+
+    #[test]
+    fn loading_a_state_replays_the_same_frames() {
+        for model in [Model::Dmg, Model::Cgb, Model::Sgb] {
+            let mut emulator = init_emulator(model, &rom(0x00, 0x00));
+            run_frames(&mut emulator, 2);
+            let saved = emulator.save_state();
+
+            run_frames(&mut emulator, 3);
+            let expected = emulator.save_state();
+            let frame = emulator.cpu.ppu.frame().to_vec();
+
+            emulator.load_state(&saved).unwrap();
+            assert_eq!(emulator.save_state(), saved);
+            run_frames(&mut emulator, 3);
+            assert_eq!(emulator.save_state(), expected, "{:?}", model);
+            assert_eq!(emulator.cpu.ppu.frame(), frame.as_slice());
+        }
+    }
+
+    #[test]
+    fn cartridge_ram_and_banks_are_restored() {
+        // MBC1 with 8 KiB of RAM.
+        let mut emulator = init_emulator(Model::Dmg, &rom(0x03, 0x02));
+        emulator.cpu.set_memory_8bit(0x0000, 0x0A);
+        emulator.cpu.set_memory_8bit(0xA000, 0x42);
+        let saved = emulator.save_state();
+
+        emulator.cpu.set_memory_8bit(0xA000, 0x24);
+        emulator.cpu.set_memory_8bit(0x0000, 0x00);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xA000), 0xFF);
+
+        emulator.load_state(&saved).unwrap();
+        assert_eq!(emulator.cpu.get_memory_8bit(0xA000), 0x42);
+    }
+
+    #[test]
+    fn states_for_another_cartridge_are_rejected() {
+        let saved = init_emulator(Model::Dmg, &rom(0x03, 0x02)).save_state();
+        let mut emulator = init_emulator(Model::Dmg, &rom(0x00, 0x00));
+        run_frames(&mut emulator, 1);
+        let before = emulator.save_state();
+
+        let error = emulator.load_state(&saved).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(emulator.save_state(), before);
+    }
+
+    #[test]
+    fn damaged_and_other_version_states_are_rejected() {
+        let mut emulator = init_emulator(Model::Dmg, &rom(0x00, 0x00));
+        let saved = emulator.save_state();
+        assert_eq!(saved[..4], MAGIC);
+        assert_eq!(u16::from_le_bytes([saved[4], saved[5]]), VERSION);
+
+        let mut newer = saved.clone();
+        newer[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        let mut older = saved.clone();
+        older[4..6].copy_from_slice(&(VERSION - 1).to_le_bytes());
+        let mut wrong_magic = saved.clone();
+        wrong_magic[0] = b'X';
+        let truncated = &saved[..saved.len() - 1];
+        let mut trailing = saved.clone();
+        trailing.push(0x00);
+
+        run_frames(&mut emulator, 1);
+        let before = emulator.save_state();
+        for state in [&newer[..], &older[..], &wrong_magic[..], truncated, &trailing[..], &[]] {
+            let error = emulator.load_state(state).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert_eq!(emulator.save_state(), before);
+        }
+    }
+}
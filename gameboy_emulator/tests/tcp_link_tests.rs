@@ -0,0 +1,142 @@
+mod common;
+
+mod tcp_link_tests {
+    use crate::common::rom_with_program;
+    use std::env;
+    use std::net::{Ipv4Addr, TcpListener};
+    use std::process::{Command, Stdio};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+    use gameboy_emulator::emulator::Emulator;
+    use gameboy_emulator::tcp_link::TcpLink;
+
+    /// Set in the child process of `bytes_are_exchanged_between_processes`
+    /// to the port to join.
+    const JOIN_PORT_VARIABLE: &str = "TCP_LINK_TESTS_JOIN_PORT";
+
+    /// An emulator linked through `link` that sits in a loop with SB and SC
+    /// already set, so it's ready to transfer from its first step.
+    fn init_emulator(link: TcpLink, data: u8, control: u8) -> Emulator {
+        // JR -2
+        let rom = rom_with_program(&[0x18, 0xFE]);
+        let mut emulator = Emulator::builder().rom(rom).build().unwrap();
+        emulator.cpu.serial.connect(Box::new(link));
+        emulator.cpu.set_memory_8bit(0xFF01, data);
+        emulator.cpu.set_memory_8bit(0xFF02, control);
+        emulator
+    }
+
+    /// Run until the serial interrupt is requested and return SB. A transfer
+    /// can't complete before the other side has run, so this never depends
+    /// on how fast either side is.
+    fn run_until_transferred(emulator: &mut Emulator) -> u8 {
+        emulator.run_until(|emulator| emulator.cpu.get_memory_8bit(0xFF0F) & 0x08 != 0);
+        emulator.cpu.get_memory_8bit(0xFF01)
+    }
+
+    fn exchange(slave_delay: Duration) -> (u8, u8) {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let slave = thread::spawn(move || {
+            let link = TcpLink::join(port).unwrap();
+            thread::sleep(slave_delay);
+            run_until_transferred(&mut init_emulator(link, 0x42, 0x80))
+        });
+        let link = TcpLink::accept(&listener).unwrap();
+        let master = run_until_transferred(&mut init_emulator(link, 0x41, 0x81));
+
+        (master, slave.join().unwrap())
+    }
+
+    #[test]
+    fn bytes_are_exchanged_between_threads() {
+        assert_eq!(exchange(Duration::ZERO), (0x42, 0x41));
+    }
+
+    #[test]
+    fn clocked_side_stalls_until_the_other_side_runs() {
+        assert_eq!(exchange(Duration::from_millis(200)), (0x42, 0x41));
+    }
+
+    #[test]
+    fn side_that_is_not_listening_answers_0xff() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let done = Arc::new(AtomicBool::new(false));
+
+        let idle = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut emulator = init_emulator(TcpLink::join(port).unwrap(), 0x42, 0x00);
+                emulator.run_until(|_| done.load(Ordering::Relaxed));
+                emulator.cpu.get_memory_8bit(0xFF01)
+            })
+        };
+        let mut master = init_emulator(TcpLink::accept(&listener).unwrap(), 0x41, 0x81);
+        assert_eq!(run_until_transferred(&mut master), 0xFF);
+        done.store(true, Ordering::Relaxed);
+        // Closing the link lets the other side run on without waiting.
+        drop(master);
+        assert_eq!(idle.join().unwrap(), 0x42);
+    }
+
+    #[test]
+    fn listening_is_decided_in_emulated_time() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let done = Arc::new(AtomicBool::new(false));
+
+        // Counts B down twice, about 8192 cycles, before listening: long
+        // after the master's transfer in emulated time, though well before
+        // it in real time, as the master starts late.
+        // LD B,0 / DEC B / JR NZ,-3 / DEC B / JR NZ,-3 / LD A,$80 / LDH (SC),A / JR -2
+        let late = [0x06, 0x00, 0x05, 0x20, 0xFD, 0x05, 0x20, 0xFD, 0x3E, 0x80, 0xE0, 0x02, 0x18, 0xFE];
+        let slave = {
+            let done = done.clone();
+            thread::spawn(move || {
+                let mut emulator = Emulator::builder().rom(rom_with_program(&late)).build().unwrap();
+                emulator.cpu.serial.connect(Box::new(TcpLink::join(port).unwrap()));
+                emulator.cpu.set_memory_8bit(0xFF01, 0x42);
+                emulator.run_until(|_| done.load(Ordering::Relaxed));
+                emulator.cpu.get_memory_8bit(0xFF01)
+            })
+        };
+        let link = TcpLink::accept(&listener).unwrap();
+        thread::sleep(Duration::from_millis(200));
+        let mut master = init_emulator(link, 0x41, 0x81);
+        assert_eq!(run_until_transferred(&mut master), 0xFF);
+        done.store(true, Ordering::Relaxed);
+        drop(master);
+        assert_eq!(slave.join().unwrap(), 0x42);
+    }
+
+    #[test]
+    fn bytes_are_exchanged_between_processes() {
+        // The child: this same test run again, joining the parent's port.
+        if let Ok(port) = env::var(JOIN_PORT_VARIABLE) {
+            let link = TcpLink::join(port.parse().unwrap()).unwrap();
+            let received = run_until_transferred(&mut init_emulator(link, 0x42, 0x80));
+            println!("slave received {:02X}", received);
+            return;
+        }
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let child = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "tcp_link_tests::bytes_are_exchanged_between_processes", "--nocapture"])
+            .env(JOIN_PORT_VARIABLE, port.to_string())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let link = TcpLink::accept(&listener).unwrap();
+        let master = run_until_transferred(&mut init_emulator(link, 0x41, 0x81));
+
+        let output = child.wait_with_output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(master, 0x42);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("slave received 41"));
+    }
+}
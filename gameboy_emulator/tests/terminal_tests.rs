@@ -0,0 +1,35 @@
+mod terminal_tests {
+    use gameboy_emulator::joypad::Button;
+    use gameboy_emulator::ppu::{SCREEN_HEIGHT, SCREEN_WIDTH};
+    use gameboy_emulator::terminal::{decode_keys, render, Key};
+
+    #[test]
+    fn decodes_arrows_buttons_and_quit() {
+        assert_eq!(
+            decode_keys(b"\x1b[A\x1bOBx\x1b[C\x1b[Dz\r "),
+            [Button::Up, Button::Down, Button::A, Button::Right, Button::Left, Button::B, Button::Start, Button::Select]
+                .map(Key::Button)
+        );
+        assert_eq!(decode_keys(b"q"), [Key::Quit]);
+        assert_eq!(decode_keys(b"\x03"), [Key::Quit]);
+        // Unknown keys and sequences, like F1, are skipped.
+        assert_eq!(decode_keys(b"7\x1bOPx"), [Key::Button(Button::A)]);
+    }
+
+    #[test]
+    fn draws_two_pixels_per_character_and_only_changed_rows() {
+        let mut frame = vec![0x7FFF; SCREEN_WIDTH * SCREEN_HEIGHT];
+        let full = render(&frame, None);
+        assert_eq!(full.matches('\u{2580}').count(), SCREEN_WIDTH * SCREEN_HEIGHT / 2);
+        // One color for the whole screen is set once per row.
+        assert_eq!(full.matches("\x1b[38;2;255;255;255;48;2;255;255;255m").count(), SCREEN_HEIGHT / 2);
+        assert_eq!(render(&frame, Some(&frame.clone())), "");
+
+        let previous = frame.clone();
+        frame[3 * SCREEN_WIDTH + 1] = 0x001F;
+        let changed = render(&frame, Some(&previous));
+        assert!(changed.starts_with("\x1b[2;1H"));
+        assert_eq!(changed.matches('\u{2580}').count(), SCREEN_WIDTH);
+        assert!(changed.contains("48;2;255;0;0m"));
+    }
+}
@@ -0,0 +1,112 @@
+mod common;
+
+mod timer_tests {
+    use crate::common::rom_with_program;
+    use gameboy_emulator::cpu::Register8bit;
+    use gameboy_emulator::emulator::Emulator;
+
+    // LD A,$01 / LDH (KEY1),A / STOP / JR -2
+    const SWITCH_SPEED: [u8; 8] = [0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00, 0x18, 0xFE];
+
+    fn init_emulator(program: &[u8], cgb_flag: u8) -> Emulator {
+        let mut rom = rom_with_program(program);
+        rom[0x143] = cgb_flag;
+        Emulator::builder().rom(rom).build().unwrap()
+    }
+
+    fn run_cycles(emulator: &mut Emulator, cycles: u32) -> u32 {
+        let mut elapsed = 0;
+        while elapsed < cycles {
+            elapsed += emulator.cpu.execute_instruction();
+        }
+        elapsed
+    }
+
+    #[test]
+    fn div_increments_every_256_cycles_and_resets_on_write() {
+        let mut emulator = init_emulator(&[0x18, 0xFE], 0x00);
+        emulator.cpu.set_memory_8bit(0xFF04, 0x00);
+        let elapsed = run_cycles(&mut emulator, 1024);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF04) as u32, elapsed / 256);
+
+        emulator.cpu.set_memory_8bit(0xFF04, 0x42);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF04), 0x00);
+    }
+
+    #[test]
+    fn tima_overflow_reloads_tma_and_requests_interrupt() {
+        // NOPs all the way, so cycles can be counted exactly.
+        let mut emulator = init_emulator(&[], 0x00);
+        emulator.cpu.set_memory_8bit(0xFF06, 0xAB);
+        emulator.cpu.set_memory_8bit(0xFF05, 0xFE);
+        // Enabled, 16 cycles per increment.
+        emulator.cpu.set_memory_8bit(0xFF07, 0x05);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF07), 0xFD);
+
+        run_cycles(&mut emulator, 16);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF05), 0xFF);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF0F) & 0x04, 0x00);
+
+        run_cycles(&mut emulator, 16);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF05), 0xAB);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF0F) & 0x04, 0x04);
+    }
+
+    #[test]
+    fn stop_switches_to_double_speed_when_prepared() {
+        let mut emulator = init_emulator(&SWITCH_SPEED, 0x80);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF4D), 0x7E);
+
+        // LD A,$01 / LDH (KEY1),A
+        emulator.cpu.execute_instruction();
+        emulator.cpu.execute_instruction();
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF4D), 0x7F);
+
+        // STOP stalls for 2050 machine cycles, counted at the new speed.
+        let cycles = emulator.cpu.execute_instruction();
+        assert_eq!(cycles, (4 + 2050 * 4) / 2);
+        assert!(emulator.cpu.double_speed);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF4D), 0xFE);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF04), 0x00);
+        assert_eq!(emulator.cpu.get_pc(), 0x0106);
+    }
+
+    #[test]
+    fn double_speed_runs_timer_twice_as_fast_as_ppu() {
+        let mut emulator = init_emulator(&SWITCH_SPEED, 0x80);
+        emulator.cpu.set_memory_8bit(0xFF40, 0x80);
+        run_cycles(&mut emulator, 1);
+        while !emulator.cpu.double_speed {
+            emulator.cpu.execute_instruction();
+        }
+
+        let line = emulator.cpu.get_memory_8bit(0xFF44);
+        emulator.cpu.set_memory_8bit(0xFF04, 0x00);
+        let elapsed = run_cycles(&mut emulator, 456 * 4);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF04) as u32, elapsed * 2 / 256);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF44), line + 4);
+    }
+
+    #[test]
+    fn stop_without_speed_switch_waits_for_joypad() {
+        // STOP / INC B / JR -2
+        let mut emulator = init_emulator(&[0x10, 0x00, 0x04, 0x18, 0xFE], 0x80);
+        run_cycles(&mut emulator, 1000);
+        assert!(emulator.cpu.stopped);
+        assert!(!emulator.cpu.double_speed);
+        assert_eq!(emulator.cpu.get_pc(), 0x0102);
+
+        emulator.cpu.set_memory_8bit(0xFF0F, 0x10);
+        run_cycles(&mut emulator, 8);
+        assert!(!emulator.cpu.stopped);
+        assert_eq!(emulator.cpu.get_register_8bit(Register8bit::B), 1);
+    }
+
+    #[test]
+    fn key1_is_unmapped_on_dmg() {
+        let mut emulator = init_emulator(&SWITCH_SPEED, 0x00);
+        run_cycles(&mut emulator, 100);
+        assert_eq!(emulator.cpu.get_memory_8bit(0xFF4D), 0xFF);
+        assert!(!emulator.cpu.double_speed);
+    }
+}